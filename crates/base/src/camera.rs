@@ -0,0 +1,305 @@
+use crate::map_state::Location;
+
+/// 视图相机的状态
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CameraState {
+    pub central: Location,
+    pub zoom_lvl: f64,
+    pub bearing: f64,
+    pub pitch: f64,
+}
+
+/// 缓动函数，输入输出均在[0,1]之间
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Easing {
+    Linear,
+    EaseIn,
+    EaseOut,
+    EaseInOut,
+    /// CSS风格的三次贝塞尔曲线，控制点为(x1,y1)与(x2,y2)
+    CubicBezier(f64, f64, f64, f64),
+}
+
+impl Easing {
+    pub fn apply(&self, t: f64) -> f64 {
+        let t = t.clamp(0.0, 1.0);
+        match *self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t * t,
+            Easing::EaseOut => 1.0 - (1.0 - t).powi(3),
+            Easing::EaseInOut => {
+                if t < 0.5 {
+                    4.0 * t * t * t
+                } else {
+                    1.0 - (-2.0 * t + 2.0).powi(3) / 2.0
+                }
+            }
+            Easing::CubicBezier(x1, y1, x2, y2) => cubic_bezier(x1, y1, x2, y2, t),
+        }
+    }
+}
+
+/// 先用牛顿法由x求曲线参数，再求y
+fn cubic_bezier(x1: f64, y1: f64, x2: f64, y2: f64, x: f64) -> f64 {
+    let bezier = |a: f64, b: f64, s: f64| {
+        3.0 * a * s * (1.0 - s).powi(2) + 3.0 * b * s * s * (1.0 - s) + s * s * s
+    };
+    let derivative = |a: f64, b: f64, s: f64| {
+        3.0 * a * (1.0 - s).powi(2) + 6.0 * (b - a) * s * (1.0 - s) + 3.0 * (1.0 - b) * s * s
+    };
+    let mut s = x;
+    for _ in 0..8 {
+        let dx = derivative(x1, x2, s);
+        if dx.abs() < 1e-6 {
+            break;
+        }
+        s = (s - (bezier(x1, x2, s) - x) / dx).clamp(0.0, 1.0);
+    }
+    bezier(y1, y2, s)
+}
+
+/// van Wijk & Nuij的缩放平移曲线参数，w0、u1为起始缩放级别下的像素，曲线长度s以屏幕宽度计
+#[derive(Clone, Copy, Debug)]
+struct FlyPath {
+    rho: f64,
+    w0: f64,
+    u1: f64,
+    r0: f64,
+    /// 终点与起点宽度之比的对数，仅在不需要平移时使用
+    k: f64,
+    /// 曲线总长度
+    s: f64,
+    pan: bool,
+}
+
+impl FlyPath {
+    /// 曲线陡峭程度，与mapbox保持一致
+    const RHO: f64 = 1.42;
+
+    fn new(w0: f64, w1: f64, u1: f64) -> Self {
+        let rho = Self::RHO;
+        let rho2 = rho * rho;
+        let r = |i: bool| {
+            let (sign, wi) = if i { (-1.0, w1) } else { (1.0, w0) };
+            let b = (w1 * w1 - w0 * w0 + sign * rho2 * rho2 * u1 * u1) / (2.0 * wi * rho2 * u1);
+            ((b * b + 1.0).sqrt() - b).ln()
+        };
+        let r0 = r(false);
+        let s = (r(true) - r0) / rho;
+        if u1.abs() < 1e-9 || !s.is_finite() {
+            let k = (w1 / w0).ln();
+            return Self {
+                rho,
+                w0,
+                u1,
+                r0: 0.0,
+                k,
+                s: k.abs() / rho,
+                pan: false,
+            };
+        }
+        Self {
+            rho,
+            w0,
+            u1,
+            r0,
+            k: 0.0,
+            s,
+            pan: true,
+        }
+    }
+
+    /// 曲线上s处的可视宽度与起点之比
+    fn w(&self, s: f64) -> f64 {
+        if self.pan {
+            self.r0.cosh() / (self.r0 + self.rho * s).cosh()
+        } else {
+            (self.k.signum() * self.rho * s).exp()
+        }
+    }
+
+    /// 曲线上s处已经走过的距离与总距离之比
+    fn u(&self, s: f64) -> f64 {
+        if self.pan {
+            let rho2 = self.rho * self.rho;
+            self.w0 * (self.r0.cosh() * (self.r0 + self.rho * s).tanh() - self.r0.sinh())
+                / rho2
+                / self.u1
+        } else {
+            0.0
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum AnimationPath {
+    Ease,
+    Fly(FlyPath),
+}
+
+/// 相机动画，由[crate::map_view_state::MapViewState::tick]按帧推进
+#[derive(Clone, Copy, Debug)]
+pub struct CameraAnimation {
+    from: CameraState,
+    to: CameraState,
+    /// 起点到终点的x、y位移，圆柱投影下x取最短方向
+    delta: Location,
+    duration: f64,
+    elapsed: f64,
+    easing: Easing,
+    path: AnimationPath,
+}
+
+impl CameraAnimation {
+    /// fly_to默认速度，每秒飞过的屏幕宽度
+    pub const FLY_SPEED: f64 = 1.2;
+
+    pub fn ease(
+        from: CameraState,
+        to: CameraState,
+        wraps_x: bool,
+        duration: f64,
+        easing: Easing,
+    ) -> Self {
+        Self {
+            from,
+            to,
+            delta: Self::delta(from.central, to.central, wraps_x),
+            duration,
+            elapsed: 0.0,
+            easing,
+            path: AnimationPath::Ease,
+        }
+    }
+
+    /// 先拉远再平移再拉近的飞行动画，`view_width`为视图像素宽度，`duration`为None时按路径长度计算
+    pub fn fly(
+        from: CameraState,
+        to: CameraState,
+        wraps_x: bool,
+        view_width: f64,
+        duration: Option<f64>,
+        easing: Easing,
+    ) -> Self {
+        let delta = Self::delta(from.central, to.central, wraps_x);
+        let scale = crate::map_view_state::TILE_SIZE * 2.0_f64.powf(from.zoom_lvl);
+        let w0 = view_width.max(1.0);
+        let w1 = w0 / 2.0_f64.powf(to.zoom_lvl - from.zoom_lvl);
+        let u1 = delta.x.hypot(delta.y) * scale;
+        let path = FlyPath::new(w0, w1, u1);
+        Self {
+            from,
+            to,
+            delta,
+            duration: duration.unwrap_or(path.s / Self::FLY_SPEED),
+            elapsed: 0.0,
+            easing,
+            path: AnimationPath::Fly(path),
+        }
+    }
+
+    fn delta(from: Location, to: Location, wraps_x: bool) -> Location {
+        if wraps_x {
+            from.wrapped_delta(to)
+        } else {
+            to - from
+        }
+    }
+
+    pub fn target(&self) -> CameraState {
+        self.to
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.elapsed >= self.duration
+    }
+
+    /// 推进`dt`秒并返回此时的相机状态
+    pub fn advance(&mut self, dt: f64) -> CameraState {
+        self.elapsed += dt.max(0.0);
+        if self.is_finished() {
+            return self.to;
+        }
+        self.camera_at(self.easing.apply(self.elapsed / self.duration))
+    }
+
+    fn camera_at(&self, k: f64) -> CameraState {
+        let lerp = |a: f64, b: f64| a + (b - a) * k;
+        let bearing_delta = (self.to.bearing - self.from.bearing + 540.0).rem_euclid(360.0) - 180.0;
+        let (progress, zoom_lvl) = match self.path {
+            AnimationPath::Ease => (k, lerp(self.from.zoom_lvl, self.to.zoom_lvl)),
+            AnimationPath::Fly(path) => {
+                let s = k * path.s;
+                (path.u(s), self.from.zoom_lvl - path.w(s).log2())
+            }
+        };
+        CameraState {
+            central: Location::new(
+                self.from.central.x + self.delta.x * progress,
+                self.from.central.y + self.delta.y * progress,
+            ),
+            zoom_lvl,
+            bearing: self.from.bearing + bearing_delta * k,
+            pitch: lerp(self.from.pitch, self.to.pitch),
+        }
+    }
+}
+
+#[test]
+fn test_easing() {
+    for e in [
+        Easing::Linear,
+        Easing::EaseIn,
+        Easing::EaseOut,
+        Easing::EaseInOut,
+        Easing::CubicBezier(0.25, 0.1, 0.25, 1.0),
+    ] {
+        assert_eq!(e.apply(0.0), 0.0);
+        assert!((e.apply(1.0) - 1.0).abs() < 1e-9);
+    }
+    assert!((Easing::CubicBezier(0.0, 0.0, 1.0, 1.0).apply(0.3) - 0.3).abs() < 1e-6);
+    assert!(Easing::EaseIn.apply(0.5) < 0.5 && Easing::EaseOut.apply(0.5) > 0.5);
+}
+
+#[test]
+fn test_fly() {
+    let from = CameraState {
+        central: Location::new(0.2, 0.4),
+        zoom_lvl: 10.0,
+        bearing: 350.0,
+        pitch: 0.0,
+    };
+    let to = CameraState {
+        central: Location::new(0.25, 0.45),
+        zoom_lvl: 12.0,
+        bearing: 10.0,
+        pitch: 30.0,
+    };
+    let mut anim = CameraAnimation::fly(from, to, true, 1000.0, None, Easing::Linear);
+    assert!(anim.duration > 0.5 && anim.duration < 10.0);
+    let half = anim.camera_at(0.5);
+    // 飞行途中先拉远
+    assert!(half.zoom_lvl < from.zoom_lvl);
+    assert!((half.bearing - 360.0).abs() < 1e-9);
+    let end = anim.camera_at(1.0);
+    assert!((end.zoom_lvl - 12.0).abs() < 1e-9);
+    assert!((end.central.x - 0.25).abs() < 1e-9 && (end.central.y - 0.45).abs() < 1e-9);
+    assert_eq!(anim.advance(100.0), to);
+    assert!(anim.is_finished());
+
+    let ease = CameraAnimation::ease(
+        CameraState {
+            central: Location::new(0.9, 0.5),
+            ..from
+        },
+        CameraState {
+            central: Location::new(0.1, 0.5),
+            ..from
+        },
+        true,
+        1.0,
+        Easing::Linear,
+    );
+    // 跨越反子午线走较近的一侧
+    assert!((ease.camera_at(0.5).central.x - 1.0).abs() < 1e-9);
+}
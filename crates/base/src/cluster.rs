@@ -0,0 +1,346 @@
+use std::hash::Hash;
+
+use rustc_hash::FxHashMap;
+
+use crate::{
+    map_state::Location,
+    qtree::{QTree, QTreeKey},
+};
+
+/// 点聚合的一个结果，可能是单个点，也可能是多个点聚合成的簇
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Cluster<Id> {
+    /// 所在的格子
+    pub key: QTreeKey,
+    pub count: usize,
+    /// 簇内所有点的质心
+    pub location: Location,
+    /// 单个点时为该点的id
+    pub id: Option<Id>,
+}
+
+/// 聚合树中的格子。只有一个点或已到达最深层级的格子直接保存点的id，不再有子格子
+#[derive(Clone)]
+struct ClusterCell<Id> {
+    count: usize,
+    /// 簇内所有点的位置之和，用于计算质心
+    sum: Location,
+    ids: Vec<Id>,
+}
+
+impl<Id> ClusterCell<Id> {
+    fn is_bucket(&self) -> bool {
+        !self.ids.is_empty()
+    }
+}
+
+/// 基于四叉树的分层网格点聚合，插入、删除与移动点的开销只与树的深度有关。
+///
+/// 缩放级别为z时，使用第`floor(z)+cell_shift`层的格子聚合，即每个格子约为`256/2^cell_shift`像素
+#[derive(Clone)]
+pub struct PointClusters<Id> {
+    cells: QTree<ClusterCell<Id>>,
+    points: FxHashMap<Id, Location>,
+    /// 聚合格子相对于瓦片层级的偏移
+    pub cell_shift: u8,
+    /// 最深的格子层级，同一最深格子内的点在更大的缩放级别下逐个显示
+    pub leaf_depth: u8,
+}
+
+impl<Id: Copy + Eq + Hash> Default for PointClusters<Id> {
+    fn default() -> Self {
+        Self::new(2, 24)
+    }
+}
+
+impl<Id: Copy + Eq + Hash> PointClusters<Id> {
+    pub fn new(cell_shift: u8, leaf_depth: u8) -> Self {
+        Self {
+            cells: QTree::new(),
+            points: FxHashMap::default(),
+            cell_shift,
+            leaf_depth: leaf_depth.min(28),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.points.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.cells = QTree::new();
+        self.points.clear();
+    }
+
+    pub fn location(&self, id: Id) -> Option<Location> {
+        self.points.get(&id).copied()
+    }
+
+    /// 缩放级别`zoom_lvl`下用于聚合的格子层级
+    pub fn depth_for_zoom(&self, zoom_lvl: f64) -> u8 {
+        (zoom_lvl.floor().max(0.0) as u8)
+            .saturating_add(self.cell_shift)
+            .min(self.leaf_depth)
+    }
+
+    /// 插入点，id已存在时移动该点
+    pub fn insert(&mut self, id: Id, location: Location) {
+        let location = location.wrap_x();
+        if self.points.contains_key(&id) {
+            self.remove(id);
+        }
+        self.points.insert(id, location);
+        let mut key = QTreeKey::root();
+        loop {
+            let Some(cell) = self.cells.get_mut(key).and_then(|n| n.data_mut()) else {
+                self.cells.insert(
+                    key,
+                    ClusterCell {
+                        count: 1,
+                        sum: location,
+                        ids: vec![id],
+                    },
+                );
+                return;
+            };
+            cell.count += 1;
+            cell.sum = cell.sum + location;
+            if key.depth() >= self.leaf_depth {
+                cell.ids.push(id);
+                return;
+            }
+            //只有一个点的格子需要拆分，把原来的点移到子格子中
+            if let Some(old) = cell.ids.pop() {
+                let old_location = self.points[&old];
+                self.cells.insert(
+                    cell_of(old_location, key.depth() + 1),
+                    ClusterCell {
+                        count: 1,
+                        sum: old_location,
+                        ids: vec![old],
+                    },
+                );
+            }
+            key = cell_of(location, key.depth() + 1);
+        }
+    }
+
+    /// 删除点并返回它的位置
+    pub fn remove(&mut self, id: Id) -> Option<Location> {
+        let location = self.points.remove(&id)?;
+        let mut key = QTreeKey::root();
+        loop {
+            let Some(cell) = self.cells.get_mut(key).and_then(|n| n.data_mut()) else {
+                return Some(location);
+            };
+            cell.count -= 1;
+            cell.sum = cell.sum - location;
+            if cell.count == 0 {
+                self.cells.remove(key);
+                return Some(location);
+            }
+            if cell.is_bucket() {
+                cell.ids.retain(|i| *i != id);
+                return Some(location);
+            }
+            if cell.count == 1 {
+                //只剩一个点时合并子格子
+                let remaining = self.points_in(key);
+                let Some(remaining) = remaining.into_iter().find(|i| *i != id) else {
+                    return Some(location);
+                };
+                let remaining_location = self.points[&remaining];
+                self.remove_children(key);
+                if let Some(cell) = self.cells.get_mut(key).and_then(|n| n.data_mut()) {
+                    cell.sum = remaining_location;
+                    cell.ids = vec![remaining];
+                }
+                return Some(location);
+            }
+            key = cell_of(location, key.depth() + 1);
+        }
+    }
+
+    /// `lt`与`rb`围成的范围内在缩放级别`zoom_lvl`下的聚合结果，范围为[0,1]内的[Location]
+    pub fn clusters(&self, lt: Location, rb: Location, zoom_lvl: f64) -> Vec<Cluster<Id>> {
+        let depth = self.depth_for_zoom(zoom_lvl);
+        let mut result = vec![];
+        let mut stack = vec![QTreeKey::root()];
+        while let Some(key) = stack.pop() {
+            let Some(cell) = self.cells.get(key).and_then(|n| n.data()) else {
+                continue;
+            };
+            if !cell_intersects(key, lt, rb) {
+                continue;
+            }
+            if cell.ids.len() == 1 || (cell.is_bucket() && depth >= self.leaf_depth) {
+                result.extend(cell.ids.iter().map(|id| Cluster {
+                    key,
+                    count: 1,
+                    location: self.points[id],
+                    id: Some(*id),
+                }));
+            } else if key.depth() >= depth || cell.is_bucket() {
+                result.push(Self::cluster_of(key, cell));
+            } else {
+                stack.extend(children(key));
+            }
+        }
+        result
+    }
+
+    /// `key`处格子的聚合结果
+    pub fn cluster(&self, key: QTreeKey) -> Option<Cluster<Id>> {
+        let cell = self.cells.get(key).and_then(|n| n.data())?;
+        Some(Self::cluster_of(key, cell))
+    }
+
+    fn cluster_of(key: QTreeKey, cell: &ClusterCell<Id>) -> Cluster<Id> {
+        Cluster {
+            key,
+            count: cell.count,
+            location: Location::new(
+                cell.sum.x / cell.count as f64,
+                cell.sum.y / cell.count as f64,
+            ),
+            id: (cell.ids.len() == 1).then(|| cell.ids[0]),
+        }
+    }
+
+    /// 簇内所有点的id
+    pub fn points_in(&self, key: QTreeKey) -> Vec<Id> {
+        let mut result = vec![];
+        let mut stack = vec![key];
+        while let Some(key) = stack.pop() {
+            if let Some(cell) = self.cells.get(key).and_then(|n| n.data()) {
+                result.extend_from_slice(&cell.ids);
+                if !cell.is_bucket() {
+                    stack.extend(children(key));
+                }
+            }
+        }
+        result
+    }
+
+    /// 簇开始拆分为多个簇或单个点时的缩放级别，用于点击簇后放大
+    pub fn expansion_zoom(&self, key: QTreeKey) -> Option<f64> {
+        let mut key = key;
+        loop {
+            let cell = self.cells.get(key).and_then(|n| n.data())?;
+            let depth = if cell.is_bucket() {
+                if cell.ids.len() > 1 {
+                    self.leaf_depth
+                } else {
+                    key.depth()
+                }
+            } else {
+                let existing: Vec<QTreeKey> = children(key)
+                    .filter(|k| self.cells.get(*k).and_then(|n| n.data()).is_some())
+                    .collect();
+                if existing.len() == 1 {
+                    key = existing[0];
+                    continue;
+                }
+                key.depth() + 1
+            };
+            return Some(depth.saturating_sub(self.cell_shift) as f64);
+        }
+    }
+
+    fn remove_children(&mut self, key: QTreeKey) {
+        children(key).for_each(|k| {
+            if self.cells.get(k).is_some() {
+                self.cells.remove(k);
+            }
+        });
+    }
+}
+
+fn children(key: QTreeKey) -> impl Iterator<Item = QTreeKey> {
+    [
+        key.child_lt(),
+        key.child_rt(),
+        key.child_lb(),
+        key.child_rb(),
+    ]
+    .into_iter()
+    .flatten()
+}
+
+/// `location`在第`depth`层所在的格子
+fn cell_of(location: Location, depth: u8) -> QTreeKey {
+    let n = 2.0_f64.powi(depth as i32);
+    let max = n - 1.0;
+    QTreeKey::new(
+        depth,
+        (location.x * n).floor().clamp(0.0, max) as u32,
+        (location.y * n).floor().clamp(0.0, max) as u32,
+    )
+    .unwrap_or(QTreeKey::root())
+}
+
+fn cell_intersects(key: QTreeKey, lt: Location, rb: Location) -> bool {
+    let n = 2.0_f64.powi(key.depth() as i32);
+    let (x0, y0) = (key.x() as f64 / n, key.y() as f64 / n);
+    x0 <= rb.x && x0 + 1.0 / n >= lt.x && y0 <= rb.y && y0 + 1.0 / n >= lt.y
+}
+
+#[test]
+fn test_clusters() {
+    let mut clusters: PointClusters<u32> = PointClusters::default();
+    //线性同余生成的伪随机点，集中在[0.4,0.6]的范围内
+    let mut seed = 7u64;
+    let mut rand = || {
+        seed = seed
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        0.4 + (seed >> 33) as f64 / (1u64 << 31) as f64 * 0.2
+    };
+    for id in 0..10_000 {
+        let location = Location::new(rand(), rand());
+        clusters.insert(id, location);
+    }
+    assert_eq!(clusters.len(), 10_000);
+    let (lt, rb) = (Location::ZERO, Location::UNIT);
+    let total = |c: &PointClusters<u32>, zoom: f64| {
+        c.clusters(lt, rb, zoom)
+            .iter()
+            .map(|c| c.count)
+            .sum::<usize>()
+    };
+    let low = clusters.clusters(lt, rb, 0.0);
+    assert!(low.len() <= 16);
+    assert_eq!(total(&clusters, 0.0), 10_000);
+    assert_eq!(total(&clusters, 10.0), 10_000);
+    assert_eq!(clusters.clusters(lt, rb, 30.0).len(), 10_000);
+
+    let big = low.iter().max_by_key(|c| c.count).unwrap();
+    assert_eq!(clusters.points_in(big.key).len(), big.count);
+    let zoom = clusters.expansion_zoom(big.key).unwrap();
+    let in_cell = |c: &Cluster<u32>| c.key.depth() > big.key.depth();
+    assert!(
+        clusters
+            .clusters(lt, rb, zoom)
+            .iter()
+            .filter(|c| in_cell(c))
+            .count()
+            > 1
+    );
+
+    //移动与删除
+    clusters.insert(0, Location::new(0.1, 0.1));
+    assert_eq!(clusters.location(0), Some(Location::new(0.1, 0.1)));
+    assert_eq!(total(&clusters, 3.0), 10_000);
+    for id in 1..10_000 {
+        clusters.remove(id);
+    }
+    let single = clusters.clusters(lt, rb, 0.0);
+    assert_eq!(single.len(), 1);
+    assert_eq!(single[0].id, Some(0));
+    assert!(clusters.remove(0).is_some());
+    assert!(clusters.is_empty() && clusters.clusters(lt, rb, 0.0).is_empty());
+}
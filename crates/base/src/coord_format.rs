@@ -0,0 +1,564 @@
+use std::{error::Error, fmt::Display, str::FromStr};
+
+use crate::{geodesy::normalize_lng, latlng::LatLng, utm::Utm};
+
+/// 经纬度的文本格式
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CoordFormat {
+    /// 十进制度，如`39.908823, 116.397470`，参数为小数位数
+    Decimal(usize),
+    /// 度分秒，如`39°54'31.76"N 116°23'50.89"E`，参数为秒的小数位数
+    Dms(usize),
+    /// 度分，如`39°54.5294'N 116°23.8482'E`，参数为分的小数位数
+    Ddm(usize),
+    /// UTM，如`50S 448251 4417894`
+    Utm,
+    /// MGRS，参数为东向/北向各自的位数（1~5）
+    Mgrs(u8),
+    /// Geohash，参数为字符数
+    Geohash(usize),
+    /// Open Location Code（Plus Codes），参数为不含`+`的字符数
+    PlusCode(usize),
+}
+
+impl CoordFormat {
+    /// 各种格式的常用精度，便于在界面上选择
+    pub const PRESETS: [CoordFormat; 7] = [
+        CoordFormat::Decimal(6),
+        CoordFormat::Dms(2),
+        CoordFormat::Ddm(4),
+        CoordFormat::Utm,
+        CoordFormat::Mgrs(5),
+        CoordFormat::Geohash(9),
+        CoordFormat::PlusCode(10),
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            CoordFormat::Decimal(_) => "Decimal",
+            CoordFormat::Dms(_) => "DMS",
+            CoordFormat::Ddm(_) => "DDM",
+            CoordFormat::Utm => "UTM",
+            CoordFormat::Mgrs(_) => "MGRS",
+            CoordFormat::Geohash(_) => "Geohash",
+            CoordFormat::PlusCode(_) => "Plus Code",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum CoordParseError {
+    Empty,
+    /// 无法识别的文本
+    Invalid(String),
+    /// 经纬度或投影坐标超出有效范围
+    OutOfRange,
+}
+
+impl Display for CoordParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CoordParseError::Empty => write!(f, "empty coordinate"),
+            CoordParseError::Invalid(s) => write!(f, "invalid coordinate: {}", s),
+            CoordParseError::OutOfRange => write!(f, "coordinate out of range"),
+        }
+    }
+}
+
+impl Error for CoordParseError {}
+
+impl LatLng {
+    /// 按指定格式输出，UTM/MGRS不支持的极地区域退化为十进制度
+    pub fn format(&self, format: CoordFormat) -> String {
+        match format {
+            CoordFormat::Decimal(p) => format!("{:.p$}, {:.p$}", self.lat, self.lng, p = p),
+            CoordFormat::Dms(p) => format!(
+                "{} {}",
+                format_dms(self.lat, 'N', 'S', p),
+                format_dms(self.lng, 'E', 'W', p)
+            ),
+            CoordFormat::Ddm(p) => format!(
+                "{} {}",
+                format_ddm(self.lat, 'N', 'S', p),
+                format_ddm(self.lng, 'E', 'W', p)
+            ),
+            CoordFormat::Utm => Utm::from_lat_lng(*self)
+                .map(|u| u.to_string())
+                .unwrap_or_else(|_| self.format(CoordFormat::Decimal(6))),
+            CoordFormat::Mgrs(digits) => Utm::from_lat_lng(*self)
+                .map(|u| u.to_mgrs(digits))
+                .unwrap_or_else(|_| self.format(CoordFormat::Decimal(6))),
+            CoordFormat::Geohash(len) => geohash_encode(*self, len),
+            CoordFormat::PlusCode(len) => plus_code_encode(*self, len),
+        }
+    }
+}
+
+fn format_dms(value: f64, pos: char, neg: char, precision: usize) -> String {
+    let hemi = if value < 0.0 { neg } else { pos };
+    let scale = 10_u64.pow(precision as u32);
+    let total = (value.abs() * 3600.0 * scale as f64).round() as u64;
+    let deg = total / (3600 * scale);
+    let min = total / (60 * scale) % 60;
+    let sec = (total % (60 * scale)) as f64 / scale as f64;
+    let width = if precision > 0 { precision + 3 } else { 2 };
+    format!(
+        "{}°{:02}'{:0w$.p$}\"{}",
+        deg,
+        min,
+        sec,
+        hemi,
+        w = width,
+        p = precision
+    )
+}
+
+fn format_ddm(value: f64, pos: char, neg: char, precision: usize) -> String {
+    let hemi = if value < 0.0 { neg } else { pos };
+    let scale = 10_u64.pow(precision as u32);
+    let total = (value.abs() * 60.0 * scale as f64).round() as u64;
+    let deg = total / (60 * scale);
+    let min = (total % (60 * scale)) as f64 / scale as f64;
+    let width = if precision > 0 { precision + 3 } else { 2 };
+    format!("{}°{:0w$.p$}'{}", deg, min, hemi, w = width, p = precision)
+}
+
+const GEOHASH_BASE32: &[u8] = b"0123456789bcdefghjkmnpqrstuvwxyz";
+
+pub fn geohash_encode(lat_lng: LatLng, len: usize) -> String {
+    let mut lat_range = (-90.0, 90.0);
+    let mut lng_range = (-180.0, 180.0);
+    let lng = normalize_lng(lat_lng.lng);
+    let mut even = true;
+    let mut hash = String::with_capacity(len);
+    while hash.len() < len {
+        let mut idx = 0;
+        for _ in 0..5 {
+            let (range, value) = if even {
+                (&mut lng_range, lng)
+            } else {
+                (&mut lat_range, lat_lng.lat)
+            };
+            let mid = (range.0 + range.1) / 2.0;
+            idx <<= 1;
+            if value >= mid {
+                idx |= 1;
+                range.0 = mid;
+            } else {
+                range.1 = mid;
+            }
+            even = !even;
+        }
+        hash.push(GEOHASH_BASE32[idx] as char);
+    }
+    hash
+}
+
+/// 返回Geohash格网的中心点
+pub fn geohash_decode(hash: &str) -> Result<LatLng, CoordParseError> {
+    let hash = hash.trim().to_ascii_lowercase();
+    if hash.is_empty() {
+        return Err(CoordParseError::Empty);
+    }
+    let mut lat_range = (-90.0, 90.0);
+    let mut lng_range = (-180.0, 180.0);
+    let mut even = true;
+    for c in hash.bytes() {
+        let idx = GEOHASH_BASE32
+            .iter()
+            .position(|b| *b == c)
+            .ok_or_else(|| CoordParseError::Invalid(hash.clone()))?;
+        for bit in (0..5).rev() {
+            let range = if even { &mut lng_range } else { &mut lat_range };
+            let mid = (range.0 + range.1) / 2.0;
+            if idx >> bit & 1 == 1 {
+                range.0 = mid;
+            } else {
+                range.1 = mid;
+            }
+            even = !even;
+        }
+    }
+    Ok(LatLng {
+        lat: (lat_range.0 + lat_range.1) / 2.0,
+        lng: (lng_range.0 + lng_range.1) / 2.0,
+    })
+}
+
+const PLUS_CODE_ALPHABET: &[u8] = b"23456789CFGHJMPQRVWX";
+/// 完整15位编码下纬度/经度的最小单位
+const PLUS_CODE_LAT_PRECISION: f64 = 25_000_000.0;
+const PLUS_CODE_LNG_PRECISION: f64 = 8_192_000.0;
+
+/// 生成Plus Code，`len`为不含`+`的位数（2~15，10位以内只能为偶数）
+pub fn plus_code_encode(lat_lng: LatLng, len: usize) -> String {
+    let mut len = len.clamp(2, 15);
+    if len < 10 && len % 2 == 1 {
+        len += 1;
+    }
+    let lat = lat_lng.lat.clamp(-90.0, 90.0);
+    let lng = normalize_lng(lat_lng.lng);
+    let lat_max = (180.0 * PLUS_CODE_LAT_PRECISION) as i64;
+    let mut lat_val = (((lat + 90.0) * PLUS_CODE_LAT_PRECISION).round() as i64).min(lat_max - 1);
+    let mut lng_val = ((lng + 180.0) * PLUS_CODE_LNG_PRECISION).round() as i64
+        % (360.0 * PLUS_CODE_LNG_PRECISION) as i64;
+
+    // 从最低位开始逆序生成
+    let mut rev = Vec::with_capacity(15);
+    if len > 10 {
+        for _ in 0..5 {
+            rev.push(PLUS_CODE_ALPHABET[(lat_val % 5 * 4 + lng_val % 4) as usize]);
+            lat_val /= 5;
+            lng_val /= 4;
+        }
+    } else {
+        lat_val /= 3125;
+        lng_val /= 1024;
+    }
+    for _ in 0..5 {
+        rev.push(PLUS_CODE_ALPHABET[(lng_val % 20) as usize]);
+        rev.push(PLUS_CODE_ALPHABET[(lat_val % 20) as usize]);
+        lat_val /= 20;
+        lng_val /= 20;
+    }
+    let mut code: String = rev.iter().rev().take(len).map(|c| *c as char).collect();
+    while code.len() < 8 {
+        code.push('0');
+    }
+    code.insert(8, '+');
+    code
+}
+
+/// 返回Plus Code格网的中心点，不支持省略前缀的短码
+pub fn plus_code_decode(code: &str) -> Result<LatLng, CoordParseError> {
+    let upper = code.trim().to_ascii_uppercase();
+    let invalid = || CoordParseError::Invalid(upper.clone());
+    if upper.find('+') != Some(8) || upper.matches('+').count() != 1 {
+        return Err(invalid());
+    }
+    let digits: Vec<usize> = upper
+        .bytes()
+        .filter(|c| *c != b'+')
+        .take_while(|c| *c != b'0')
+        .map(|c| PLUS_CODE_ALPHABET.iter().position(|a| *a == c))
+        .collect::<Option<_>>()
+        .ok_or_else(invalid)?;
+    if digits.len() < 2 || (digits.len() < 10 && digits.len() % 2 == 1) {
+        return Err(invalid());
+    }
+    let mut lat = 0.0;
+    let mut lng = 0.0;
+    let mut lat_res = 400.0;
+    let mut lng_res = 400.0;
+    digits.iter().take(10).enumerate().for_each(|(i, d)| {
+        if i % 2 == 0 {
+            lat_res /= 20.0;
+            lat += *d as f64 * lat_res;
+        } else {
+            lng_res /= 20.0;
+            lng += *d as f64 * lng_res;
+        }
+    });
+    digits.iter().skip(10).for_each(|d| {
+        lat_res /= 5.0;
+        lng_res /= 4.0;
+        lat += (*d / 4) as f64 * lat_res;
+        lng += (*d % 4) as f64 * lng_res;
+    });
+    Ok(LatLng {
+        lat: lat + lat_res / 2.0 - 90.0,
+        lng: lng + lng_res / 2.0 - 180.0,
+    })
+}
+
+enum Token {
+    Num(f64),
+    Hemi(char),
+    LatKey,
+    LngKey,
+    Sep,
+}
+
+fn tokenize(s: &str) -> Vec<Token> {
+    let chars: Vec<char> = s.chars().collect();
+    let mut tokens = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let starts_number = c.is_ascii_digit()
+            || ((c == '-' || c == '+' || c == '.')
+                && chars
+                    .get(i + 1)
+                    .is_some_and(|n| n.is_ascii_digit() || *n == '.'));
+        if starts_number {
+            let start = i;
+            i += 1;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            let text: String = chars[start..i].iter().collect();
+            if let Ok(v) = text.parse() {
+                tokens.push(Token::Num(v));
+            }
+        } else if c.is_alphabetic() {
+            let start = i;
+            while i < chars.len() && chars[i].is_alphabetic() {
+                i += 1;
+            }
+            let word: String = chars[start..i].iter().collect::<String>().to_uppercase();
+            match word.as_str() {
+                "N" | "S" | "E" | "W" => tokens.push(Token::Hemi(word.chars().next().unwrap())),
+                "LAT" | "LATITUDE" => tokens.push(Token::LatKey),
+                "LNG" | "LON" | "LONG" | "LONGITUDE" => tokens.push(Token::LngKey),
+                _ => {}
+            }
+        } else {
+            if c == ',' || c == ';' || c == '/' || c == '，' {
+                tokens.push(Token::Sep);
+            }
+            i += 1;
+        }
+    }
+    tokens
+}
+
+/// 一个坐标分量：数值（度、分、秒）与可选的方向标记
+struct Component {
+    nums: Vec<f64>,
+    /// 'N'/'S'/'E'/'W'，或由lat/lng关键字推断出的'N'/'E'
+    axis: Option<char>,
+}
+
+impl Component {
+    fn value(&self) -> Option<f64> {
+        if self.nums.is_empty() || self.nums.len() > 3 {
+            return None;
+        }
+        if self.nums[1..].iter().any(|v| !(0.0..60.0).contains(v)) {
+            return None;
+        }
+        let deg = self.nums[0];
+        let abs = deg.abs()
+            + self.nums.get(1).unwrap_or(&0.0) / 60.0
+            + self.nums.get(2).unwrap_or(&0.0) / 3600.0;
+        let negative = deg.is_sign_negative() || matches!(self.axis, Some('S') | Some('W'));
+        Some(if negative { -abs } else { abs })
+    }
+
+    fn is_lat(&self) -> Option<bool> {
+        self.axis.map(|a| a == 'N' || a == 'S')
+    }
+}
+
+/// 解析十进制度、度分秒、度分等形式的经纬度
+fn parse_degrees(s: &str) -> Result<LatLng, CoordParseError> {
+    let invalid = || CoordParseError::Invalid(s.to_string());
+    let tokens = tokenize(s);
+    let mut comps: Vec<Component> = vec![];
+    let has_marker = tokens
+        .iter()
+        .any(|t| matches!(t, Token::Hemi(_) | Token::LatKey | Token::LngKey));
+    if has_marker {
+        // 方向标记在数字前（N39 E116）还是在数字后（39N 116E）
+        let prefix = !matches!(
+            tokens.iter().find(|t| !matches!(t, Token::Sep)),
+            Some(Token::Num(_))
+        );
+        let mut curr = Component {
+            nums: vec![],
+            axis: None,
+        };
+        for t in tokens {
+            let axis = match t {
+                Token::Num(v) => {
+                    curr.nums.push(v);
+                    continue;
+                }
+                Token::Sep => continue,
+                Token::Hemi(h) => h,
+                Token::LatKey => 'N',
+                Token::LngKey => 'E',
+            };
+            if prefix {
+                if !curr.nums.is_empty() || curr.axis.is_some() {
+                    comps.push(curr);
+                }
+                curr = Component {
+                    nums: vec![],
+                    axis: Some(axis),
+                };
+            } else {
+                curr.axis = Some(axis);
+                comps.push(curr);
+                curr = Component {
+                    nums: vec![],
+                    axis: None,
+                };
+            }
+        }
+        if !curr.nums.is_empty() {
+            comps.push(curr);
+        }
+    } else {
+        let mut groups: Vec<Vec<f64>> = vec![vec![]];
+        tokens.iter().for_each(|t| match t {
+            Token::Num(v) => groups.last_mut().unwrap().push(*v),
+            Token::Sep if !groups.last().unwrap().is_empty() => groups.push(vec![]),
+            _ => {}
+        });
+        groups.retain(|g| !g.is_empty());
+        if groups.len() == 1 && groups[0].len().is_multiple_of(2) {
+            // 没有分隔符时按数量平分
+            let nums = groups.pop().unwrap();
+            let half = nums.len() / 2;
+            groups = vec![nums[..half].to_vec(), nums[half..].to_vec()];
+        }
+        comps = groups
+            .into_iter()
+            .map(|nums| Component { nums, axis: None })
+            .collect();
+    }
+    if comps.len() != 2 {
+        return Err(invalid());
+    }
+    let (lat, lng) = match (comps[0].is_lat(), comps[1].is_lat()) {
+        (Some(false), _) | (_, Some(true)) => (&comps[1], &comps[0]),
+        _ => (&comps[0], &comps[1]),
+    };
+    let lat_lng = LatLng {
+        lat: lat.value().ok_or_else(invalid)?,
+        lng: lng.value().ok_or_else(invalid)?,
+    };
+    if !(-90.0..=90.0).contains(&lat_lng.lat) || !(-180.0..=180.0).contains(&lat_lng.lng) {
+        return Err(CoordParseError::OutOfRange);
+    }
+    Ok(lat_lng)
+}
+
+/// 尽量宽松地解析用户粘贴的坐标，依次尝试Plus Code、MGRS、UTM、度数形式和Geohash
+impl FromStr for LatLng {
+    type Err = CoordParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Err(CoordParseError::Empty);
+        }
+        if let Ok(lat_lng) = plus_code_decode(s) {
+            return Ok(lat_lng);
+        }
+        if let Ok(utm) = Utm::from_mgrs(s).or_else(|_| Utm::from_utm_str(s)) {
+            return Ok(utm.to_lat_lng());
+        }
+        let degrees = parse_degrees(s);
+        if degrees.is_ok() || s.chars().any(|c| !c.is_ascii_alphanumeric()) {
+            return degrees;
+        }
+        geohash_decode(s).map_err(|_| degrees.unwrap_err())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: LatLng, lat: f64, lng: f64, eps: f64) {
+        assert!(
+            (a.lat - lat).abs() < eps && (a.lng - lng).abs() < eps,
+            "{} != ({}, {})",
+            a,
+            lat,
+            lng
+        );
+    }
+
+    #[test]
+    fn test_format() {
+        let p = LatLng {
+            lat: 39.908823,
+            lng: -116.39747,
+        };
+        assert_eq!(p.format(CoordFormat::Decimal(3)), "39.909, -116.397");
+        assert_eq!(
+            p.format(CoordFormat::Dms(1)),
+            "39°54'31.8\"N 116°23'50.9\"W"
+        );
+        assert_eq!(p.format(CoordFormat::Ddm(2)), "39°54.53'N 116°23.85'W");
+        let edge = LatLng {
+            lat: 0.9999999,
+            lng: 0.0,
+        };
+        assert_eq!(edge.format(CoordFormat::Dms(0)), "1°00'00\"N 0°00'00\"E");
+    }
+
+    #[test]
+    fn test_geohash_plus_code() {
+        let p = LatLng {
+            lat: 57.64911,
+            lng: 10.40744,
+        };
+        assert_eq!(geohash_encode(p, 11), "u4pruydqqvj");
+        assert_close(
+            geohash_decode("u4pruydqqvj").unwrap(),
+            57.64911,
+            10.40744,
+            1e-5,
+        );
+
+        let p = LatLng {
+            lat: 20.375,
+            lng: 2.775,
+        };
+        assert_eq!(plus_code_encode(p, 6), "7FG49Q00+");
+        assert_close(plus_code_decode("7FG49Q00+").unwrap(), 20.375, 2.775, 1e-9);
+        let p = LatLng {
+            lat: 47.365590,
+            lng: 8.524997,
+        };
+        let code = plus_code_encode(p, 11);
+        assert_eq!(code.len(), 12);
+        assert_close(plus_code_decode(&code).unwrap(), p.lat, p.lng, 2e-5);
+    }
+
+    #[test]
+    fn test_parse() {
+        let cases = [
+            "39.9088, 116.3975",
+            "39.9088 116.3975",
+            "{lat:39.9088,lng:116.3975}",
+            "lng=116.3975 lat=39.9088",
+            "39.9088N, 116.3975E",
+            "N 39.9088 E 116.3975",
+            "116.3975E 39.9088N",
+            "39°54'31.68\"N 116°23'51.0\"E",
+            "39 54 31.68 N 116 23 51 E",
+            "39°54.528′ N, 116°23.85′ E",
+        ];
+        cases.iter().for_each(|c| {
+            let p: LatLng = c.parse().unwrap_or_else(|e| panic!("{}: {}", c, e));
+            assert_close(p, 39.9088, 116.3975, 1e-6);
+        });
+
+        let p: LatLng = "33.8568 S, 151.2153 W".parse().unwrap();
+        assert_close(p, -33.8568, -151.2153, 1e-9);
+        let p: LatLng = "-33.8568,-151.2153".parse().unwrap();
+        assert_close(p, -33.8568, -151.2153, 1e-9);
+
+        let p: LatLng = "31U DQ 48251 11932".parse().unwrap();
+        assert_close(p, 48.8582, 2.2945, 1e-4);
+        let p: LatLng = "31U 448252 5411933".parse().unwrap();
+        assert_close(p, 48.8582, 2.2945, 1e-4);
+        let p: LatLng = "ezs42".parse().unwrap();
+        assert_close(p, 42.6, -5.6, 0.03);
+        let p: LatLng = "7fg49q00+".parse().unwrap();
+        assert_close(p, 20.375, 2.775, 1e-9);
+
+        assert_eq!("".parse::<LatLng>().err(), Some(CoordParseError::Empty));
+        assert_eq!(
+            "91, 10".parse::<LatLng>().err(),
+            Some(CoordParseError::OutOfRange)
+        );
+        assert!("hello world".parse::<LatLng>().is_err());
+        assert!("1 2 3".parse::<LatLng>().is_err());
+    }
+}
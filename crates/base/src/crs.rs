@@ -0,0 +1,339 @@
+use std::f64::consts::{FRAC_PI_2, FRAC_PI_4, PI};
+
+use crate::{
+    latlng::{LatLng, WebMercator, WCS},
+    map_state::Location,
+};
+
+/// 等经纬度投影（Plate Carrée，EPSG:4326）。
+///
+/// 经度360°映射到x的[0,1]，纬度180°映射到y的[0,0.5]，保证每个瓦片在经纬度上都是正方形。
+/// 因此深度为d的瓦片对应EPSG:4326瓦片网格（z0为两张瓦片）中的z=d-1。
+pub struct Equirectangular;
+
+impl WCS for Equirectangular {
+    fn to_lat_lng(&self, location: Location) -> LatLng {
+        LatLng {
+            lat: 90.0 - location.y * 360.0,
+            lng: location.x * 360.0 - 180.0,
+        }
+    }
+
+    fn to_location(&self, lat_lng: LatLng) -> Location {
+        Location {
+            x: (lat_lng.lng + 180.0) / 360.0,
+            y: (90.0 - lat_lng.lat) / 360.0,
+        }
+    }
+
+    fn wraps_x(&self) -> bool {
+        true
+    }
+}
+
+/// 使用GCJ-02（火星坐标系）偏移的Web墨卡托投影，高德、腾讯、必应中国等底图使用。
+///
+/// [LatLng]始终是WGS-84坐标，偏移只在投影时施加。
+pub struct Gcj02Mercator;
+
+impl WCS for Gcj02Mercator {
+    fn to_lat_lng(&self, location: Location) -> LatLng {
+        gcj02_to_wgs84(WebMercator.to_lat_lng(location))
+    }
+
+    fn to_location(&self, lat_lng: LatLng) -> Location {
+        WebMercator.to_location(wgs84_to_gcj02(lat_lng))
+    }
+
+    fn lat_range(&self) -> (f64, f64) {
+        WebMercator.lat_range()
+    }
+
+    fn wraps_x(&self) -> bool {
+        true
+    }
+
+    fn is_web_mercator(&self) -> bool {
+        true
+    }
+}
+
+/// 使用BD-09偏移的Web墨卡托投影，百度系底图使用。
+pub struct Bd09Mercator;
+
+impl WCS for Bd09Mercator {
+    fn to_lat_lng(&self, location: Location) -> LatLng {
+        gcj02_to_wgs84(bd09_to_gcj02(WebMercator.to_lat_lng(location)))
+    }
+
+    fn to_location(&self, lat_lng: LatLng) -> Location {
+        WebMercator.to_location(gcj02_to_bd09(wgs84_to_gcj02(lat_lng)))
+    }
+
+    fn lat_range(&self) -> (f64, f64) {
+        WebMercator.lat_range()
+    }
+
+    fn wraps_x(&self) -> bool {
+        true
+    }
+
+    fn is_web_mercator(&self) -> bool {
+        true
+    }
+}
+
+/// 球面极射赤面投影，极点位于[Location]的中心(0.5,0.5)。
+///
+/// 北极投影中0°经线指向下方，南极投影中0°经线指向上方；
+/// `boundary_lat`所在的纬线圈内切于单位正方形。
+pub struct PolarStereographic {
+    pub north: bool,
+    pub boundary_lat: f64,
+}
+
+impl PolarStereographic {
+    pub fn north(boundary_lat: f64) -> Self {
+        Self {
+            north: true,
+            boundary_lat,
+        }
+    }
+
+    pub fn south(boundary_lat: f64) -> Self {
+        Self {
+            north: false,
+            boundary_lat,
+        }
+    }
+
+    /// 到极点的投影距离
+    fn rho(&self, lat: f64) -> f64 {
+        let lat_rad = lat.to_radians();
+        if self.north {
+            2.0 * (FRAC_PI_4 - lat_rad / 2.0).tan()
+        } else {
+            2.0 * (FRAC_PI_4 + lat_rad / 2.0).tan()
+        }
+    }
+}
+
+impl WCS for PolarStereographic {
+    fn to_lat_lng(&self, location: Location) -> LatLng {
+        let r = self.rho(self.boundary_lat);
+        let px = (location.x - 0.5) * 2.0 * r;
+        let py = (location.y - 0.5) * 2.0 * r;
+        let rho = px.hypot(py);
+        let (lat_rad, lng_rad) = if self.north {
+            (FRAC_PI_2 - 2.0 * (rho / 2.0).atan(), px.atan2(py))
+        } else {
+            (2.0 * (rho / 2.0).atan() - FRAC_PI_2, px.atan2(-py))
+        };
+        LatLng {
+            lat: lat_rad.to_degrees(),
+            lng: lng_rad.to_degrees(),
+        }
+    }
+
+    fn to_location(&self, lat_lng: LatLng) -> Location {
+        let r = self.rho(self.boundary_lat);
+        let rho = self.rho(lat_lng.lat);
+        let lng_rad = lat_lng.lng.to_radians();
+        let px = rho * lng_rad.sin();
+        let py = if self.north {
+            rho * lng_rad.cos()
+        } else {
+            -rho * lng_rad.cos()
+        };
+        Location {
+            x: 0.5 + px / (2.0 * r),
+            y: 0.5 + py / (2.0 * r),
+        }
+    }
+
+    /// 单位正方形内切圆以内的纬度
+    fn lat_range(&self) -> (f64, f64) {
+        if self.north {
+            (self.boundary_lat, 90.0)
+        } else {
+            (-90.0, self.boundary_lat)
+        }
+    }
+}
+
+// GCJ-02使用的克拉索夫斯基椭球参数
+const KRASOVSKY_A: f64 = 6378245.0;
+const KRASOVSKY_EE: f64 = 0.006_693_421_622_965_943;
+const BD09_X_PI: f64 = PI * 3000.0 / 180.0;
+
+/// 中国境外不做偏移
+pub fn out_of_china(lat_lng: LatLng) -> bool {
+    !(72.004..=137.8347).contains(&lat_lng.lng) || !(0.8293..=55.8271).contains(&lat_lng.lat)
+}
+
+fn gcj02_transform_lat(x: f64, y: f64) -> f64 {
+    let mut ret = -100.0 + 2.0 * x + 3.0 * y + 0.2 * y * y + 0.1 * x * y + 0.2 * x.abs().sqrt();
+    ret += (20.0 * (6.0 * x * PI).sin() + 20.0 * (2.0 * x * PI).sin()) * 2.0 / 3.0;
+    ret += (20.0 * (y * PI).sin() + 40.0 * (y / 3.0 * PI).sin()) * 2.0 / 3.0;
+    ret += (160.0 * (y / 12.0 * PI).sin() + 320.0 * (y * PI / 30.0).sin()) * 2.0 / 3.0;
+    ret
+}
+
+fn gcj02_transform_lng(x: f64, y: f64) -> f64 {
+    let mut ret = 300.0 + x + 2.0 * y + 0.1 * x * x + 0.1 * x * y + 0.1 * x.abs().sqrt();
+    ret += (20.0 * (6.0 * x * PI).sin() + 20.0 * (2.0 * x * PI).sin()) * 2.0 / 3.0;
+    ret += (20.0 * (x * PI).sin() + 40.0 * (x / 3.0 * PI).sin()) * 2.0 / 3.0;
+    ret += (150.0 * (x / 12.0 * PI).sin() + 300.0 * (x / 30.0 * PI).sin()) * 2.0 / 3.0;
+    ret
+}
+
+pub fn wgs84_to_gcj02(lat_lng: LatLng) -> LatLng {
+    if out_of_china(lat_lng) {
+        return lat_lng;
+    }
+    let d_lat = gcj02_transform_lat(lat_lng.lng - 105.0, lat_lng.lat - 35.0);
+    let d_lng = gcj02_transform_lng(lat_lng.lng - 105.0, lat_lng.lat - 35.0);
+    let rad_lat = lat_lng.lat.to_radians();
+    let magic = 1.0 - KRASOVSKY_EE * rad_lat.sin() * rad_lat.sin();
+    let sqrt_magic = magic.sqrt();
+    LatLng {
+        lat: lat_lng.lat
+            + d_lat * 180.0 / ((KRASOVSKY_A * (1.0 - KRASOVSKY_EE)) / (magic * sqrt_magic) * PI),
+        lng: lat_lng.lng + d_lng * 180.0 / (KRASOVSKY_A / sqrt_magic * rad_lat.cos() * PI),
+    }
+}
+
+/// GCJ-02没有解析逆变换，这里用迭代逼近，精度约1e-9度
+pub fn gcj02_to_wgs84(lat_lng: LatLng) -> LatLng {
+    if out_of_china(lat_lng) {
+        return lat_lng;
+    }
+    let mut wgs = lat_lng;
+    for _ in 0..30 {
+        let gcj = wgs84_to_gcj02(wgs);
+        let d_lat = gcj.lat - lat_lng.lat;
+        let d_lng = gcj.lng - lat_lng.lng;
+        wgs.lat -= d_lat;
+        wgs.lng -= d_lng;
+        if d_lat.abs() < 1e-9 && d_lng.abs() < 1e-9 {
+            break;
+        }
+    }
+    wgs
+}
+
+pub fn gcj02_to_bd09(lat_lng: LatLng) -> LatLng {
+    let x = lat_lng.lng;
+    let y = lat_lng.lat;
+    let z = x.hypot(y) + 0.00002 * (y * BD09_X_PI).sin();
+    let theta = y.atan2(x) + 0.000003 * (x * BD09_X_PI).cos();
+    LatLng {
+        lat: z * theta.sin() + 0.006,
+        lng: z * theta.cos() + 0.0065,
+    }
+}
+
+pub fn bd09_to_gcj02(lat_lng: LatLng) -> LatLng {
+    let x = lat_lng.lng - 0.0065;
+    let y = lat_lng.lat - 0.006;
+    let z = x.hypot(y) - 0.00002 * (y * BD09_X_PI).sin();
+    let theta = y.atan2(x) - 0.000003 * (x * BD09_X_PI).cos();
+    LatLng {
+        lat: z * theta.sin(),
+        lng: z * theta.cos(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: LatLng, b: LatLng, eps: f64) {
+        assert!(
+            (a.lat - b.lat).abs() < eps && (a.lng - b.lng).abs() < eps,
+            "{} != {}",
+            a,
+            b
+        );
+    }
+
+    #[test]
+    fn test_equirectangular() {
+        let nw = Equirectangular.to_lat_lng(Location::ZERO);
+        assert_eq!(nw.lat, 90.0);
+        assert_eq!(nw.lng, -180.0);
+        let sw = Equirectangular.to_location(LatLng {
+            lat: -90.0,
+            lng: 180.0,
+        });
+        assert_eq!(sw.x, 1.0);
+        assert_eq!(sw.y, 0.5);
+    }
+
+    #[test]
+    fn test_gcj02_bd09() {
+        let wgs = LatLng {
+            lat: 39.915,
+            lng: 116.404,
+        };
+        let gcj = wgs84_to_gcj02(wgs);
+        assert_close(
+            gcj,
+            LatLng {
+                lat: 39.91640428150164,
+                lng: 116.41024449916938,
+            },
+            1e-12,
+        );
+        assert_close(gcj02_to_wgs84(gcj), wgs, 1e-8);
+        assert_close(
+            gcj02_to_bd09(wgs),
+            LatLng {
+                lat: 39.92133699351022,
+                lng: 116.41036949371029,
+            },
+            1e-12,
+        );
+        assert_close(bd09_to_gcj02(gcj02_to_bd09(gcj)), gcj, 1e-5);
+        assert_close(
+            Bd09Mercator.to_lat_lng(Bd09Mercator.to_location(wgs)),
+            wgs,
+            1e-5,
+        );
+
+        // 中国境外不偏移
+        let paris = LatLng {
+            lat: 48.8566,
+            lng: 2.3522,
+        };
+        assert_close(
+            Gcj02Mercator.to_lat_lng(Gcj02Mercator.to_location(paris)),
+            paris,
+            1e-9,
+        );
+    }
+
+    #[test]
+    fn test_polar_stereographic() {
+        let north = PolarStereographic::north(0.0);
+        let pole = north.to_location(LatLng {
+            lat: 90.0,
+            lng: 0.0,
+        });
+        assert!((pole.x - 0.5).abs() < 1e-12 && (pole.y - 0.5).abs() < 1e-12);
+        let equator = north.to_location(LatLng { lat: 0.0, lng: 0.0 });
+        assert!((equator.x - 0.5).abs() < 1e-12 && (equator.y - 1.0).abs() < 1e-12);
+
+        let south = PolarStereographic::south(-60.0);
+        let p = LatLng {
+            lat: -75.0,
+            lng: 120.0,
+        };
+        assert_close(south.to_lat_lng(south.to_location(p)), p, 1e-9);
+        let p = LatLng {
+            lat: 70.0,
+            lng: -45.0,
+        };
+        assert_close(north.to_lat_lng(north.to_location(p)), p, 1e-9);
+    }
+}
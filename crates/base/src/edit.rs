@@ -0,0 +1,323 @@
+use crate::{
+    latlng::{LatLng, WCS},
+    map_state::{MapItem, MapItemData},
+};
+
+/// 绘制圆时外轮廓的顶点数
+pub const CIRCLE_SEGMENTS: usize = 64;
+
+/// 可编辑的图形，顶点均为经纬度
+#[derive(Clone, Debug, PartialEq)]
+pub enum EditShape {
+    Point(LatLng),
+    Line(Vec<LatLng>),
+    /// 不需要重复首个顶点
+    Polygon(Vec<LatLng>),
+    /// 矩形的两个对角
+    Rect(LatLng, LatLng),
+    /// 圆心与半径，半径单位为米
+    Circle {
+        center: LatLng,
+        radius: f64,
+    },
+}
+
+impl EditShape {
+    /// 可以拖动的控制点。矩形为四个角，圆为圆心与正东方向圆周上的一点
+    pub fn vertices(&self) -> Vec<LatLng> {
+        match self {
+            EditShape::Point(p) => vec![*p],
+            EditShape::Line(points) | EditShape::Polygon(points) => points.clone(),
+            EditShape::Rect(a, b) => rect_corners(*a, *b).to_vec(),
+            EditShape::Circle { center, radius } => {
+                vec![*center, center.destination(*radius, 90.0)]
+            }
+        }
+    }
+
+    /// 将第`index`个控制点移动到`to`。拖动矩形的角时对角不动，拖动圆周上的点时改变半径
+    pub fn move_vertex(&mut self, index: usize, to: LatLng) {
+        match self {
+            EditShape::Point(p) => *p = to,
+            EditShape::Line(points) | EditShape::Polygon(points) => {
+                if let Some(p) = points.get_mut(index) {
+                    *p = to;
+                }
+            }
+            EditShape::Rect(a, b) => {
+                //保持角的顺序不变，拖动过程中`index`始终对应同一个角
+                let o = rect_corners(*a, *b)[(index + 2) % 4];
+                let ll = |lat: f64, lng: f64| LatLng { lat, lng };
+                *self = match index % 4 {
+                    0 => EditShape::Rect(to, o),
+                    1 => EditShape::Rect(ll(to.lat, o.lng), ll(o.lat, to.lng)),
+                    2 => EditShape::Rect(o, to),
+                    _ => EditShape::Rect(ll(o.lat, to.lng), ll(to.lat, o.lng)),
+                };
+            }
+            EditShape::Circle { center, radius } => match index {
+                0 => *center = to,
+                _ => *radius = center.distance_to(to),
+            },
+        }
+    }
+
+    /// 是否可以插入或删除顶点，只有折线与多边形可以
+    pub fn is_editable_path(&self) -> bool {
+        matches!(self, EditShape::Line(_) | EditShape::Polygon(_))
+    }
+
+    /// 在`index`处插入顶点，成功时返回true
+    pub fn insert_vertex(&mut self, index: usize, at: LatLng) -> bool {
+        match self {
+            EditShape::Line(points) | EditShape::Polygon(points) if index <= points.len() => {
+                points.insert(index, at);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// 删除第`index`个顶点，折线至少保留2个、多边形至少保留3个顶点，成功时返回true
+    pub fn remove_vertex(&mut self, index: usize) -> bool {
+        let (points, min) = match self {
+            EditShape::Line(points) => (points, 2),
+            EditShape::Polygon(points) => (points, 3),
+            _ => return false,
+        };
+        if index >= points.len() || points.len() <= min {
+            return false;
+        }
+        points.remove(index);
+        true
+    }
+
+    /// 图形的轮廓，矩形为四个角，圆按[CIRCLE_SEGMENTS]等分圆周
+    pub fn outline(&self) -> Vec<LatLng> {
+        match self {
+            EditShape::Rect(a, b) => rect_corners(*a, *b).to_vec(),
+            EditShape::Circle { center, radius } => (0..CIRCLE_SEGMENTS)
+                .map(|i| center.destination(*radius, i as f64 * 360.0 / CIRCLE_SEGMENTS as f64))
+                .collect(),
+            _ => self.vertices(),
+        }
+    }
+
+    /// 轮廓是否闭合
+    pub fn is_closed(&self) -> bool {
+        !matches!(self, EditShape::Point(_) | EditShape::Line(_))
+    }
+
+    /// 用`wcs`转换为[MapItem]，矩形与圆转换为多边形
+    pub fn to_map_item(&self, wcs: &(impl WCS + ?Sized), data: MapItemData) -> MapItem {
+        match self {
+            EditShape::Point(p) => MapItem::point_lat_lng(wcs, *p, data),
+            EditShape::Line(points) => MapItem::line_lat_lng(wcs, points, data),
+            _ => MapItem::polygon_lat_lng(wcs, &self.outline(), data),
+        }
+    }
+}
+
+/// 以`a`、`b`为对角的矩形的四个角，按顺序相邻
+fn rect_corners(a: LatLng, b: LatLng) -> [LatLng; 4] {
+    [
+        a,
+        LatLng {
+            lat: a.lat,
+            lng: b.lng,
+        },
+        b,
+        LatLng {
+            lat: b.lat,
+            lng: a.lng,
+        },
+    ]
+}
+
+/// 带撤销与重做的图形列表，每次修改前保存整个列表的快照
+#[derive(Clone, Debug, Default)]
+pub struct ShapeEditor {
+    shapes: Vec<EditShape>,
+    undo: Vec<Vec<EditShape>>,
+    redo: Vec<Vec<EditShape>>,
+    /// 最多保留的撤销步数，0表示不限制
+    pub max_history: usize,
+}
+
+impl ShapeEditor {
+    pub fn new() -> Self {
+        Self {
+            max_history: 100,
+            ..Default::default()
+        }
+    }
+
+    pub fn shapes(&self) -> &[EditShape] {
+        &self.shapes
+    }
+
+    pub fn len(&self) -> usize {
+        self.shapes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shapes.is_empty()
+    }
+
+    /// 保存当前状态作为一个撤销步骤并清空重做记录。
+    ///
+    /// 拖动等连续修改应在开始时调用一次，之后用[ShapeEditor::move_vertex]修改
+    pub fn checkpoint(&mut self) {
+        self.undo.push(self.shapes.clone());
+        if self.max_history > 0 && self.undo.len() > self.max_history {
+            self.undo.remove(0);
+        }
+        self.redo.clear();
+    }
+
+    /// 添加图形并返回它的下标
+    pub fn push(&mut self, shape: EditShape) -> usize {
+        self.checkpoint();
+        self.shapes.push(shape);
+        self.shapes.len() - 1
+    }
+
+    pub fn remove(&mut self, index: usize) -> Option<EditShape> {
+        if index >= self.shapes.len() {
+            return None;
+        }
+        self.checkpoint();
+        Some(self.shapes.remove(index))
+    }
+
+    pub fn clear(&mut self) {
+        if !self.shapes.is_empty() {
+            self.checkpoint();
+            self.shapes.clear();
+        }
+    }
+
+    /// 移动顶点，不记录撤销步骤
+    pub fn move_vertex(&mut self, shape: usize, vertex: usize, to: LatLng) {
+        if let Some(s) = self.shapes.get_mut(shape) {
+            s.move_vertex(vertex, to);
+        }
+    }
+
+    pub fn insert_vertex(&mut self, shape: usize, index: usize, at: LatLng) -> bool {
+        let Some(mut s) = self.shapes.get(shape).cloned() else {
+            return false;
+        };
+        if !s.insert_vertex(index, at) {
+            return false;
+        }
+        self.checkpoint();
+        self.shapes[shape] = s;
+        true
+    }
+
+    pub fn remove_vertex(&mut self, shape: usize, index: usize) -> bool {
+        let Some(mut s) = self.shapes.get(shape).cloned() else {
+            return false;
+        };
+        if !s.remove_vertex(index) {
+            return false;
+        }
+        self.checkpoint();
+        self.shapes[shape] = s;
+        true
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    pub fn undo(&mut self) -> bool {
+        let Some(shapes) = self.undo.pop() else {
+            return false;
+        };
+        self.redo.push(std::mem::replace(&mut self.shapes, shapes));
+        true
+    }
+
+    pub fn redo(&mut self) -> bool {
+        let Some(shapes) = self.redo.pop() else {
+            return false;
+        };
+        self.undo.push(std::mem::replace(&mut self.shapes, shapes));
+        true
+    }
+
+    /// 用`wcs`将所有图形转换为[MapItem]
+    pub fn to_map_items(&self, wcs: &(impl WCS + ?Sized)) -> Vec<MapItem> {
+        self.shapes
+            .iter()
+            .map(|s| s.to_map_item(wcs, MapItemData::default()))
+            .collect()
+    }
+}
+
+#[test]
+fn test_shape_editor() {
+    let p = |lat: f64, lng: f64| LatLng { lat, lng };
+    let mut editor = ShapeEditor::new();
+    let line = editor.push(EditShape::Line(vec![p(0.0, 0.0), p(0.0, 1.0)]));
+    assert!(editor.insert_vertex(line, 1, p(1.0, 0.5)));
+    assert!(!editor.insert_vertex(line, 5, p(1.0, 0.5)));
+    assert_eq!(editor.shapes()[line].vertices().len(), 3);
+    editor.checkpoint();
+    editor.move_vertex(line, 1, p(2.0, 0.5));
+    editor.move_vertex(line, 1, p(3.0, 0.5));
+    assert!(editor.remove_vertex(line, 1));
+    //折线只剩两个顶点时不能再删除
+    assert!(!editor.remove_vertex(line, 0));
+
+    assert!(editor.undo());
+    assert_eq!(editor.shapes()[line].vertices()[1], p(3.0, 0.5));
+    assert!(editor.undo());
+    assert_eq!(editor.shapes()[line].vertices()[1], p(1.0, 0.5));
+    assert!(editor.redo());
+    assert_eq!(editor.shapes()[line].vertices()[1], p(3.0, 0.5));
+    editor.push(EditShape::Point(p(0.0, 0.0)));
+    assert!(!editor.can_redo());
+    while editor.undo() {}
+    assert!(editor.is_empty());
+
+    //拖动矩形的角时对角不动
+    let mut rect = EditShape::Rect(p(0.0, 0.0), p(1.0, 1.0));
+    rect.move_vertex(2, p(2.0, 3.0));
+    assert_eq!(rect, EditShape::Rect(p(0.0, 0.0), p(2.0, 3.0)));
+    rect.move_vertex(1, p(-1.0, 4.0));
+    assert_eq!(rect.outline()[1], p(-1.0, 4.0));
+    assert_eq!(rect.outline()[3], p(2.0, 0.0));
+    //连续拖动同一个角时对角保持不变
+    let mut rect = EditShape::Rect(p(0.0, 0.0), p(1.0, 1.0));
+    rect.move_vertex(1, p(0.0, 2.0));
+    rect.move_vertex(1, p(0.0, 3.0));
+    assert_eq!(rect.vertices()[1], p(0.0, 3.0));
+    assert_eq!(rect.vertices()[3], p(1.0, 0.0));
+    rect.move_vertex(3, p(2.0, -1.0));
+    rect.move_vertex(3, p(3.0, -1.0));
+    assert_eq!(rect.vertices()[3], p(3.0, -1.0));
+    assert_eq!(rect.vertices()[1], p(0.0, 3.0));
+    rect.move_vertex(0, p(-1.0, -2.0));
+    assert_eq!(rect.vertices()[0], p(-1.0, -2.0));
+    assert_eq!(rect.vertices()[2], p(3.0, 3.0));
+
+    let mut circle = EditShape::Circle {
+        center: p(30.0, 120.0),
+        radius: 1000.0,
+    };
+    let edge = circle.vertices()[1];
+    assert!((p(30.0, 120.0).distance_to(edge) - 1000.0).abs() < 1e-6);
+    circle.move_vertex(1, p(30.0, 120.0).destination(2000.0, 0.0));
+    let EditShape::Circle { radius, .. } = circle else {
+        unreachable!()
+    };
+    assert!((radius - 2000.0).abs() < 1e-6);
+    assert_eq!(circle.outline().len(), CIRCLE_SEGMENTS);
+}
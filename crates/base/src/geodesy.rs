@@ -0,0 +1,529 @@
+use crate::latlng::LatLng;
+
+/// 地球平均半径（IUGG），用于球面计算
+pub const EARTH_MEAN_RADIUS: f64 = 6371008.8;
+/// WGS-84椭球长半轴
+pub const WGS84_A: f64 = 6378137.0;
+/// WGS-84椭球扁率
+pub const WGS84_F: f64 = 1.0 / 298.257223563;
+
+impl LatLng {
+    /// 大圆距离（米），使用haversine公式
+    pub fn distance_to(&self, other: LatLng) -> f64 {
+        let phi1 = self.lat.to_radians();
+        let phi2 = other.lat.to_radians();
+        let d_phi = phi2 - phi1;
+        let d_lambda = (other.lng - self.lng).to_radians();
+        let a =
+            (d_phi / 2.0).sin().powi(2) + phi1.cos() * phi2.cos() * (d_lambda / 2.0).sin().powi(2);
+        2.0 * EARTH_MEAN_RADIUS * a.sqrt().atan2((1.0 - a).sqrt())
+    }
+
+    /// WGS-84椭球面上的距离（米），使用Vincenty反解公式。
+    ///
+    /// 两点接近对跖时迭代可能不收敛，此时返回None。
+    pub fn vincenty_distance_to(&self, other: LatLng) -> Option<f64> {
+        vincenty_inverse(*self, other).map(|r| r.0)
+    }
+
+    /// WGS-84椭球面上的距离（米）。
+    ///
+    /// 优先使用Vincenty反解，两点接近对跖而不收敛时改用[geodesic_inverse_distance]
+    pub fn ellipsoidal_distance_to(&self, other: LatLng) -> f64 {
+        self.vincenty_distance_to(other)
+            .unwrap_or_else(|| geodesic_inverse_distance(*self, other))
+    }
+
+    /// 从当前点出发沿大圆到达`other`的初始方位角，以正北为0，顺时针[0,360)
+    pub fn initial_bearing_to(&self, other: LatLng) -> f64 {
+        let phi1 = self.lat.to_radians();
+        let phi2 = other.lat.to_radians();
+        let d_lambda = (other.lng - self.lng).to_radians();
+        let y = d_lambda.sin() * phi2.cos();
+        let x = phi1.cos() * phi2.sin() - phi1.sin() * phi2.cos() * d_lambda.cos();
+        normalize_bearing(y.atan2(x).to_degrees())
+    }
+
+    /// 沿大圆到达`other`时的方位角
+    pub fn final_bearing_to(&self, other: LatLng) -> f64 {
+        normalize_bearing(other.initial_bearing_to(*self) + 180.0)
+    }
+
+    /// 沿`bearing`方位角的大圆前进`distance`米后到达的点
+    pub fn destination(&self, distance: f64, bearing: f64) -> LatLng {
+        let delta = distance / EARTH_MEAN_RADIUS;
+        let theta = bearing.to_radians();
+        let phi1 = self.lat.to_radians();
+        let lambda1 = self.lng.to_radians();
+        let sin_phi2 = phi1.sin() * delta.cos() + phi1.cos() * delta.sin() * theta.cos();
+        let phi2 = sin_phi2.asin();
+        let y = theta.sin() * delta.sin() * phi1.cos();
+        let x = delta.cos() - phi1.sin() * sin_phi2;
+        let lambda2 = lambda1 + y.atan2(x);
+        LatLng {
+            lat: phi2.to_degrees(),
+            lng: normalize_lng(lambda2.to_degrees()),
+        }
+    }
+
+    /// 大圆上的中点
+    pub fn midpoint(&self, other: LatLng) -> LatLng {
+        let phi1 = self.lat.to_radians();
+        let phi2 = other.lat.to_radians();
+        let lambda1 = self.lng.to_radians();
+        let d_lambda = (other.lng - self.lng).to_radians();
+        let bx = phi2.cos() * d_lambda.cos();
+        let by = phi2.cos() * d_lambda.sin();
+        let phi3 = (phi1.sin() + phi2.sin()).atan2(((phi1.cos() + bx).powi(2) + by * by).sqrt());
+        let lambda3 = lambda1 + by.atan2(phi1.cos() + bx);
+        LatLng {
+            lat: phi3.to_degrees(),
+            lng: normalize_lng(lambda3.to_degrees()),
+        }
+    }
+}
+
+/// 将方位角规整到[0,360)
+pub fn normalize_bearing(bearing: f64) -> f64 {
+    bearing.rem_euclid(360.0)
+}
+
+/// 将经度规整到[-180,180)
+pub fn normalize_lng(lng: f64) -> f64 {
+    (lng + 180.0).rem_euclid(360.0) - 180.0
+}
+
+/// Vincenty反解，返回（距离，初始方位角，最终方位角）
+fn vincenty_inverse(p1: LatLng, p2: LatLng) -> Option<(f64, f64, f64)> {
+    let a = WGS84_A;
+    let f = WGS84_F;
+    let b = (1.0 - f) * a;
+    let l = (p2.lng - p1.lng).to_radians();
+    let u1 = ((1.0 - f) * p1.lat.to_radians().tan()).atan();
+    let u2 = ((1.0 - f) * p2.lat.to_radians().tan()).atan();
+    let (sin_u1, cos_u1) = u1.sin_cos();
+    let (sin_u2, cos_u2) = u2.sin_cos();
+
+    let mut lambda = l;
+    for _ in 0..200 {
+        let (sin_lambda, cos_lambda) = lambda.sin_cos();
+        let sin_sigma = ((cos_u2 * sin_lambda).powi(2)
+            + (cos_u1 * sin_u2 - sin_u1 * cos_u2 * cos_lambda).powi(2))
+        .sqrt();
+        if sin_sigma == 0.0 {
+            // 两点重合
+            return Some((0.0, 0.0, 0.0));
+        }
+        let cos_sigma = sin_u1 * sin_u2 + cos_u1 * cos_u2 * cos_lambda;
+        let sigma = sin_sigma.atan2(cos_sigma);
+        let sin_alpha = cos_u1 * cos_u2 * sin_lambda / sin_sigma;
+        let cos_sq_alpha = 1.0 - sin_alpha * sin_alpha;
+        let cos_2sigma_m = if cos_sq_alpha != 0.0 {
+            cos_sigma - 2.0 * sin_u1 * sin_u2 / cos_sq_alpha
+        } else {
+            // 赤道线
+            0.0
+        };
+        let c = f / 16.0 * cos_sq_alpha * (4.0 + f * (4.0 - 3.0 * cos_sq_alpha));
+        let lambda_prev = lambda;
+        lambda = l
+            + (1.0 - c)
+                * f
+                * sin_alpha
+                * (sigma
+                    + c * sin_sigma
+                        * (cos_2sigma_m + c * cos_sigma * (-1.0 + 2.0 * cos_2sigma_m.powi(2))));
+        if (lambda - lambda_prev).abs() < 1e-12 {
+            let u_sq = cos_sq_alpha * (a * a - b * b) / (b * b);
+            let big_a =
+                1.0 + u_sq / 16384.0 * (4096.0 + u_sq * (-768.0 + u_sq * (320.0 - 175.0 * u_sq)));
+            let big_b = u_sq / 1024.0 * (256.0 + u_sq * (-128.0 + u_sq * (74.0 - 47.0 * u_sq)));
+            let delta_sigma = big_b
+                * sin_sigma
+                * (cos_2sigma_m
+                    + big_b / 4.0
+                        * (cos_sigma * (-1.0 + 2.0 * cos_2sigma_m.powi(2))
+                            - big_b / 6.0
+                                * cos_2sigma_m
+                                * (-3.0 + 4.0 * sin_sigma.powi(2))
+                                * (-3.0 + 4.0 * cos_2sigma_m.powi(2))));
+            let s = b * big_a * (sigma - delta_sigma);
+            let (sin_lambda, cos_lambda) = lambda.sin_cos();
+            let alpha1 =
+                (cos_u2 * sin_lambda).atan2(cos_u1 * sin_u2 - sin_u1 * cos_u2 * cos_lambda);
+            let alpha2 =
+                (cos_u1 * sin_lambda).atan2(-sin_u1 * cos_u2 + cos_u1 * sin_u2 * cos_lambda);
+            return Some((
+                s,
+                normalize_bearing(alpha1.to_degrees()),
+                normalize_bearing(alpha2.to_degrees()),
+            ));
+        }
+    }
+    None
+}
+
+/// WGS-84椭球面上的测地线距离（米），对任意两点都收敛，用于Vincenty不收敛的近对跖点。
+///
+/// 按Karney（2013）的方法在辅助球上对起点方位角α1求根，使经度差等于目标值，
+/// 测地线积分用数值积分代替级数展开，因此比Vincenty慢
+pub fn geodesic_inverse_distance(p1: LatLng, p2: LatLng) -> f64 {
+    let f = WGS84_F;
+    let b = (1.0 - f) * WGS84_A;
+    let ep2 = f * (2.0 - f) / ((1.0 - f) * (1.0 - f));
+    //规整为lat1 <= 0、|lat1| >= |lat2|、经度差在[0,180]，距离不变
+    let lam12 = normalize_lng(p2.lng - p1.lng).abs().to_radians();
+    let (mut lat1, mut lat2) = if p1.lat.abs() >= p2.lat.abs() {
+        (p1.lat, p2.lat)
+    } else {
+        (p2.lat, p1.lat)
+    };
+    if lat1 > 0.0 {
+        (lat1, lat2) = (-lat1, -lat2);
+    }
+    if lat1 == 0.0 {
+        //两点都在赤道上，经度差不超过(1-f)π时沿赤道最短
+        if lam12 <= (1.0 - f) * std::f64::consts::PI {
+            return WGS84_A * lam12;
+        }
+        //否则起点稍微偏南，使下面取正根的公式成立
+        lat1 = -1e-12;
+    }
+    let reduced = |lat: f64| ((1.0 - f) * lat.to_radians().tan()).atan().sin_cos();
+    let (sbet1, cbet1) = reduced(lat1);
+    let (sbet2, cbet2) = reduced(lat2);
+
+    //起点方位角为α1时的（经度差，距离）
+    let solve = |alp1: f64, with_distance: bool| {
+        let (salp1, calp1) = alp1.sin_cos();
+        let salp0 = salp1 * cbet1;
+        let calp2 = ((calp1 * cbet1).powi(2) + (cbet2 - cbet1) * (cbet2 + cbet1))
+            .max(0.0)
+            .sqrt()
+            / cbet2;
+        let (ssig1, csig1) = (sbet1, calp1 * cbet1);
+        let (ssig2, csig2) = (sbet2, calp2 * cbet2);
+        let sig1 = ssig1.atan2(csig1);
+        let sig12 = (csig1 * ssig2 - ssig1 * csig2)
+            .max(0.0)
+            .atan2(csig1 * csig2 + ssig1 * ssig2);
+        let (somg1, comg1) = (salp0 * sbet1, csig1);
+        let (somg2, comg2) = (salp0 * sbet2, csig2);
+        let omg12 = (comg1 * somg2 - somg1 * comg2)
+            .max(0.0)
+            .atan2(comg1 * comg2 + somg1 * somg2);
+        let k2 = ep2 * (1.0 - salp0 * salp0);
+        let i3 = simpson(sig1, sig1 + sig12, |sig| {
+            (2.0 - f) / (1.0 + (1.0 - f) * (1.0 + k2 * sig.sin().powi(2)).sqrt())
+        });
+        let lam = omg12 - f * salp0 * i3;
+        let s = if with_distance {
+            b * simpson(sig1, sig1 + sig12, |sig| {
+                (1.0 + k2 * sig.sin().powi(2)).sqrt()
+            })
+        } else {
+            0.0
+        };
+        (lam, s)
+    };
+
+    //经度差随α1从0到π单调增加，二分求根
+    let (mut lo, mut hi) = (0.0, std::f64::consts::PI);
+    for _ in 0..64 {
+        let mid = (lo + hi) / 2.0;
+        if solve(mid, false).0 < lam12 {
+            lo = mid;
+        } else {
+            hi = mid;
+        }
+    }
+    solve((lo + hi) / 2.0, true).1
+}
+
+/// 复合辛普森积分
+fn simpson(a: f64, b: f64, f: impl Fn(f64) -> f64) -> f64 {
+    const N: usize = 512;
+    let h = (b - a) / N as f64;
+    let sum: f64 = (1..N)
+        .map(|i| f(a + h * i as f64) * if i % 2 == 1 { 4.0 } else { 2.0 })
+        .sum();
+    (f(a) + f(b) + sum) * h / 3.0
+}
+
+/// 多边形周长（米），首尾自动闭合
+pub fn polygon_perimeter(polygon: &[LatLng]) -> f64 {
+    if polygon.len() < 2 {
+        return 0.0;
+    }
+    polygon
+        .iter()
+        .zip(polygon.iter().cycle().skip(1))
+        .map(|(a, b)| a.distance_to(*b))
+        .sum()
+}
+
+/// 多边形在球面上的面积（平方米），首尾自动闭合，与顶点顺序无关。
+///
+/// 相邻顶点的经度差取[-180,180)内较短的一侧，多边形可以跨越反子午线
+pub fn polygon_area(polygon: &[LatLng]) -> f64 {
+    if polygon.len() < 3 {
+        return 0.0;
+    }
+    let sum: f64 = polygon
+        .iter()
+        .zip(polygon.iter().cycle().skip(1))
+        .map(|(a, b)| {
+            normalize_lng(b.lng - a.lng).to_radians()
+                * (2.0 + a.lat.to_radians().sin() + b.lat.to_radians().sin())
+        })
+        .sum();
+    (sum * EARTH_MEAN_RADIUS * EARTH_MEAN_RADIUS / 2.0).abs()
+}
+
+/// 折线长度（米），不闭合
+pub fn path_length(path: &[LatLng]) -> f64 {
+    path.windows(2).map(|w| w[0].distance_to(w[1])).sum()
+}
+
+/// 国际英尺（米）
+pub const FOOT: f64 = 0.3048;
+/// 国际英里（米）
+pub const MILE: f64 = 1609.344;
+/// 国际海里（米）
+pub const NAUTICAL_MILE: f64 = 1852.0;
+/// 英亩（平方米）
+pub const ACRE: f64 = 4046.8564224;
+
+/// 距离与面积的显示单位制
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum UnitSystem {
+    /// 米、千米，平方米、平方千米
+    #[default]
+    Metric,
+    /// 英尺、英里，平方英尺、英亩、平方英里
+    Imperial,
+    /// 海里，平方海里
+    Nautical,
+}
+
+impl UnitSystem {
+    pub const ALL: [UnitSystem; 3] = [
+        UnitSystem::Metric,
+        UnitSystem::Imperial,
+        UnitSystem::Nautical,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            UnitSystem::Metric => "Metric",
+            UnitSystem::Imperial => "Imperial",
+            UnitSystem::Nautical => "Nautical",
+        }
+    }
+
+    /// 按单位制格式化距离，`meters`单位为米
+    pub fn format_distance(&self, meters: f64) -> String {
+        match self {
+            UnitSystem::Metric if meters < 1000.0 => format!("{:.1} m", meters),
+            UnitSystem::Metric => format!("{:.3} km", meters / 1000.0),
+            UnitSystem::Imperial if meters < MILE => format!("{:.0} ft", meters / FOOT),
+            UnitSystem::Imperial => format!("{:.3} mi", meters / MILE),
+            UnitSystem::Nautical => format!("{:.3} NM", meters / NAUTICAL_MILE),
+        }
+    }
+
+    /// 按单位制格式化面积，`square_meters`单位为平方米
+    pub fn format_area(&self, square_meters: f64) -> String {
+        match self {
+            UnitSystem::Metric if square_meters < 1e6 => format!("{:.0} m²", square_meters),
+            UnitSystem::Metric => format!("{:.3} km²", square_meters / 1e6),
+            UnitSystem::Imperial if square_meters < ACRE => {
+                format!("{:.0} ft²", square_meters / (FOOT * FOOT))
+            }
+            UnitSystem::Imperial if square_meters < MILE * MILE => {
+                format!("{:.2} ac", square_meters / ACRE)
+            }
+            UnitSystem::Imperial => format!("{:.3} mi²", square_meters / (MILE * MILE)),
+            UnitSystem::Nautical => {
+                format!("{:.3} NM²", square_meters / (NAUTICAL_MILE * NAUTICAL_MILE))
+            }
+        }
+    }
+}
+
+/// 点是否在多边形内（射线法，按经纬度平面计算）。
+///
+/// 顶点的经度相对首个顶点展开，多边形可以跨越反子午线，但不能包含极点
+pub fn polygon_contains(polygon: &[LatLng], point: LatLng) -> bool {
+    let Some(first) = polygon.first() else {
+        return false;
+    };
+    let mut lng = first.lng;
+    let ring: Vec<LatLng> = polygon
+        .iter()
+        .scan(first, |prev, p| {
+            lng += normalize_lng(p.lng - prev.lng);
+            *prev = p;
+            Some(LatLng { lat: p.lat, lng })
+        })
+        .collect();
+    //展开后的经度范围可能超出首个顶点两侧各180°，点的三个副本都要检查
+    let lng = first.lng + normalize_lng(point.lng - first.lng);
+    [lng - 360.0, lng, lng + 360.0].into_iter().any(|lng| {
+        let mut inside = false;
+        ring.iter()
+            .zip(ring.iter().cycle().skip(1))
+            .for_each(|(a, b)| {
+                if (a.lat > point.lat) != (b.lat > point.lat)
+                    && lng < (b.lng - a.lng) * (point.lat - a.lat) / (b.lat - a.lat) + a.lng
+                {
+                    inside = !inside;
+                }
+            });
+        inside
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dms(d: f64, m: f64, s: f64) -> f64 {
+        d.signum() * (d.abs() + m / 60.0 + s / 3600.0)
+    }
+
+    #[test]
+    fn test_distance_bearing() {
+        let a = LatLng { lat: 0.0, lng: 0.0 };
+        let b = LatLng { lat: 0.0, lng: 1.0 };
+        assert!((a.distance_to(b) - 111195.0797).abs() < 1e-3);
+        assert!((a.initial_bearing_to(b) - 90.0).abs() < 1e-9);
+
+        // Vincenty论文中的Flinders Peak到Buninyong
+        let flinders = LatLng {
+            lat: dms(-37.0, 57.0, 3.72030),
+            lng: dms(144.0, 25.0, 29.52440),
+        };
+        let buninyong = LatLng {
+            lat: dms(-37.0, 39.0, 10.15610),
+            lng: dms(143.0, 55.0, 35.38390),
+        };
+        let (s, a1, a2) = vincenty_inverse(flinders, buninyong).unwrap();
+        assert!((s - 54972.271).abs() < 1e-3);
+        assert!((a1 - dms(306.0, 52.0, 5.37)).abs() < 1e-5);
+        assert!((a2 - dms(307.0, 10.0, 25.07)).abs() < 1e-5);
+
+        // 近对跖点Vincenty不收敛，Karney论文中的例子
+        let p1 = LatLng {
+            lat: -30.0,
+            lng: 0.0,
+        };
+        let p2 = LatLng {
+            lat: 29.9,
+            lng: 179.8,
+        };
+        assert!(p1.vincenty_distance_to(p2).is_none());
+        assert!((p1.ellipsoidal_distance_to(p2) - 19989832.827610).abs() < 1e-3);
+        // 收敛时与Vincenty一致
+        let s2 = geodesic_inverse_distance(flinders, buninyong);
+        assert!((s2 - 54972.271).abs() < 1e-3);
+        let e = LatLng {
+            lat: 0.0,
+            lng: 170.0,
+        };
+        let s3 = geodesic_inverse_distance(a, e);
+        assert!((s3 - a.vincenty_distance_to(e).unwrap()).abs() < 1e-3);
+        let c = LatLng {
+            lat: 0.5,
+            lng: 179.7,
+        };
+        assert!(a.ellipsoidal_distance_to(c) > 19_900_000.0);
+    }
+
+    #[test]
+    fn test_destination_midpoint() {
+        let a = LatLng {
+            lat: 51.4778,
+            lng: -0.0015,
+        };
+        let d = a.destination(7794.0, 300.7);
+        assert!((a.distance_to(d) - 7794.0).abs() < 1e-6);
+        assert!((a.initial_bearing_to(d) - 300.7).abs() < 1e-9);
+
+        let m = a.midpoint(d);
+        assert!((a.distance_to(m) - d.distance_to(m)).abs() < 1e-6);
+
+        let e = LatLng {
+            lat: 0.0,
+            lng: 179.5,
+        }
+        .destination(111195.0797, 90.0);
+        assert!((e.lng + 179.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_polygon() {
+        let square = [
+            LatLng { lat: 0.0, lng: 0.0 },
+            LatLng { lat: 0.0, lng: 1.0 },
+            LatLng { lat: 1.0, lng: 1.0 },
+            LatLng { lat: 1.0, lng: 0.0 },
+        ];
+        let expected =
+            EARTH_MEAN_RADIUS.powi(2) * 1.0_f64.to_radians() * 1.0_f64.to_radians().sin();
+        assert!((polygon_area(&square) - expected).abs() < 1e-3);
+        assert!((polygon_perimeter(&square) - 4.0 * 111195.0797).abs() < 50.0);
+        assert!(polygon_contains(&square, LatLng { lat: 0.5, lng: 0.5 }));
+        assert!(!polygon_contains(&square, LatLng { lat: 1.5, lng: 0.5 }));
+
+        //跨越反子午线的多边形与不跨越的同样大小的多边形面积相同
+        let ll = |lat: f64, lng: f64| LatLng { lat, lng };
+        let pacific = [
+            ll(-1.0, 179.5),
+            ll(-1.0, -179.5),
+            ll(1.0, -179.5),
+            ll(1.0, 179.5),
+        ];
+        let greenwich = [ll(-1.0, -0.5), ll(-1.0, 0.5), ll(1.0, 0.5), ll(1.0, -0.5)];
+        assert!((polygon_area(&pacific) - polygon_area(&greenwich)).abs() < 1e-3);
+        assert!(polygon_contains(&pacific, ll(0.0, 180.0)));
+        assert!(polygon_contains(&pacific, ll(0.0, -179.8)));
+        assert!(polygon_contains(&pacific, ll(0.0, 179.8)));
+        assert!(!polygon_contains(&pacific, ll(0.0, 0.0)));
+        assert!(!polygon_contains(&pacific, ll(0.0, 179.0)));
+        //展开后超出首个顶点180°以外的部分
+        let wide = [
+            ll(-1.0, 0.0),
+            ll(-1.0, 120.0),
+            ll(-1.0, -120.0),
+            ll(1.0, -120.0),
+            ll(1.0, 120.0),
+            ll(1.0, 0.0),
+        ];
+        assert!(polygon_contains(&wide, ll(0.0, -150.0)));
+        assert!(!polygon_contains(&wide, ll(0.0, -60.0)));
+    }
+
+    #[test]
+    fn test_units() {
+        let path = [
+            LatLng { lat: 0.0, lng: 0.0 },
+            LatLng { lat: 0.0, lng: 1.0 },
+            LatLng { lat: 0.0, lng: 2.0 },
+        ];
+        assert!((path_length(&path) - 2.0 * path[0].distance_to(path[1])).abs() < 1e-6);
+        assert_eq!(path_length(&path[..1]), 0.0);
+
+        assert_eq!(UnitSystem::Metric.format_distance(12.34), "12.3 m");
+        assert_eq!(UnitSystem::Metric.format_distance(1500.0), "1.500 km");
+        assert_eq!(UnitSystem::Imperial.format_distance(30.48), "100 ft");
+        assert_eq!(UnitSystem::Imperial.format_distance(2.0 * MILE), "2.000 mi");
+        assert_eq!(UnitSystem::Nautical.format_distance(926.0), "0.500 NM");
+        assert_eq!(UnitSystem::Metric.format_area(2.5e6), "2.500 km²");
+        assert_eq!(UnitSystem::Imperial.format_area(10.0 * ACRE), "10.00 ac");
+        assert_eq!(
+            UnitSystem::Nautical.format_area(NAUTICAL_MILE * NAUTICAL_MILE),
+            "1.000 NM²"
+        );
+    }
+}
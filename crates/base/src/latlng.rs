@@ -1,27 +1,109 @@
-use std::{f64::consts::PI, fmt::Display, sync::Arc};
+use std::{error::Error, f64::consts::PI, fmt::Display, sync::Arc};
 
 use crate::map_state::Location;
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct LatLng {
     pub lat: f64,
     pub lng: f64,
 }
 
+impl LatLng {
+    /// 校验经纬度，纬度须在[-90,90]之间，经度须在[-180,180]之间
+    pub fn new(lat: f64, lng: f64) -> Result<LatLng, LatLngError> {
+        if !lat.is_finite() || !lng.is_finite() {
+            Err(LatLngError::NotFinite)
+        } else if !(-90.0..=90.0).contains(&lat) {
+            Err(LatLngError::LatOutOfRange(lat))
+        } else if !(-180.0..=180.0).contains(&lng) {
+            Err(LatLngError::LngOutOfRange(lng))
+        } else {
+            Ok(LatLng { lat, lng })
+        }
+    }
+
+    /// 纬度限制在[-90,90]之间，经度绕回到[-180,180)之间
+    pub fn normalized(lat: f64, lng: f64) -> Result<LatLng, LatLngError> {
+        if !lat.is_finite() || !lng.is_finite() {
+            return Err(LatLngError::NotFinite);
+        }
+        Ok(LatLng {
+            lat: lat.clamp(-90.0, 90.0),
+            lng: (lng + 180.0).rem_euclid(360.0) - 180.0,
+        })
+    }
+}
+
 impl Display for LatLng {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{{lat:{},lng:{}}}", self.lat, self.lng)
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LatLngError {
+    NotFinite,
+    /// 纬度超出范围，对于投影来说是超出了投影的定义域
+    LatOutOfRange(f64),
+    LngOutOfRange(f64),
+}
+
+impl Display for LatLngError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LatLngError::NotFinite => write!(f, "lat/lng is not finite"),
+            LatLngError::LatOutOfRange(lat) => write!(f, "latitude {} out of range", lat),
+            LatLngError::LngOutOfRange(lng) => write!(f, "longitude {} out of range", lng),
+        }
+    }
+}
+
+impl Error for LatLngError {}
+
 pub trait WCS {
     fn to_lat_lng(&self, location: Location) -> LatLng;
     fn to_location(&self, lat_lng: LatLng) -> Location;
+
+    /// 投影定义域内的纬度范围
+    fn lat_range(&self) -> (f64, f64) {
+        (-90.0, 90.0)
+    }
+
+    /// 是否为经度方向首尾相接的圆柱投影，此时[Location]的x可以跨越反子午线循环
+    fn wraps_x(&self) -> bool {
+        false
+    }
+
+    /// 是否为Web墨卡托投影，包括GCJ-02等带偏移的墨卡托投影，导出的世界文件只支持这类投影
+    fn is_web_mercator(&self) -> bool {
+        false
+    }
+
+    /// 与[WCS::to_location]相同，但经纬度不合法或超出投影定义域时返回错误
+    fn try_to_location(&self, lat_lng: LatLng) -> Result<Location, LatLngError> {
+        let lat_lng = LatLng::new(lat_lng.lat, lat_lng.lng)?;
+        let (min, max) = self.lat_range();
+        if lat_lng.lat < min || lat_lng.lat > max {
+            return Err(LatLngError::LatOutOfRange(lat_lng.lat));
+        }
+        Ok(self.to_location(lat_lng))
+    }
+
+    /// 将经度绕回到[-180,180)之间，纬度限制在投影定义域内
+    fn clamp_lat_lng(&self, lat_lng: LatLng) -> LatLng {
+        let (min, max) = self.lat_range();
+        LatLng {
+            lat: lat_lng.lat.clamp(min, max),
+            lng: (lat_lng.lng + 180.0).rem_euclid(360.0) - 180.0,
+        }
+    }
 }
 
+pub type CommonWCS = Arc<dyn WCS + Send + Sync>;
+
 impl<T> WCS for Arc<T>
 where
-    T: WCS + Send + Sync,
+    T: WCS + Send + Sync + ?Sized,
 {
     fn to_lat_lng(&self, location: Location) -> LatLng {
         self.as_ref().to_lat_lng(location)
@@ -30,6 +112,18 @@ where
     fn to_location(&self, lat_lng: LatLng) -> Location {
         self.as_ref().to_location(lat_lng)
     }
+
+    fn lat_range(&self) -> (f64, f64) {
+        self.as_ref().lat_range()
+    }
+
+    fn wraps_x(&self) -> bool {
+        self.as_ref().wraps_x()
+    }
+
+    fn is_web_mercator(&self) -> bool {
+        self.as_ref().is_web_mercator()
+    }
 }
 
 pub struct WebMercator;
@@ -38,6 +132,8 @@ impl WebMercator {
     const EARTH_RADIUS: f64 = 6378137.0;
     // 墨卡托投影最大范围（赤道周长）
     const MERCATOR_MAX: f64 = 20037508.342789244;
+    // 墨卡托投影的最大纬度，超出后投影坐标趋于无穷
+    pub const MAX_LATITUDE: f64 = 85.0511287798066;
 }
 
 impl WCS for WebMercator {
@@ -56,14 +152,29 @@ impl WCS for WebMercator {
     fn to_location(&self, lat_lng: LatLng) -> Location {
         // 经度线性映射到[0,1]范围
         let x = (lat_lng.lng + 180.0) / 360.0;
-        // 将纬度转换为墨卡托投影坐标
-        let lat_rad = lat_lng.lat.to_radians();
+        // 将纬度转换为墨卡托投影坐标，超出定义域的纬度截断到边界
+        let lat_rad = lat_lng
+            .lat
+            .clamp(-WebMercator::MAX_LATITUDE, WebMercator::MAX_LATITUDE)
+            .to_radians();
         let y_merc = WebMercator::EARTH_RADIUS * (PI / 4.0 + lat_rad / 2.0).tan().ln();
         // 将墨卡托坐标归一化并翻转Y轴
         let y = (WebMercator::MERCATOR_MAX - y_merc) / (2.0 * WebMercator::MERCATOR_MAX);
 
         Location { x, y }
     }
+
+    fn lat_range(&self) -> (f64, f64) {
+        (-WebMercator::MAX_LATITUDE, WebMercator::MAX_LATITUDE)
+    }
+
+    fn wraps_x(&self) -> bool {
+        true
+    }
+
+    fn is_web_mercator(&self) -> bool {
+        true
+    }
 }
 
 // 单元测试验证关键坐标点
@@ -91,4 +202,33 @@ mod tests {
         assert_eq!(se.lng, 180.0);
         assert_eq!(se.lat, -85.0511287798066);
     }
+
+    #[test]
+    fn test_validate() {
+        assert!(LatLng::new(91.0, 0.0).is_err());
+        assert!(LatLng::new(0.0, f64::NAN).is_err());
+        let l = LatLng::normalized(95.0, 190.0).unwrap();
+        assert_eq!(l.lat, 90.0);
+        assert_eq!(l.lng, -170.0);
+
+        // 超出墨卡托定义域的纬度截断到边界，不会产生无穷大
+        let pole = WebMercator.to_location(LatLng {
+            lat: 90.0,
+            lng: 0.0,
+        });
+        assert_eq!(pole.y, 0.0);
+        assert_eq!(
+            WebMercator.try_to_location(LatLng {
+                lat: 89.0,
+                lng: 0.0
+            }),
+            Err(LatLngError::LatOutOfRange(89.0))
+        );
+        let c = WebMercator.clamp_lat_lng(LatLng {
+            lat: -89.0,
+            lng: 180.0,
+        });
+        assert_eq!(c.lat, -WebMercator::MAX_LATITUDE);
+        assert_eq!(c.lng, -180.0);
+    }
 }
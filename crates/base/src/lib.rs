@@ -1,9 +1,16 @@
 use std::time::{SystemTime, UNIX_EPOCH};
 
+pub mod camera;
+pub mod cluster;
+pub mod coord_format;
+pub mod crs;
+pub mod edit;
+pub mod geodesy;
 pub mod latlng;
 pub mod map_state;
 pub mod map_view_state;
 pub mod qtree;
+pub mod utm;
 
 pub fn curr_time_millis() -> u128 {
     SystemTime::now()
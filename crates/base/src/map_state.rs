@@ -6,9 +6,12 @@ use std::{
 
 use rustc_hash::FxHashMap;
 
-use super::qtree::QTreeKey;
+use super::{
+    latlng::{LatLng, WCS},
+    qtree::QTreeKey,
+};
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Location {
     pub x: f64,
     pub y: f64,
@@ -65,6 +68,24 @@ impl Location {
         Self { x, y }
     }
 
+    pub fn is_finite(&self) -> bool {
+        self.x.is_finite() && self.y.is_finite()
+    }
+
+    /// 将x绕回到[0,1)之间，用于跨越反子午线的圆柱投影
+    pub fn wrap_x(&self) -> Self {
+        Self {
+            x: self.x.rem_euclid(1.0),
+            y: self.y,
+        }
+    }
+
+    /// 到`other`的差值，x方向取跨越反子午线后较短的一侧
+    pub fn wrapped_delta(&self, other: Location) -> Location {
+        let dx = (other.x - self.x + 0.5).rem_euclid(1.0) - 0.5;
+        Location::new(dx, other.y - self.y)
+    }
+
     pub fn from_qtree_key(key: QTreeKey) -> Self {
         let x = (key.x() as f64) / 2.0_f64.powf(key.depth() as f64);
         let y = (key.y() as f64) / 2.0_f64.powf(key.depth() as f64);
@@ -106,6 +127,14 @@ pub fn walk(lt: QTreeKey, rb: QTreeKey) -> impl Iterator<Item = QTreeKey> {
     })
 }
 
+#[test]
+fn test_wrap_x() {
+    assert_eq!(Location::new(1.25, 0.5).wrap_x(), Location::new(0.25, 0.5));
+    assert_eq!(Location::new(-0.25, 0.5).wrap_x(), Location::new(0.75, 0.5));
+    let d = Location::new(0.9, 0.5).wrapped_delta(Location::new(0.1, 0.25));
+    assert!((d.x - 0.2).abs() < 1e-12 && d.y == -0.25);
+}
+
 #[test]
 fn test_location() {
     println!("{}", Location::new(1.0, 1.0).as_qtree_key(28).unwrap());
@@ -144,9 +173,145 @@ pub enum MapItem {
     },
 }
 
+impl MapItem {
+    pub fn point(location: Location, data: MapItemData) -> Self {
+        Self::Point { location, data }
+    }
+
+    pub fn line(locations: Vec<Location>, data: MapItemData) -> Self {
+        Self::Line { locations, data }
+    }
+
+    /// 多边形不需要重复首个顶点
+    pub fn polygon(locations: Vec<Location>, data: MapItemData) -> Self {
+        Self::Polygon { locations, data }
+    }
+
+    /// 用`wcs`将经纬度转换为位置后创建点
+    pub fn point_lat_lng(wcs: &(impl WCS + ?Sized), lat_lng: LatLng, data: MapItemData) -> Self {
+        Self::point(wcs.to_location(lat_lng), data)
+    }
+
+    pub fn line_lat_lng(wcs: &(impl WCS + ?Sized), lat_lngs: &[LatLng], data: MapItemData) -> Self {
+        Self::line(lat_lngs.iter().map(|l| wcs.to_location(*l)).collect(), data)
+    }
+
+    pub fn polygon_lat_lng(
+        wcs: &(impl WCS + ?Sized),
+        lat_lngs: &[LatLng],
+        data: MapItemData,
+    ) -> Self {
+        Self::polygon(lat_lngs.iter().map(|l| wcs.to_location(*l)).collect(), data)
+    }
+
+    pub fn data(&self) -> &MapItemData {
+        match self {
+            MapItem::Point { data, .. } => data,
+            MapItem::Line { data, .. } => data,
+            MapItem::Polygon { data, .. } => data,
+        }
+    }
+
+    pub fn locations(&self) -> &[Location] {
+        match self {
+            MapItem::Point { location, .. } => std::slice::from_ref(location),
+            MapItem::Line { locations, .. } => locations,
+            MapItem::Polygon { locations, .. } => locations,
+        }
+    }
+
+    /// 外包矩形的左上与右下，没有顶点时返回None
+    pub fn bounds(&self) -> Option<(Location, Location)> {
+        let locations = self.locations();
+        let first = *locations.first()?;
+        Some(locations.iter().fold((first, first), |(lt, rb), l| {
+            (
+                Location::new(lt.x.min(l.x), lt.y.min(l.y)),
+                Location::new(rb.x.max(l.x), rb.y.max(l.y)),
+            )
+        }))
+    }
+}
+
+#[derive(Clone, Debug, Default)]
 pub struct MapItemData {
-    name: String,
-    props: FxHashMap<String, String>,
+    pub name: String,
+    pub props: FxHashMap<String, String>,
+}
+
+impl MapItemData {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            props: FxHashMap::default(),
+        }
+    }
+
+    pub fn with_prop(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.props.insert(key.into(), value.into());
+        self
+    }
+}
+
+/// Douglas-Peucker折线抽稀，偏离小于`tolerance`的顶点会被去掉，首尾顶点始终保留
+pub fn simplify_locations(locations: &[Location], tolerance: f64) -> Vec<Location> {
+    if locations.len() < 3 || tolerance <= 0.0 {
+        return locations.to_vec();
+    }
+    let mut keep = vec![false; locations.len()];
+    keep[0] = true;
+    keep[locations.len() - 1] = true;
+    let mut stack = vec![(0, locations.len() - 1)];
+    while let Some((start, end)) = stack.pop() {
+        let (a, b) = (locations[start], locations[end]);
+        let d = b - a;
+        let len2 = d.x * d.x + d.y * d.y;
+        let (mut max_dist, mut index) = (0.0, start);
+        for (i, p) in locations.iter().enumerate().take(end).skip(start + 1) {
+            let t = if len2 > 0.0 {
+                (((p.x - a.x) * d.x + (p.y - a.y) * d.y) / len2).clamp(0.0, 1.0)
+            } else {
+                0.0
+            };
+            let dist = (p.x - a.x - d.x * t).hypot(p.y - a.y - d.y * t);
+            if dist > max_dist {
+                max_dist = dist;
+                index = i;
+            }
+        }
+        if max_dist > tolerance {
+            keep[index] = true;
+            stack.push((start, index));
+            stack.push((index, end));
+        }
+    }
+    locations
+        .iter()
+        .zip(keep)
+        .filter(|(_, k)| *k)
+        .map(|(l, _)| *l)
+        .collect()
+}
+
+#[test]
+fn test_simplify() {
+    let line: Vec<Location> = (0..=100)
+        .map(|i| {
+            let x = i as f64 / 100.0;
+            Location::new(x, if i == 50 { 0.1 } else { x * 1e-6 })
+        })
+        .collect();
+    let simplified = simplify_locations(&line, 1e-3);
+    // 尖峰及其两侧的顶点被保留
+    assert_eq!(
+        simplified,
+        vec![line[0], line[49], line[50], line[51], line[100]]
+    );
+    assert_eq!(simplify_locations(&line, 0.2).len(), 2);
+
+    let item = MapItem::line(simplified, MapItemData::new("l"));
+    let (lt, rb) = item.bounds().unwrap();
+    assert_eq!((lt.x, lt.y, rb.x, rb.y), (0.0, 0.0, 1.0, 0.1));
 }
 
 pub struct MapTileData {
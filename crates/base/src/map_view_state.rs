@@ -1,14 +1,64 @@
-use crate::{map_state::Location, qtree::QTreeKey};
+use std::{ops::RangeInclusive, sync::Arc};
+
+use rustc_hash::FxHashSet;
+
+use crate::{
+    camera::{CameraAnimation, CameraState, Easing},
+    latlng::{CommonWCS, LatLng, WebMercator},
+    map_state::Location,
+    qtree::QTreeKey,
+};
 
 pub struct MapViewState {
     pub central: Location,
     pub view_size: [f64; 2],
     pub zoom_lvl: f64,
+    /// 地图旋转角度，单位为度，表示视图正上方对应的方位角，顺时针为正
+    pub bearing: f64,
+    /// 相机俯仰角，单位为度，0为正俯视
+    pub pitch: f64,
+    /// 视图使用的坐标系，[Location]均处于该坐标系下
+    pub wcs: CommonWCS,
+    /// 视图允许的缩放级别范围
+    pub min_zoom: f64,
+    pub max_zoom: f64,
+    /// 选择瓦片层级时对缩放级别的偏移，层级为`floor(zoom_lvl + level_bias)`。
+    ///
+    /// 0表示缩放到下一整数级别才切换，瓦片始终被放大显示；0.5表示就近取整。
+    pub level_bias: f64,
+    animation: Option<CameraAnimation>,
 }
 
 pub static TILE_SIZE: f64 = 256.0;
 
+/// 最大俯仰角，此时地平线仍在视图之外
+pub const MAX_PITCH: f64 = 60.0;
+
+/// 相机到视图中心的距离与视图高度之比，对应约37°的垂直视场角
+pub const CAMERA_DISTANCE: f64 = 1.5;
+
+/// [QTreeKey]支持的最大深度
+pub const MAX_TILE_DEPTH: u8 = 28;
+
+/// 瓦片源最多缩小显示的层级数，每缩小一级需要加载的瓦片数约为4倍
+pub const MAX_UNDERZOOM: u8 = 3;
+
 impl MapViewState {
+    pub fn new(central: Location, view_size: [f64; 2], zoom_lvl: f64) -> Self {
+        Self {
+            central,
+            view_size,
+            zoom_lvl,
+            bearing: 0.0,
+            pitch: 0.0,
+            wcs: Arc::new(WebMercator),
+            min_zoom: 2.0,
+            max_zoom: 18.5,
+            level_bias: 0.4,
+            animation: None,
+        }
+    }
+
     pub fn zoom(&self) -> f64 {
         2.0_f64.powf(self.zoom_lvl)
     }
@@ -16,10 +66,10 @@ impl MapViewState {
     pub fn apply_zoom_delta(&mut self, delta: f64, zoom_view_central: [f64; 2]) {
         let zoom_central = self.view_pos_to_location(zoom_view_central);
         self.zoom_lvl += delta.log2();
-        if self.zoom_lvl < 2.0 {
-            self.zoom_lvl = 2.0
-        } else if self.zoom_lvl > 18.5 {
-            self.zoom_lvl = 18.5
+        if self.zoom_lvl < self.min_zoom {
+            self.zoom_lvl = self.min_zoom
+        } else if self.zoom_lvl > self.max_zoom {
+            self.zoom_lvl = self.max_zoom
         } else {
             //central = pos + delta
             let tmp_pos = self.view_pos_to_location(zoom_view_central);
@@ -29,32 +79,261 @@ impl MapViewState {
         }
     }
 
+    /// 以视图中`view_pos`处为中心缩放`delta`级后的相机状态，该处的位置保持不变，用于缩放动画的目标
+    pub fn camera_zoomed_around(&self, delta: f64, view_pos: [f64; 2]) -> CameraState {
+        let zoom_lvl = self.clamp_zoom(self.zoom_lvl + delta);
+        let anchor = self.view_pos_to_location(view_pos);
+        let k = 2.0_f64.powf(self.zoom_lvl - zoom_lvl);
+        CameraState {
+            central: Location::new(
+                anchor.x + (self.central.x - anchor.x) * k,
+                anchor.y + (self.central.y - anchor.y) * k,
+            ),
+            zoom_lvl,
+            ..self.camera()
+        }
+    }
+
+    /// 将缩放级别限制在[min_zoom, max_zoom]之间
+    pub fn clamp_zoom(&self, zoom_lvl: f64) -> f64 {
+        zoom_lvl.clamp(self.min_zoom, self.max_zoom.max(self.min_zoom))
+    }
+
+    pub fn set_zoom_lvl(&mut self, zoom_lvl: f64) {
+        if zoom_lvl.is_finite() {
+            self.zoom_lvl = self.clamp_zoom(zoom_lvl);
+        }
+    }
+
+    pub fn camera(&self) -> CameraState {
+        CameraState {
+            central: self.central,
+            zoom_lvl: self.zoom_lvl,
+            bearing: self.bearing,
+            pitch: self.pitch,
+        }
+    }
+
+    pub fn set_camera(&mut self, camera: CameraState) {
+        self.set_central(camera.central);
+        self.set_zoom_lvl(camera.zoom_lvl);
+        self.set_bearing(camera.bearing);
+        self.set_pitch(camera.pitch);
+    }
+
+    /// 在`duration`秒内平滑过渡到目标中心、缩放级别与旋转角度，俯仰角保持不变
+    pub fn ease_to(
+        &mut self,
+        central: Location,
+        zoom_lvl: f64,
+        bearing: f64,
+        duration: f64,
+        easing: Easing,
+    ) {
+        let to = CameraState {
+            central,
+            zoom_lvl,
+            bearing,
+            ..self.camera()
+        };
+        self.animation = Some(CameraAnimation::ease(
+            self.camera(),
+            to,
+            self.wcs.wraps_x(),
+            duration,
+            easing,
+        ));
+    }
+
+    /// 先拉远再拉近地飞到目标位置，`duration`为None时按飞行距离自动计算
+    pub fn fly_to(
+        &mut self,
+        central: Location,
+        zoom_lvl: f64,
+        bearing: f64,
+        duration: Option<f64>,
+    ) {
+        let to = CameraState {
+            central,
+            zoom_lvl: self.clamp_zoom(zoom_lvl),
+            bearing,
+            ..self.camera()
+        };
+        self.animation = Some(CameraAnimation::fly(
+            self.camera(),
+            to,
+            self.wcs.wraps_x(),
+            self.view_size[0].max(self.view_size[1]),
+            duration,
+            Easing::EaseInOut,
+        ));
+    }
+
+    pub fn is_animating(&self) -> bool {
+        self.animation.is_some()
+    }
+
+    /// 中断当前动画，相机停留在当前位置，用户操作地图时调用
+    pub fn stop_animation(&mut self) {
+        self.animation = None;
+    }
+
+    /// 将动画推进`dt`秒，返回动画是否仍在进行，进行中时需要继续请求重绘
+    pub fn tick(&mut self, dt: f64) -> bool {
+        let Some(mut animation) = self.animation.take() else {
+            return false;
+        };
+        let camera = animation.advance(dt);
+        self.set_camera(camera);
+        if animation.is_finished() {
+            false
+        } else {
+            self.animation = Some(animation);
+            true
+        }
+    }
+
+    /// 圆柱投影下将`location`换算到离视图中心最近的世界副本，用于绘制存储在[0,1)内的位置
+    pub fn nearest_copy(&self, location: Location) -> Location {
+        if self.wcs.wraps_x() {
+            self.central + self.central.wrapped_delta(location)
+        } else {
+            location
+        }
+    }
+
+    /// 设置视图中心，圆柱投影下x跨越反子午线后绕回，否则限制在[0,1]之间
     pub fn set_central(&mut self, central: Location) {
-        self.central = central;
-        if self.central.x < 0.0 {
-            self.central.x = 0.0
-        } else if self.central.x > 1.0 {
-            self.central.x = 1.0
+        if !central.is_finite() {
+            return;
+        }
+        self.central = if self.wcs.wraps_x() {
+            central.wrap_x()
+        } else {
+            Location::new(central.x.clamp(0.0, 1.0), central.y)
+        };
+        self.central.y = self.central.y.clamp(0.0, 1.0);
+    }
+
+    /// 以经纬度设置视图中心，经纬度会被规整到投影的定义域内
+    pub fn set_central_lat_lng(&mut self, lat_lng: LatLng) {
+        let location = self.wcs.to_location(self.wcs.clamp_lat_lng(lat_lng));
+        self.set_central(location);
+    }
+
+    pub fn central_lat_lng(&self) -> LatLng {
+        self.wcs.to_lat_lng(self.central)
+    }
+
+    /// 设置地图旋转角度，会被规整到[0,360)之间。
+    ///
+    /// 车头朝上模式下每帧以当前航向调用即可。
+    pub fn set_bearing(&mut self, bearing: f64) {
+        if bearing.is_finite() {
+            self.bearing = bearing.rem_euclid(360.0);
         }
-        if self.central.y < 0.0 {
-            self.central.y = 0.0
-        } else if self.central.y > 1.0 {
-            self.central.y = 1.0
+    }
+
+    /// 以视图中`view_pos`处为轴旋转地图，旋转后该处的位置保持不变
+    pub fn rotate_around(&mut self, delta: f64, view_pos: [f64; 2]) {
+        let anchor = self.view_pos_to_location(view_pos);
+        self.set_bearing(self.bearing + delta);
+        let moved = self.view_pos_to_location(view_pos);
+        self.set_central(self.central + anchor - moved);
+    }
+
+    /// 按视图中的像素位移平移地图，位移方向与拖动方向相同
+    pub fn pan_by(&mut self, delta: [f64; 2]) {
+        let c = [self.view_size[0] / 2.0, self.view_size[1] / 2.0];
+        let central = self.view_pos_to_location([c[0] - delta[0], c[1] - delta[1]]);
+        self.set_central(central);
+    }
+
+    /// 设置俯仰角，0为正俯视，最大为[MAX_PITCH]
+    pub fn set_pitch(&mut self, pitch: f64) {
+        if pitch.is_finite() {
+            self.pitch = pitch.clamp(0.0, MAX_PITCH);
         }
     }
 
+    /// 从[Location]到视图位置的投影矩阵（齐次坐标），依次为平移、缩放、旋转、透视与平移到视图中心。
+    ///
+    /// 相机位于视图中心所对地面点的正后上方，距离为视图高度的[CAMERA_DISTANCE]倍。
+    pub fn projection_matrix(&self) -> [[f64; 3]; 3] {
+        let scale = TILE_SIZE * self.zoom();
+        let [w, h] = self.view_size;
+        let (b_sin, b_cos) = self.bearing.to_radians().sin_cos();
+        let (p_sin, p_cos) = self.pitch.to_radians().sin_cos();
+        let d = CAMERA_DISTANCE * h.max(1.0);
+        let translate = [
+            [scale, 0.0, -self.central.x * scale],
+            [0.0, scale, -self.central.y * scale],
+            [0.0, 0.0, 1.0],
+        ];
+        let rotate = [[b_cos, b_sin, 0.0], [-b_sin, b_cos, 0.0], [0.0, 0.0, 1.0]];
+        let perspective = [[1.0, 0.0, 0.0], [0.0, p_cos, 0.0], [0.0, -p_sin / d, 1.0]];
+        let center = [[1.0, 0.0, w / 2.0], [0.0, 1.0, h / 2.0], [0.0, 0.0, 1.0]];
+        mat3_mul(
+            &center,
+            &mat3_mul(&perspective, &mat3_mul(&rotate, &translate)),
+        )
+    }
+
+    /// 俯仰时视图上方可能超出地平线，此时返回地平线附近的极远处
     pub fn view_pos_to_location(&self, pos: [f64; 2]) -> Location {
-        let zoom = self.zoom();
-        let x: f64 = self.central.x + (pos[0] - self.view_size[0] / 2.0) / (TILE_SIZE * zoom);
-        let y: f64 = self.central.y + (pos[1] - self.view_size[1] / 2.0) / (TILE_SIZE * zoom);
+        let [x, y] = mat3_apply(&mat3_inverse(&self.projection_matrix()), pos);
         Location::new(x, y)
     }
 
     pub fn location_to_view_pos(&self, location: Location) -> [f64; 2] {
-        let zoom = self.zoom();
-        let x = (location.x - self.central.x) * (TILE_SIZE * zoom) + self.view_size[0] / 2.0;
-        let y = (location.y - self.central.y) * (TILE_SIZE * zoom) + self.view_size[1] / 2.0;
-        [x, y]
+        mat3_apply(&self.projection_matrix(), [location.x, location.y])
+    }
+
+    /// 圆柱投影下会先将x绕回，返回的经度始终在[-180,180)之间
+    pub fn view_pos_to_lat_lng(&self, pos: [f64; 2]) -> LatLng {
+        let location = self.view_pos_to_location(pos);
+        if self.wcs.wraps_x() {
+            self.wcs.to_lat_lng(location.wrap_x())
+        } else {
+            self.wcs.to_lat_lng(location)
+        }
+    }
+
+    pub fn lat_lng_to_view_pos(&self, lat_lng: LatLng) -> [f64; 2] {
+        self.location_to_view_pos(self.wcs.to_location(lat_lng))
+    }
+
+    /// 视图与`wcs`坐标系是否都在x方向上首尾相接，此时视图可以显示多个世界副本
+    fn wraps_with(&self, wcs: Option<&CommonWCS>) -> bool {
+        self.wcs.wraps_x() && wcs.map(|w| w.wraps_x()).unwrap_or(true)
+    }
+
+    /// 将视图坐标系下的位置转换到`wcs`坐标系下，`wcs`为None时表示与视图坐标系相同。
+    ///
+    /// 两个坐标系都是圆柱投影时保留x所在的世界副本。
+    pub fn location_to_wcs(&self, location: Location, wcs: Option<&CommonWCS>) -> Location {
+        match wcs {
+            Some(wcs) if self.wraps_with(Some(wcs)) => {
+                let world = location.x.floor();
+                let l = wcs.to_location(self.wcs.to_lat_lng(location.wrap_x()));
+                Location::new(l.x + world, l.y)
+            }
+            Some(wcs) => wcs.to_location(self.wcs.to_lat_lng(location)),
+            None => location,
+        }
+    }
+
+    /// 将`wcs`坐标系下的位置转换到视图坐标系下，`wcs`为None时表示与视图坐标系相同
+    pub fn location_from_wcs(&self, location: Location, wcs: Option<&CommonWCS>) -> Location {
+        match wcs {
+            Some(wcs) if self.wraps_with(Some(wcs)) => {
+                let world = location.x.floor();
+                let l = self.wcs.to_location(wcs.to_lat_lng(location.wrap_x()));
+                Location::new(l.x + world, l.y)
+            }
+            Some(wcs) => self.wcs.to_location(wcs.to_lat_lng(location)),
+            None => location,
+        }
     }
 
     pub fn top_left_location(&self) -> Location {
@@ -64,7 +343,7 @@ impl MapViewState {
 
     pub fn top_left_key(&self) -> QTreeKey {
         self.top_left_location()
-            .as_qtree_key((self.zoom_lvl + 0.4) as u8)
+            .as_qtree_key(self.tile_depth())
             .unwrap()
     }
 
@@ -75,7 +354,450 @@ impl MapViewState {
 
     pub fn bottom_right_key(&self) -> QTreeKey {
         self.bottom_right_location()
-            .as_qtree_key((self.zoom_lvl + 0.4) as u8)
+            .as_qtree_key(self.tile_depth())
             .unwrap()
     }
+
+    /// 当前缩放级别对应的瓦片深度
+    pub fn tile_depth(&self) -> u8 {
+        (self.zoom_lvl + self.level_bias)
+            .floor()
+            .clamp(0.0, MAX_TILE_DEPTH as f64) as u8
+    }
+
+    /// 瓦片源在当前缩放级别下使用的瓦片深度，超出源的显示范围时为None。
+    ///
+    /// 超过`max_native`时放大使用`max_native`层级的瓦片，低于`min_native`时缩小使用`min_native`层级的瓦片，
+    /// 但最多缩小[MAX_UNDERZOOM]级，再低时不显示。
+    pub fn source_depth(&self, levels: &TileLevels) -> Option<u8> {
+        if self.zoom_lvl < levels.min_zoom || self.zoom_lvl > levels.max_zoom {
+            return None;
+        }
+        let depth = self.tile_depth();
+        if depth.saturating_add(MAX_UNDERZOOM) < levels.min_native {
+            return None;
+        }
+        Some(depth.clamp(levels.min_native, levels.max_native.max(levels.min_native)))
+    }
+
+    /// 视图中可见的世界副本序号，非圆柱投影时只有0
+    pub fn visible_worlds(&self) -> RangeInclusive<i64> {
+        if !self.wcs.wraps_x() {
+            return 0..=0;
+        }
+        let (lt, rb) = self.location_range_in(None);
+        lt.x.floor() as i64..=rb.x.floor() as i64
+    }
+
+    /// 视图在`wcs`坐标系下的外包范围，可以跨越世界副本时x不做限制。
+    ///
+    /// 坐标系之间的转换不一定是线性的，所以沿视图边界采样后取外包矩形。
+    pub fn location_range_in(&self, wcs: Option<&CommonWCS>) -> (Location, Location) {
+        const SAMPLES: usize = 8;
+        let [w, h] = self.view_size;
+        let mut lt = Location::new(f64::MAX, f64::MAX);
+        let mut rb = Location::new(f64::MIN, f64::MIN);
+        let samples = if wcs.is_none() { 1 } else { SAMPLES };
+        for i in 0..=samples {
+            let t = i as f64 / samples as f64;
+            for pos in [[t * w, 0.0], [t * w, h], [0.0, t * h], [w, t * h]] {
+                let l = self.location_to_wcs(self.view_pos_to_location(pos), wcs);
+                if l.is_finite() {
+                    lt = Location::new(lt.x.min(l.x), lt.y.min(l.y));
+                    rb = Location::new(rb.x.max(l.x), rb.y.max(l.y));
+                }
+            }
+        }
+        if lt.x > rb.x {
+            return (Location::ZERO, Location::UNIT);
+        }
+        if self.wraps_with(wcs) {
+            (
+                Location::new(lt.x, lt.y.clamp(0.0, 1.0)),
+                Location::new(rb.x, rb.y.clamp(0.0, 1.0)),
+            )
+        } else {
+            (
+                lt.wrap(Location::ZERO, Location::UNIT),
+                rb.wrap(Location::ZERO, Location::UNIT),
+            )
+        }
+    }
+
+    /// 视图在`wcs`坐标系下覆盖的瓦片序号范围，x未对2^depth取模
+    fn tile_index_range(
+        &self,
+        depth: u8,
+        wcs: Option<&CommonWCS>,
+    ) -> (RangeInclusive<i64>, RangeInclusive<i64>) {
+        let n = (1_i64 << depth) as f64;
+        let (lt, rb) = self.location_range_in(wcs);
+        let x0 = (lt.x * n).floor() as i64;
+        let x1 = ((rb.x * n - 0.00001).floor() as i64).max(x0);
+        let y0 = ((lt.y * n).floor() as i64).clamp(0, n as i64 - 1);
+        let y1 = ((rb.y * n - 0.00001).floor() as i64).clamp(y0, n as i64 - 1);
+        (x0..=x1, y0..=y1)
+    }
+
+    /// 视图中可见的`wcs`坐标系下的瓦片，跨越反子午线时同一个key可能出现在多个世界副本中。
+    ///
+    /// 地图旋转或俯仰时外包范围内与视图不相交的瓦片会被剔除；
+    /// 俯仰时按瓦片在视图中的大小选择层级，越靠近地平线层级越低，结果按层级从低到高排列。
+    /// 层级范围由`levels`限制，超出源的显示范围时返回空。
+    pub fn visible_tiles(&self, wcs: Option<&CommonWCS>, levels: &TileLevels) -> Vec<VisibleTile> {
+        let Some(depth) = self.source_depth(levels) else {
+            return vec![];
+        };
+        let (xs, ys) = self.tile_index_range(depth, wcs);
+        let mut seen = FxHashSet::default();
+        let mut tiles = vec![];
+        for iy in ys {
+            for ix in xs.clone() {
+                let corners = self.tile_corners(depth, ix, iy, wcs);
+                if (self.bearing != 0.0 || self.pitch != 0.0)
+                    && !self.quad_intersects_view(&corners)
+                {
+                    continue;
+                }
+                if self.pitch == 0.0 {
+                    tiles.push(VisibleTile::new(depth, ix, iy, corners));
+                    continue;
+                }
+                let d = self
+                    .lod_depth(depth, &corners)
+                    .max(levels.min_native.min(depth));
+                let shift = depth - d;
+                let (px, py) = (ix >> shift, iy >> shift);
+                if seen.insert((d, px, py)) {
+                    let corners = if shift == 0 {
+                        corners
+                    } else {
+                        self.tile_corners(d, px, py, wcs)
+                    };
+                    tiles.push(VisibleTile::new(d, px, py, corners));
+                }
+            }
+        }
+        tiles.sort_by_key(|t| t.key.depth());
+        tiles
+    }
+
+    /// `wcs`坐标系下瓦片四个角在视图中的位置，`ix`未对2^depth取模
+    fn tile_corners(&self, depth: u8, ix: i64, iy: i64, wcs: Option<&CommonWCS>) -> [[f64; 2]; 4] {
+        let size = 1.0 / (1_i64 << depth) as f64;
+        let lt = Location::new(ix as f64 * size, iy as f64 * size);
+        [(0.0, 0.0), (size, 0.0), (size, size), (0.0, size)].map(|(dx, dy)| {
+            let l = Location::new(lt.x + dx, lt.y + dy);
+            self.location_to_view_pos(self.location_from_wcs(l, wcs))
+        })
+    }
+
+    /// 根据瓦片在视图中的最长边计算适合的层级，不超过`depth`
+    fn lod_depth(&self, depth: u8, quad: &[[f64; 2]; 4]) -> u8 {
+        let edge = (0..4)
+            .map(|i| {
+                let (p, q) = (quad[i], quad[(i + 1) % 4]);
+                (q[0] - p[0]).hypot(q[1] - p[1])
+            })
+            .fold(0.0, f64::max);
+        if !edge.is_finite() || edge <= 0.0 {
+            return depth;
+        }
+        let local_zoom = depth as f64 + (edge / TILE_SIZE).log2();
+        (local_zoom + self.level_bias)
+            .floor()
+            .clamp(0.0, depth as f64) as u8
+    }
+
+    /// 视图中的凸四边形是否与视图矩形相交，使用分离轴判断
+    fn quad_intersects_view(&self, quad: &[[f64; 2]; 4]) -> bool {
+        let [w, h] = self.view_size;
+        let view = [[0.0, 0.0], [w, 0.0], [w, h], [0.0, h]];
+        let separated =
+            |a: &[[f64; 2]; 4], b: &[[f64; 2]; 4]| {
+                (0..4).any(|i| {
+                    let (p, q) = (a[i], a[(i + 1) % 4]);
+                    let n = [q[1] - p[1], p[0] - q[0]];
+                    let proj = |v: &[f64; 2]| (v[0] - p[0]) * n[0] + (v[1] - p[1]) * n[1];
+                    let side = (0..4).map(|j| proj(&a[j])).fold(0.0, |s: f64, d| {
+                        if d.abs() > s.abs() {
+                            d
+                        } else {
+                            s
+                        }
+                    });
+                    b.iter().all(|v| proj(v) * side < 0.0)
+                })
+            };
+        !separated(quad, &view) && !separated(&view, quad)
+    }
+
+    /// `wcs`坐标系下的瓦片当前是否可见
+    pub fn is_key_visible(
+        &self,
+        key: QTreeKey,
+        wcs: Option<&CommonWCS>,
+        levels: &TileLevels,
+    ) -> bool {
+        if self.pitch != 0.0 {
+            return self.visible_tiles(wcs, levels).iter().any(|t| t.key == key);
+        }
+        let Some(depth) = self.source_depth(levels) else {
+            return false;
+        };
+        let (mut xs, ys) = self.tile_index_range(depth, wcs);
+        let n = 1_i64 << depth;
+        key.depth() == depth
+            && ys.contains(&(key.y() as i64))
+            && xs.any(|ix| ix.rem_euclid(n) == key.x() as i64)
+    }
+}
+
+fn mat3_mul(a: &[[f64; 3]; 3], b: &[[f64; 3]; 3]) -> [[f64; 3]; 3] {
+    let mut m = [[0.0; 3]; 3];
+    for (i, row) in m.iter_mut().enumerate() {
+        for (j, v) in row.iter_mut().enumerate() {
+            *v = (0..3).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+    m
+}
+
+fn mat3_inverse(m: &[[f64; 3]; 3]) -> [[f64; 3]; 3] {
+    let c =
+        |r0: usize, r1: usize, c0: usize, c1: usize| m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0];
+    let adj = [
+        [c(1, 2, 1, 2), -c(0, 2, 1, 2), c(0, 1, 1, 2)],
+        [-c(1, 2, 0, 2), c(0, 2, 0, 2), -c(0, 1, 0, 2)],
+        [c(1, 2, 0, 1), -c(0, 2, 0, 1), c(0, 1, 0, 1)],
+    ];
+    let det = m[0][0] * adj[0][0] + m[0][1] * adj[1][0] + m[0][2] * adj[2][0];
+    adj.map(|row| row.map(|v| v / det))
+}
+
+/// 对点做齐次变换，w不为正时（位于相机后方）取一个极小的正数
+fn mat3_apply(m: &[[f64; 3]; 3], p: [f64; 2]) -> [f64; 2] {
+    let [x, y, w] = m.map(|row| row[0] * p[0] + row[1] * p[1] + row[2]);
+    let w = if w > 1e-9 { w } else { 1e-9 };
+    [x / w, y / w]
+}
+
+/// 瓦片源的层级范围
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TileLevels {
+    /// 显示该瓦片源的视图缩放级别范围，超出时不显示
+    pub min_zoom: f64,
+    pub max_zoom: f64,
+    /// 瓦片源实际提供的瓦片层级范围
+    pub min_native: u8,
+    pub max_native: u8,
+}
+
+impl Default for TileLevels {
+    fn default() -> Self {
+        Self {
+            min_zoom: 0.0,
+            max_zoom: f64::INFINITY,
+            min_native: 0,
+            max_native: MAX_TILE_DEPTH,
+        }
+    }
+}
+
+impl TileLevels {
+    /// 只提供`min_native`到`max_native`层级瓦片的源，在任意缩放级别下显示
+    pub fn native(min_native: u8, max_native: u8) -> Self {
+        Self {
+            min_native,
+            max_native,
+            ..Default::default()
+        }
+    }
+}
+
+/// 视图中的一个瓦片
+#[derive(Clone, Copy, Debug)]
+pub struct VisibleTile {
+    /// x已对2^depth取模的瓦片key
+    pub key: QTreeKey,
+    /// 所在的世界副本，0为主世界，负数在西侧，正数在东侧
+    pub world: i64,
+    /// 瓦片四个角在视图中的位置，依次为左上、右上、右下、左下
+    pub corners: [[f64; 2]; 4],
+}
+
+impl VisibleTile {
+    /// `ix`为未取模的瓦片序号
+    fn new(depth: u8, ix: i64, iy: i64, corners: [[f64; 2]; 4]) -> Self {
+        let n = 1_i64 << depth;
+        Self {
+            key: QTreeKey::new(depth, ix.rem_euclid(n) as u32, iy as u32).unwrap(),
+            world: ix.div_euclid(n),
+            corners,
+        }
+    }
+}
+
+#[test]
+fn test_visible_tiles() {
+    let mut mvs = MapViewState::new(Location::new(0.0, 0.5), [1024.0, 512.0], 2.0);
+    assert_eq!(mvs.visible_worlds(), -1..=0);
+    let tiles = mvs.visible_tiles(None, &TileLevels::default());
+    // 4x2个瓦片，跨越反子午线
+    assert_eq!(tiles.len(), 8);
+    assert!(tiles.iter().any(|t| t.world == -1 && t.key.x() == 3));
+    assert!(tiles.iter().any(|t| t.world == 0 && t.key.x() == 0));
+    let west = tiles
+        .iter()
+        .find(|t| t.world == -1 && t.key.x() == 2 && t.key.y() == 1)
+        .unwrap();
+    assert_eq!(west.corners[0], [0.0, 0.0]);
+    assert_eq!(west.corners[2], [256.0, 256.0]);
+    assert!(mvs.is_key_visible(
+        QTreeKey::new(2, 3, 2).unwrap(),
+        None,
+        &TileLevels::default()
+    ));
+    assert!(!mvs.is_key_visible(
+        QTreeKey::new(2, 1, 0).unwrap(),
+        None,
+        &TileLevels::default()
+    ));
+
+    mvs.set_central(Location::new(-0.25, 0.5));
+    assert_eq!(mvs.central.x, 0.75);
+    // 反子午线另一侧的位置换算到视图中心所在的世界副本
+    let near = mvs.nearest_copy(Location::new(0.05, 0.5));
+    assert!((near.x - 1.05).abs() < 1e-9);
+    let [x, _] = mvs.location_to_view_pos(near);
+    assert!((0.0..1024.0).contains(&x));
+}
+
+#[test]
+fn test_bearing() {
+    let mut mvs = MapViewState::new(Location::new(0.5, 0.5), [800.0, 400.0], 3.0);
+    mvs.set_bearing(-90.0);
+    assert_eq!(mvs.bearing, 270.0);
+    // 方位角270°时视图正上方指向西
+    let up = mvs.view_pos_to_location([400.0, 0.0]);
+    assert!((up.x - (0.5 - 200.0 / 2048.0)).abs() < 1e-9);
+    assert!((up.y - 0.5).abs() < 1e-9);
+    let pos = mvs.location_to_view_pos(Location::new(0.43, 0.56));
+    let back = mvs.view_pos_to_location(pos);
+    assert!((back.x - 0.43).abs() < 1e-9 && (back.y - 0.56).abs() < 1e-9);
+
+    // 旋转后视图的竖直方向覆盖原来的水平范围
+    let (lt, rb) = mvs.location_range_in(None);
+    assert!((rb.x - lt.x - 400.0 / 2048.0).abs() < 1e-9);
+    assert!((rb.y - lt.y - 800.0 / 2048.0).abs() < 1e-9);
+
+    let anchor = mvs.view_pos_to_location([100.0, 100.0]);
+    mvs.rotate_around(33.0, [100.0, 100.0]);
+    let moved = mvs.view_pos_to_location([100.0, 100.0]);
+    assert!((anchor.x - moved.x).abs() < 1e-9 && (anchor.y - moved.y).abs() < 1e-9);
+
+    // 旋转45°时外包范围四角的瓦片与视图不相交
+    mvs.set_bearing(45.0);
+    mvs.set_central(Location::new(0.5, 0.5));
+    mvs.view_size = [1024.0, 1024.0];
+    let tiles = mvs.visible_tiles(None, &TileLevels::default());
+    assert!(tiles.len() < 36);
+    assert!(tiles.iter().any(|t| t.key.x() == 1 && t.key.y() == 3));
+    assert!(!tiles.iter().any(|t| t.key.x() == 1 && t.key.y() == 1));
+}
+
+#[test]
+fn test_pitch() {
+    let mut mvs = MapViewState::new(Location::new(0.5, 0.5), [1024.0, 768.0], 6.0);
+    mvs.set_bearing(30.0);
+    mvs.set_pitch(75.0);
+    assert_eq!(mvs.pitch, MAX_PITCH);
+    // 视图中心不受俯仰影响
+    let c = mvs.view_pos_to_location([512.0, 384.0]);
+    assert!((c.x - 0.5).abs() < 1e-12 && (c.y - 0.5).abs() < 1e-12);
+    for pos in [[0.0, 0.0], [1024.0, 0.0], [300.0, 700.0]] {
+        let back = mvs.location_to_view_pos(mvs.view_pos_to_location(pos));
+        assert!((back[0] - pos[0]).abs() < 1e-6 && (back[1] - pos[1]).abs() < 1e-6);
+    }
+    // 视图上方比下方覆盖更大的范围
+    let scale = |y: f64| {
+        let a = mvs.view_pos_to_location([0.0, y]);
+        let b = mvs.view_pos_to_location([1024.0, y]);
+        (a.x - b.x).hypot(a.y - b.y)
+    };
+    assert!(scale(0.0) > scale(768.0) * 2.0);
+
+    let tiles = mvs.visible_tiles(None, &TileLevels::default());
+    let depth = mvs.tile_depth();
+    assert!(tiles.iter().any(|t| t.key.depth() == depth));
+    assert!(tiles.iter().any(|t| t.key.depth() < depth));
+    assert!(tiles
+        .windows(2)
+        .all(|w| w[0].key.depth() <= w[1].key.depth()));
+    let far = tiles.first().unwrap().key;
+    assert!(mvs.is_key_visible(far, None, &TileLevels::default()));
+}
+
+#[test]
+fn test_animation() {
+    let mut mvs = MapViewState::new(Location::new(0.5, 0.5), [800.0, 600.0], 4.0);
+    mvs.ease_to(Location::new(0.6, 0.5), 6.0, 90.0, 1.0, Easing::Linear);
+    assert!(mvs.tick(0.5));
+    assert!((mvs.central.x - 0.55).abs() < 1e-9);
+    assert!((mvs.zoom_lvl - 5.0).abs() < 1e-9);
+    assert!((mvs.bearing - 45.0).abs() < 1e-9);
+    assert!(!mvs.tick(0.6));
+    assert!(!mvs.is_animating());
+    assert_eq!(mvs.zoom_lvl, 6.0);
+
+    let zoomed = mvs.camera_zoomed_around(1.0, [200.0, 150.0]);
+    let anchor = mvs.view_pos_to_location([200.0, 150.0]);
+    mvs.set_camera(zoomed);
+    let moved = mvs.view_pos_to_location([200.0, 150.0]);
+    assert!((anchor.x - moved.x).abs() < 1e-9 && (anchor.y - moved.y).abs() < 1e-9);
+    assert_eq!(mvs.zoom_lvl, 7.0);
+
+    mvs.fly_to(Location::new(0.1, 0.3), 10.0, 0.0, None);
+    assert!(mvs.tick(0.1));
+    mvs.stop_animation();
+    let camera = mvs.camera();
+    assert!(!mvs.tick(0.1));
+    assert_eq!(mvs.camera(), camera);
+}
+
+#[test]
+fn test_tile_levels() {
+    let mut mvs = MapViewState::new(Location::new(0.5, 0.5), [512.0, 512.0], 5.7);
+    assert_eq!(mvs.tile_depth(), 6);
+    mvs.level_bias = 0.0;
+    assert_eq!(mvs.tile_depth(), 5);
+
+    // 放大超过源的最大层级时使用最大层级的瓦片
+    let levels = TileLevels::native(0, 3);
+    assert_eq!(mvs.source_depth(&levels), Some(3));
+    let tiles = mvs.visible_tiles(None, &levels);
+    assert!(tiles.iter().all(|t| t.key.depth() == 3));
+    assert!(mvs.is_key_visible(tiles[0].key, None, &levels));
+    // 缩小低于源的最小层级时使用最小层级的瓦片
+    assert_eq!(mvs.source_depth(&TileLevels::native(8, 12)), Some(8));
+    // 低于最小层级太多时不显示，避免加载大量瓦片
+    let far = TileLevels::native(10, 18);
+    assert_eq!(mvs.source_depth(&far), None);
+    assert!(mvs.visible_tiles(None, &far).is_empty());
+    mvs.set_zoom_lvl(3.0);
+    assert_eq!(mvs.source_depth(&far), None);
+    // 超出显示范围时不显示
+    let hidden = TileLevels {
+        min_zoom: 8.0,
+        ..Default::default()
+    };
+    assert_eq!(mvs.source_depth(&hidden), None);
+    assert!(mvs.visible_tiles(None, &hidden).is_empty());
+
+    mvs.min_zoom = 4.0;
+    mvs.max_zoom = 6.0;
+    mvs.apply_zoom_delta(8.0, [256.0, 256.0]);
+    assert_eq!(mvs.zoom_lvl, 6.0);
+    mvs.set_zoom_lvl(1.0);
+    assert_eq!(mvs.zoom_lvl, 4.0);
 }
@@ -135,10 +135,12 @@ pub trait ReadonlyQTree<T> {
     fn get_mut(&mut self, key: QTreeKey) -> Option<&mut QTreeNode<T>>;
 }
 
+#[derive(Clone)]
 pub struct QTree<T> {
     data: FxHashMap<u64, QTreeNode<T>>,
 }
 
+#[derive(Clone)]
 pub struct QTreeNode<T> {
     data: Option<T>,
 }
@@ -147,6 +149,15 @@ impl<T> QTreeNode<T> {
     pub fn new(data: T) -> Self {
         Self { data: Some(data) }
     }
+
+    /// Returns the data of the node. Nodes created implicitly as parents have no data.
+    pub fn data(&self) -> Option<&T> {
+        self.data.as_ref()
+    }
+
+    pub fn data_mut(&mut self) -> Option<&mut T> {
+        self.data.as_mut()
+    }
 }
 
 impl<T> QTree<T> {
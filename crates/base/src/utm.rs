@@ -0,0 +1,344 @@
+use std::fmt::Display;
+
+use crate::{
+    coord_format::CoordParseError,
+    geodesy::{WGS84_A, WGS84_F},
+    latlng::LatLng,
+};
+
+const UTM_K0: f64 = 0.9996;
+const UTM_FALSE_EASTING: f64 = 500000.0;
+const UTM_FALSE_NORTHING: f64 = 10000000.0;
+/// MGRS纬度带字母，从-80°开始每8°一个，X带为12°
+const LAT_BANDS: &[u8] = b"CDEFGHJKLMNPQRSTUVWXX";
+/// MGRS百公里格网的列字母，按`(zone - 1) % 3`分为三组
+const E100K_LETTERS: [&[u8]; 3] = [b"ABCDEFGH", b"JKLMNPQR", b"STUVWXYZ"];
+/// MGRS百公里格网的行字母，偶数带偏移5个字母
+const N100K_LETTERS: &[u8] = b"ABCDEFGHJKLMNPQRSTUV";
+
+/// UTM坐标（WGS-84），纬度带字母同时表示南北半球
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Utm {
+    pub zone: u8,
+    pub band: char,
+    pub easting: f64,
+    pub northing: f64,
+}
+
+impl Utm {
+    pub fn is_north(&self) -> bool {
+        self.band >= 'N'
+    }
+
+    /// UTM只定义在[-80°,84°]之间
+    pub fn from_lat_lng(lat_lng: LatLng) -> Result<Utm, CoordParseError> {
+        let lat = lat_lng.lat;
+        if !(-80.0..=84.0).contains(&lat) {
+            return Err(CoordParseError::OutOfRange);
+        }
+        let lng = (lat_lng.lng + 180.0).rem_euclid(360.0) - 180.0;
+        let mut zone = ((lng + 180.0) / 6.0).floor() as u8 + 1;
+        let band = LAT_BANDS[((lat + 80.0) / 8.0).floor() as usize] as char;
+        // 挪威和斯瓦尔巴的特殊分带
+        if band == 'V' && zone == 31 && lng >= 3.0 {
+            zone = 32;
+        }
+        if band == 'X' {
+            zone = match zone {
+                32 if lng < 9.0 => 31,
+                32 => 33,
+                34 if lng < 21.0 => 33,
+                34 => 35,
+                36 if lng < 33.0 => 35,
+                36 => 37,
+                z => z,
+            };
+        }
+        let lng0 = (zone as f64 - 1.0) * 6.0 - 180.0 + 3.0;
+
+        let (n, big_a, e) = ellipsoid_series();
+        let alpha = [
+            n / 2.0 - 2.0 / 3.0 * n.powi(2) + 5.0 / 16.0 * n.powi(3) + 41.0 / 180.0 * n.powi(4)
+                - 127.0 / 288.0 * n.powi(5)
+                + 7891.0 / 37800.0 * n.powi(6),
+            13.0 / 48.0 * n.powi(2) - 3.0 / 5.0 * n.powi(3)
+                + 557.0 / 1440.0 * n.powi(4)
+                + 281.0 / 630.0 * n.powi(5)
+                - 1983433.0 / 1935360.0 * n.powi(6),
+            61.0 / 240.0 * n.powi(3) - 103.0 / 140.0 * n.powi(4)
+                + 15061.0 / 26880.0 * n.powi(5)
+                + 167603.0 / 181440.0 * n.powi(6),
+            49561.0 / 161280.0 * n.powi(4) - 179.0 / 168.0 * n.powi(5)
+                + 6601661.0 / 7257600.0 * n.powi(6),
+            34729.0 / 80640.0 * n.powi(5) - 3418889.0 / 1995840.0 * n.powi(6),
+            212378941.0 / 319334400.0 * n.powi(6),
+        ];
+
+        let phi = lat.to_radians();
+        let lambda = (lng - lng0).to_radians();
+        let tau = phi.tan();
+        let sigma = (e * (e * tau / (1.0 + tau * tau).sqrt()).atanh()).sinh();
+        let tau_p = tau * (1.0 + sigma * sigma).sqrt() - sigma * (1.0 + tau * tau).sqrt();
+        let xi_p = tau_p.atan2(lambda.cos());
+        let eta_p = (lambda.sin() / (tau_p * tau_p + lambda.cos().powi(2)).sqrt()).asinh();
+        let mut xi = xi_p;
+        let mut eta = eta_p;
+        alpha.iter().enumerate().for_each(|(i, a)| {
+            let j = 2.0 * (i + 1) as f64;
+            xi += a * (j * xi_p).sin() * (j * eta_p).cosh();
+            eta += a * (j * xi_p).cos() * (j * eta_p).sinh();
+        });
+        let easting = UTM_K0 * big_a * eta + UTM_FALSE_EASTING;
+        let mut northing = UTM_K0 * big_a * xi;
+        if northing < 0.0 {
+            northing += UTM_FALSE_NORTHING;
+        }
+        Ok(Utm {
+            zone,
+            band,
+            easting,
+            northing,
+        })
+    }
+
+    pub fn to_lat_lng(&self) -> LatLng {
+        let (n, big_a, e) = ellipsoid_series();
+        let beta = [
+            n / 2.0 - 2.0 / 3.0 * n.powi(2) + 37.0 / 96.0 * n.powi(3)
+                - 1.0 / 360.0 * n.powi(4)
+                - 81.0 / 512.0 * n.powi(5)
+                + 96199.0 / 604800.0 * n.powi(6),
+            1.0 / 48.0 * n.powi(2) + 1.0 / 15.0 * n.powi(3) - 437.0 / 1440.0 * n.powi(4)
+                + 46.0 / 105.0 * n.powi(5)
+                - 1118711.0 / 3870720.0 * n.powi(6),
+            17.0 / 480.0 * n.powi(3) - 37.0 / 840.0 * n.powi(4) - 209.0 / 4480.0 * n.powi(5)
+                + 5569.0 / 90720.0 * n.powi(6),
+            4397.0 / 161280.0 * n.powi(4)
+                - 11.0 / 504.0 * n.powi(5)
+                - 830251.0 / 7257600.0 * n.powi(6),
+            4583.0 / 161280.0 * n.powi(5) - 108847.0 / 3991680.0 * n.powi(6),
+            20648693.0 / 638668800.0 * n.powi(6),
+        ];
+        let x = self.easting - UTM_FALSE_EASTING;
+        let y = if self.is_north() {
+            self.northing
+        } else {
+            self.northing - UTM_FALSE_NORTHING
+        };
+        let eta = x / (UTM_K0 * big_a);
+        let xi = y / (UTM_K0 * big_a);
+        let mut xi_p = xi;
+        let mut eta_p = eta;
+        beta.iter().enumerate().for_each(|(i, b)| {
+            let j = 2.0 * (i + 1) as f64;
+            xi_p -= b * (j * xi).sin() * (j * eta).cosh();
+            eta_p -= b * (j * xi).cos() * (j * eta).sinh();
+        });
+        let sinh_eta_p = eta_p.sinh();
+        let tau_p = xi_p.sin() / (sinh_eta_p * sinh_eta_p + xi_p.cos().powi(2)).sqrt();
+        // 牛顿迭代求解τ
+        let e2 = e * e;
+        let mut tau = tau_p;
+        for _ in 0..20 {
+            let sigma = (e * (e * tau / (1.0 + tau * tau).sqrt()).atanh()).sinh();
+            let tau_i = tau * (1.0 + sigma * sigma).sqrt() - sigma * (1.0 + tau * tau).sqrt();
+            let delta = (tau_p - tau_i) / (1.0 + tau_i * tau_i).sqrt()
+                * (1.0 + (1.0 - e2) * tau * tau)
+                / ((1.0 - e2) * (1.0 + tau * tau).sqrt());
+            tau += delta;
+            if delta.abs() < 1e-12 {
+                break;
+            }
+        }
+        let lng0 = (self.zone as f64 - 1.0) * 6.0 - 180.0 + 3.0;
+        LatLng {
+            lat: tau.atan().to_degrees(),
+            lng: lng0 + sinh_eta_p.atan2(xi_p.cos()).to_degrees(),
+        }
+    }
+
+    /// 格式化为MGRS，`digits`为东向/北向各自的位数（1~5，5位为1米精度）
+    pub fn to_mgrs(&self, digits: u8) -> String {
+        let digits = digits.clamp(1, 5) as i32;
+        let col = (self.easting / 100000.0).floor() as usize;
+        let e100k = E100K_LETTERS[(self.zone as usize - 1) % 3]
+            .get(col.wrapping_sub(1))
+            .map(|c| *c as char)
+            .unwrap_or('?');
+        let row = (self.northing / 100000.0).floor() as usize % 20;
+        let row_offset = if self.zone.is_multiple_of(2) { 5 } else { 0 };
+        let n100k = N100K_LETTERS[(row + row_offset) % 20] as char;
+        let scale = 10_f64.powi(5 - digits);
+        let e = ((self.easting % 100000.0) / scale).floor() as u32;
+        let n = ((self.northing % 100000.0) / scale).floor() as u32;
+        format!(
+            "{}{} {}{} {:0w$} {:0w$}",
+            self.zone,
+            self.band,
+            e100k,
+            n100k,
+            e,
+            n,
+            w = digits as usize
+        )
+    }
+
+    /// 解析MGRS，如`31U DQ 48251 11932`，空格可有可无，返回格网左下角
+    pub fn from_mgrs(s: &str) -> Result<Utm, CoordParseError> {
+        let s: String = s
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect::<String>()
+            .to_ascii_uppercase();
+        let zone_len = s.chars().take_while(|c| c.is_ascii_digit()).count();
+        if !s.is_ascii() || zone_len == 0 || zone_len > 2 || s.len() < zone_len + 3 {
+            return Err(CoordParseError::Invalid(s));
+        }
+        let zone: u8 = s[..zone_len].parse().unwrap();
+        let letters = &s.as_bytes()[zone_len..zone_len + 3];
+        let digits = &s[zone_len + 3..];
+        if !(1..=60).contains(&zone)
+            || !LAT_BANDS.contains(&letters[0])
+            || !digits.len().is_multiple_of(2)
+            || digits.len() > 10
+            || !digits.chars().all(|c| c.is_ascii_digit())
+        {
+            return Err(CoordParseError::Invalid(s));
+        }
+        let band = letters[0] as char;
+        let col = E100K_LETTERS[(zone as usize - 1) % 3]
+            .iter()
+            .position(|c| *c == letters[1])
+            .ok_or_else(|| CoordParseError::Invalid(s.clone()))?;
+        let row = N100K_LETTERS
+            .iter()
+            .position(|c| *c == letters[2])
+            .ok_or_else(|| CoordParseError::Invalid(s.clone()))?;
+        let row_offset = if zone.is_multiple_of(2) { 5 } else { 0 };
+        let n100k = ((row + 20 - row_offset) % 20) as f64 * 100000.0;
+        let half = digits.len() / 2;
+        let scale = 10_f64.powi(5 - half as i32);
+        let e: f64 = digits[..half].parse::<f64>().unwrap_or(0.0) * scale;
+        let n: f64 = digits[half..].parse::<f64>().unwrap_or(0.0) * scale;
+
+        // 百公里格网行字母每2000km循环一次，根据纬度带确定所在的循环
+        let band_lat =
+            (LAT_BANDS.iter().position(|c| *c == letters[0]).unwrap() as f64) * 8.0 - 80.0;
+        let band_northing = Utm::from_lat_lng(LatLng {
+            lat: band_lat,
+            lng: (zone as f64 - 1.0) * 6.0 - 180.0 + 3.0,
+        })
+        .map(|u| (u.northing / 100000.0).floor() * 100000.0)
+        .unwrap_or(0.0);
+        let mut n2m = 0.0;
+        while n2m + n100k + n < band_northing {
+            n2m += 2000000.0;
+        }
+        Ok(Utm {
+            zone,
+            band,
+            easting: (col + 1) as f64 * 100000.0 + e,
+            northing: n2m + n100k + n,
+        })
+    }
+
+    /// 解析UTM，如`31U 448251 5411932`
+    pub fn from_utm_str(s: &str) -> Result<Utm, CoordParseError> {
+        let upper = s.trim().to_ascii_uppercase();
+        let mut parts = upper.split_whitespace();
+        let head = parts
+            .next()
+            .ok_or_else(|| CoordParseError::Invalid(upper.clone()))?;
+        let zone_len = head.chars().take_while(|c| c.is_ascii_digit()).count();
+        let (zone, band) = if zone_len == head.len() {
+            // 带号与纬度带之间有空格
+            (head, parts.next().unwrap_or_default())
+        } else {
+            head.split_at(zone_len)
+        };
+        let zone: u8 = zone
+            .parse()
+            .map_err(|_| CoordParseError::Invalid(upper.clone()))?;
+        let band = band.chars().next().unwrap_or_default();
+        let mut number = || {
+            parts
+                .next()
+                .map(|p| p.trim_end_matches(['M', 'E', 'N']))
+                .and_then(|p| p.parse::<f64>().ok())
+        };
+        match (number(), number(), parts.next()) {
+            (Some(easting), Some(northing), None)
+                if (1..=60).contains(&zone) && LAT_BANDS.contains(&(band as u8)) =>
+            {
+                Ok(Utm {
+                    zone,
+                    band,
+                    easting,
+                    northing,
+                })
+            }
+            _ => Err(CoordParseError::Invalid(upper)),
+        }
+    }
+}
+
+impl Display for Utm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}{} {:.0} {:.0}",
+            self.zone, self.band, self.easting, self.northing
+        )
+    }
+}
+
+/// 返回（第三扁率n，子午线弧长系数A，第一偏心率e）
+fn ellipsoid_series() -> (f64, f64, f64) {
+    let f = WGS84_F;
+    let n = f / (2.0 - f);
+    let big_a =
+        WGS84_A / (1.0 + n) * (1.0 + n.powi(2) / 4.0 + n.powi(4) / 64.0 + n.powi(6) / 256.0);
+    let e = (f * (2.0 - f)).sqrt();
+    (n, big_a, e)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_utm_mgrs() {
+        // 埃菲尔铁塔
+        let eiffel = LatLng {
+            lat: 48.8582,
+            lng: 2.2945,
+        };
+        let utm = Utm::from_lat_lng(eiffel).unwrap();
+        assert_eq!(utm.zone, 31);
+        assert_eq!(utm.band, 'U');
+        assert_eq!(format!("{}", utm), "31U 448252 5411933");
+        let back = utm.to_lat_lng();
+        assert!((back.lat - eiffel.lat).abs() < 1e-9 && (back.lng - eiffel.lng).abs() < 1e-9);
+
+        let mgrs = utm.to_mgrs(5);
+        assert_eq!(mgrs, "31U DQ 48251 11932");
+        let parsed = Utm::from_mgrs(&mgrs).unwrap();
+        assert_eq!(parsed.zone, 31);
+        assert!((parsed.easting - 448251.0).abs() < 1e-6);
+        assert!((parsed.northing - 5411932.0).abs() < 1e-6);
+
+        let utm = Utm::from_utm_str("31 U 448252mE 5411933mN").unwrap();
+        assert!((utm.to_lat_lng().lat - eiffel.lat).abs() < 1e-4);
+
+        // 南半球
+        let sydney = LatLng {
+            lat: -33.8568,
+            lng: 151.2153,
+        };
+        let utm = Utm::from_lat_lng(sydney).unwrap();
+        assert_eq!(utm.zone, 56);
+        assert!(!utm.is_north());
+        let parsed = Utm::from_mgrs(&utm.to_mgrs(5)).unwrap().to_lat_lng();
+        assert!((parsed.lat - sydney.lat).abs() < 1e-4 && (parsed.lng - sydney.lng).abs() < 1e-4);
+    }
+}
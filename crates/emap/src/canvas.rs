@@ -0,0 +1,102 @@
+use std::sync::Arc;
+
+use egui::{
+    epaint::{CircleShape, CornerRadiusF32},
+    Align2, Color32, ColorImage, FontId, Galley, Painter, Pos2, Rect, Shape, Stroke, StrokeKind,
+    TextureHandle, TextureId, TextureOptions,
+};
+
+use crate::tile_drawable::TileQuad;
+
+/// 与渲染后端无关的绘制接口，瓦片、矢量图层与聚合点图层都通过它绘制。
+///
+/// egui的[Painter]直接实现了该接口，离屏绘制见[crate::raster::RasterCanvas]
+pub trait MapCanvas {
+    /// 绘制区域，地图以其左上角为原点
+    fn clip_rect(&self) -> Rect;
+
+    fn add(&self, shape: Shape);
+
+    /// 排版单行文字，用于先计算文字大小再绘制
+    fn layout_no_wrap(&self, text: String, font_id: FontId, color: Color32) -> Arc<Galley>;
+
+    fn text(&self, pos: Pos2, anchor: Align2, text: &str, font_id: FontId, color: Color32) {
+        let galley = self.layout_no_wrap(text.to_owned(), font_id, color);
+        let rect = anchor.anchor_size(pos, galley.size());
+        self.add(Shape::galley(rect.min, galley, color));
+    }
+
+    /// 将纹理中的`uv`区域绘制到`quad`处，无法访问显存的后端不绘制
+    fn texture(&self, texture_id: TextureId, uv: Rect, quad: TileQuad, tint: Color32);
+
+    /// 将内存中图片的`uv`区域绘制到`quad`处
+    fn image(&self, image: &Arc<ColorImage>, uv: Rect, quad: TileQuad, tint: Color32);
+
+    /// 将图片上传为纹理，供需要反复绘制的图片持有，无法访问显存的后端返回None
+    fn load_texture(&self, _image: &ColorImage) -> Option<TextureHandle> {
+        None
+    }
+
+    fn circle(&self, center: Pos2, radius: f32, fill: Color32, stroke: Stroke) {
+        self.add(
+            CircleShape {
+                center,
+                radius,
+                fill,
+                stroke,
+            }
+            .into(),
+        );
+    }
+
+    /// 轴对齐时绘制矩形边框，否则绘制四边形边框
+    fn quad_stroke(&self, quad: TileQuad, stroke: Stroke) {
+        if quad.is_axis_aligned() {
+            self.add(Shape::rect_stroke(
+                quad.bounding_rect(),
+                CornerRadiusF32::same(0.0),
+                stroke,
+                StrokeKind::Inside,
+            ));
+        } else {
+            self.add(quad.outline(stroke));
+        }
+    }
+}
+
+impl MapCanvas for Painter {
+    fn clip_rect(&self) -> Rect {
+        Painter::clip_rect(self)
+    }
+
+    fn add(&self, shape: Shape) {
+        Painter::add(self, shape);
+    }
+
+    fn layout_no_wrap(&self, text: String, font_id: FontId, color: Color32) -> Arc<Galley> {
+        Painter::layout_no_wrap(self, text, font_id, color)
+    }
+
+    /// 轴对齐时直接绘制图片，地图旋转或俯仰时改为绘制网格
+    fn texture(&self, texture_id: TextureId, uv: Rect, quad: TileQuad, tint: Color32) {
+        if quad.is_axis_aligned() {
+            Painter::image(self, texture_id, quad.bounding_rect(), uv, tint);
+        } else {
+            Painter::add(self, quad.textured_mesh(texture_id, uv, tint));
+        }
+    }
+
+    /// 每次绘制都会上传一次纹理，需要反复绘制的图片应通过[MapCanvas::load_texture]持有纹理
+    fn image(&self, image: &Arc<ColorImage>, uv: Rect, quad: TileQuad, tint: Color32) {
+        if let Some(texture) = MapCanvas::load_texture(self, image) {
+            self.texture(texture.id(), uv, quad, tint);
+        }
+    }
+
+    fn load_texture(&self, image: &ColorImage) -> Option<TextureHandle> {
+        Some(
+            self.ctx()
+                .load_texture("emap_canvas_image", image.clone(), TextureOptions::LINEAR),
+        )
+    }
+}
@@ -0,0 +1,227 @@
+use std::collections::HashMap;
+
+use egui::{Align2, Color32, FontId, Pos2, Stroke};
+use rustitude_base::{
+    cluster::{Cluster, PointClusters},
+    map_state::{Location, MapItemData},
+    map_view_state::{MapViewState, TILE_SIZE},
+};
+
+use crate::{
+    canvas::MapCanvas,
+    overlay::ItemStyle,
+    pick::{FeatureInfo, PickSource, PickedFeature},
+};
+
+/// 聚合点图层的绘制样式
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ClusterStyle {
+    /// 单个点的样式
+    pub point: ItemStyle,
+    pub cluster_fill: Color32,
+    pub cluster_stroke: Stroke,
+    pub text_color: Color32,
+    /// 簇的最小半径，点数每增加10倍半径增加[ClusterStyle::radius_step]
+    pub min_radius: f32,
+    pub radius_step: f32,
+    pub max_radius: f32,
+}
+
+impl Default for ClusterStyle {
+    fn default() -> Self {
+        Self {
+            point: ItemStyle::default(),
+            cluster_fill: Color32::from_rgba_unmultiplied(0x33, 0x88, 0xff, 0xcc),
+            cluster_stroke: Stroke::new(3.0, Color32::from_white_alpha(0x99)),
+            text_color: Color32::WHITE,
+            min_radius: 10.0,
+            radius_step: 5.0,
+            max_radius: 30.0,
+        }
+    }
+}
+
+impl ClusterStyle {
+    pub fn radius(&self, count: usize) -> f32 {
+        if count <= 1 {
+            return self.point.point_radius;
+        }
+        (self.min_radius + (count as f32).log10() * self.radius_step).min(self.max_radius)
+    }
+}
+
+/// 大量点的聚合图层，缩小时相近的点合并为显示数量的簇。
+///
+/// 点的坐标为视图坐标系下的[Location]，可以随时增删或移动
+#[derive(Clone)]
+pub struct ClusterLayer {
+    /// 图层名，用于查找图层
+    pub name: String,
+    pub clusters: PointClusters<u64>,
+    /// 点的描述信息，用于拾取后显示
+    pub data: HashMap<u64, MapItemData>,
+    pub style: ClusterStyle,
+    pub visible: bool,
+    /// 点击簇时放大到簇拆开的缩放级别
+    pub expand_on_click: bool,
+}
+
+impl ClusterLayer {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            clusters: PointClusters::default(),
+            data: HashMap::new(),
+            style: ClusterStyle::default(),
+            visible: true,
+            expand_on_click: true,
+        }
+    }
+
+    pub fn with_style(mut self, style: ClusterStyle) -> Self {
+        self.style = style;
+        self
+    }
+
+    /// 插入点，id已存在时移动该点并替换描述信息
+    pub fn insert(&mut self, id: u64, location: Location, data: MapItemData) {
+        self.clusters.insert(id, location);
+        self.data.insert(id, data);
+    }
+
+    /// 只移动点，不改变描述信息
+    pub fn move_to(&mut self, id: u64, location: Location) {
+        if self.clusters.location(id).is_some() {
+            self.clusters.insert(id, location);
+        }
+    }
+
+    pub fn remove(&mut self, id: u64) -> Option<MapItemData> {
+        self.clusters.remove(id);
+        self.data.remove(&id)
+    }
+
+    pub fn clear(&mut self) {
+        self.clusters.clear();
+        self.data.clear();
+    }
+
+    /// 视图内的簇与其中心的视图位置，圆柱投影下每个可见的世界副本各一次
+    fn visible_clusters(&self, mvs: &MapViewState) -> Vec<(Cluster<u64>, Pos2)> {
+        let (lt, rb) = mvs.location_range_in(None);
+        let margin = self.style.max_radius as f64 / (TILE_SIZE * mvs.zoom());
+        let mut result = vec![];
+        for world in mvs.visible_worlds() {
+            let w = world as f64;
+            let world_lt = Location::new(lt.x - w - margin, lt.y - margin);
+            let world_rb = Location::new(rb.x - w + margin, rb.y + margin);
+            if world_rb.x < 0.0 || world_lt.x > 1.0 {
+                continue;
+            }
+            self.clusters
+                .clusters(world_lt, world_rb, mvs.zoom_lvl)
+                .into_iter()
+                .for_each(|c| {
+                    let [x, y] =
+                        mvs.location_to_view_pos(Location::new(c.location.x + w, c.location.y));
+                    result.push((c, Pos2::new(x as f32, y as f32)));
+                });
+        }
+        result
+    }
+}
+
+/// 绘制聚合点图层，簇绘制为带数量的圆
+pub fn emap_draw_clusters(canvas: &dyn MapCanvas, mvs: &MapViewState, layer: &ClusterLayer) {
+    let offset = canvas.clip_rect().min.to_vec2();
+    let style = &layer.style;
+    layer
+        .visible_clusters(mvs)
+        .into_iter()
+        .for_each(|(c, pos)| {
+            let pos = pos + offset;
+            if c.count == 1 {
+                canvas.circle(
+                    pos,
+                    style.point.point_radius,
+                    style.point.fill,
+                    style.point.stroke,
+                );
+            } else {
+                canvas.circle(
+                    pos,
+                    style.radius(c.count),
+                    style.cluster_fill,
+                    style.cluster_stroke,
+                );
+                canvas.text(
+                    pos,
+                    Align2::CENTER_CENTER,
+                    &format_count(c.count),
+                    FontId::proportional(12.0),
+                    style.text_color,
+                );
+            }
+        });
+}
+
+/// 拾取聚合点图层中的点或簇
+pub fn emap_pick_clusters(
+    mvs: &MapViewState,
+    layer: &ClusterLayer,
+    pos: Pos2,
+    tolerance: f32,
+) -> Vec<PickedFeature> {
+    let mut picked: Vec<PickedFeature> = layer
+        .visible_clusters(mvs)
+        .into_iter()
+        .filter_map(|(c, p)| {
+            let distance = (p.distance(pos) - layer.style.radius(c.count)).max(0.0);
+            if distance > tolerance {
+                return None;
+            }
+            let info = match c.id.and_then(|id| layer.data.get(&id)) {
+                Some(data) => {
+                    let mut props: Vec<(String, String)> = data
+                        .props
+                        .iter()
+                        .map(|(k, v)| (k.clone(), v.clone()))
+                        .collect();
+                    props.sort();
+                    FeatureInfo {
+                        id: c.id,
+                        name: data.name.clone(),
+                        props,
+                        distance,
+                    }
+                }
+                None => FeatureInfo {
+                    id: c.id,
+                    name: format_count(c.count),
+                    props: vec![],
+                    distance,
+                },
+            };
+            Some(PickedFeature {
+                source: PickSource::Cluster {
+                    layer: layer.name.clone(),
+                    cluster: c,
+                },
+                info,
+            })
+        })
+        .collect();
+    picked.sort_by(|a, b| a.info.distance.total_cmp(&b.info.distance));
+    picked
+}
+
+/// 簇的数量文字，超过一千时缩写
+fn format_count(count: usize) -> String {
+    if count >= 10_000 {
+        format!("{}k", count / 1000)
+    } else if count >= 1000 {
+        format!("{:.1}k", count as f64 / 1000.0)
+    } else {
+        count.to_string()
+    }
+}
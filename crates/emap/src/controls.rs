@@ -0,0 +1,362 @@
+use std::sync::{Arc, RwLock};
+
+use egui::{
+    vec2, Align2, Color32, CornerRadius, FontId, Pos2, Rect, Response, Sense, Shape, Stroke, Vec2,
+};
+use rustitude_base::{camera::Easing, coord_format::CoordFormat, map_view_state::MapViewState};
+
+use crate::canvas::MapCanvas;
+
+/// 控件停靠的地图角落
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MapCorner {
+    LeftTop,
+    RightTop,
+    LeftBottom,
+    RightBottom,
+}
+
+/// 地图上的内置控件，每个控件可以停靠在任意角落，为None时不显示。
+///
+/// 停靠在同一角落的控件按缩放按钮、指北针、比例尺、鼠标位置、版权信息的顺序从角落向内排列
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MapControls {
+    pub zoom_buttons: Option<MapCorner>,
+    /// 地图旋转后显示，点击后转回正北
+    pub compass: Option<MapCorner>,
+    pub scale_bar: Option<MapCorner>,
+    pub mouse_position: Option<MapCorner>,
+    /// 各图层瓦片源的版权信息
+    pub attribution: Option<MapCorner>,
+    /// 鼠标位置的显示格式
+    pub coord_format: CoordFormat,
+    /// 比例尺的最大像素宽度
+    pub scale_bar_width: f32,
+}
+
+impl Default for MapControls {
+    fn default() -> Self {
+        Self {
+            zoom_buttons: Some(MapCorner::RightTop),
+            compass: Some(MapCorner::RightTop),
+            scale_bar: Some(MapCorner::LeftBottom),
+            mouse_position: Some(MapCorner::LeftBottom),
+            attribution: Some(MapCorner::RightBottom),
+            coord_format: CoordFormat::Decimal(6),
+            scale_bar_width: 100.0,
+        }
+    }
+}
+
+impl MapControls {
+    /// 不显示任何控件
+    pub fn none() -> Self {
+        Self {
+            zoom_buttons: None,
+            compass: None,
+            scale_bar: None,
+            mouse_position: None,
+            attribution: None,
+            ..Default::default()
+        }
+    }
+}
+
+/// 控件与地图边缘的距离
+const CONTROL_MARGIN: f32 = 8.0;
+/// 同一角落中控件之间的距离
+const CONTROL_GAP: f32 = 6.0;
+const CONTROL_PADDING: f32 = 4.0;
+const ZOOM_BUTTON_SIZE: f32 = 28.0;
+pub const COMPASS_SIZE: f32 = 32.0;
+
+fn control_fill() -> Color32 {
+    Color32::from_white_alpha(0xd0)
+}
+
+fn control_text() -> Color32 {
+    Color32::from_gray(0x20)
+}
+
+/// 按角落依次排列控件
+struct CornerLayout {
+    rect: Rect,
+    /// 每个角落已占用的高度
+    used: [f32; 4],
+}
+
+impl CornerLayout {
+    fn place(&mut self, corner: MapCorner, size: Vec2) -> Rect {
+        let used = &mut self.used[corner as usize];
+        let offset = CONTROL_MARGIN + *used;
+        *used += size.y + CONTROL_GAP;
+        let min = match corner {
+            MapCorner::LeftTop => self.rect.left_top() + vec2(CONTROL_MARGIN, offset),
+            MapCorner::RightTop => self.rect.right_top() + vec2(-CONTROL_MARGIN - size.x, offset),
+            MapCorner::LeftBottom => {
+                self.rect.left_bottom() + vec2(CONTROL_MARGIN, -offset - size.y)
+            }
+            MapCorner::RightBottom => {
+                self.rect.right_bottom() + vec2(-CONTROL_MARGIN - size.x, -offset - size.y)
+            }
+        };
+        Rect::from_min_size(min, size)
+    }
+}
+
+/// 绘制地图控件并处理控件的点击，需要在[crate::egui_map::emap_show]之后调用，以便控件优先响应点击。
+///
+/// `response`为地图区域的交互结果，`attributions`为可见图层的版权信息
+pub fn emap_show_controls(
+    ui: &mut egui::Ui,
+    response: &Response,
+    mvs_ref: &Arc<RwLock<MapViewState>>,
+    controls: &MapControls,
+    attributions: &[String],
+) {
+    let rect = response.rect;
+    let painter = ui.painter_at(rect);
+    let mut layout = CornerLayout {
+        rect,
+        used: [0.0; 4],
+    };
+    let mut mvs = mvs_ref.write().unwrap();
+
+    if let Some(corner) = controls.zoom_buttons {
+        let r = layout.place(corner, vec2(ZOOM_BUTTON_SIZE, ZOOM_BUTTON_SIZE * 2.0));
+        let (zoom_in, zoom_out) = r.split_top_bottom_at_fraction(0.5);
+        painter.rect_filled(r, CornerRadius::same(4), control_fill());
+        painter.line_segment(
+            [zoom_out.left_top(), zoom_out.right_top()],
+            Stroke::new(1.0, Color32::from_gray(0xaa)),
+        );
+        for (button, delta, text) in [(zoom_in, 1.0, "+"), (zoom_out, -1.0, "−")] {
+            let id = ui.id().with(("emap_zoom_button", text));
+            let target_zoom = mvs.zoom_lvl + delta;
+            let enabled = mvs.clamp_zoom(target_zoom) != mvs.zoom_lvl;
+            let resp = ui.interact(button, id, Sense::click());
+            if enabled && resp.hovered() {
+                painter.rect_filled(
+                    button.shrink(2.0),
+                    CornerRadius::same(3),
+                    Color32::from_black_alpha(0x18),
+                );
+            }
+            painter.text(
+                button.center(),
+                Align2::CENTER_CENTER,
+                text,
+                FontId::proportional(18.0),
+                if enabled {
+                    control_text()
+                } else {
+                    Color32::from_gray(0xa0)
+                },
+            );
+            if enabled && resp.clicked() {
+                let center = [mvs.view_size[0] / 2.0, mvs.view_size[1] / 2.0];
+                let target = mvs.camera_zoomed_around(delta, center);
+                mvs.ease_to(
+                    target.central,
+                    target.zoom_lvl,
+                    target.bearing,
+                    0.25,
+                    Easing::EaseOut,
+                );
+            }
+        }
+    }
+
+    if let Some(corner) = controls.compass {
+        if mvs.bearing != 0.0 {
+            let r = layout.place(corner, Vec2::splat(COMPASS_SIZE));
+            let id = ui.id().with("emap_compass");
+            if ui.interact(r, id, Sense::click()).clicked() {
+                let (central, zoom_lvl) = (mvs.central, mvs.zoom_lvl);
+                mvs.ease_to(central, zoom_lvl, 0.0, 0.3, Easing::EaseOut);
+            }
+            emap_draw_compass(&painter, r, mvs.bearing);
+        }
+    }
+
+    if let Some(corner) = controls.scale_bar {
+        emap_scale_bar_control(
+            &painter,
+            &mut layout,
+            corner,
+            &mvs,
+            controls.scale_bar_width,
+        );
+    }
+
+    if let Some(corner) = controls.mouse_position {
+        if let Some(p) = response.hover_pos() {
+            let p = p - rect.left_top();
+            let lat_lng = mvs.view_pos_to_lat_lng([p.x as f64, p.y as f64]);
+            emap_text_control(
+                &painter,
+                &mut layout,
+                corner,
+                lat_lng.format(controls.coord_format),
+                FontId::monospace(11.0),
+            );
+        }
+    }
+
+    if let Some(corner) = controls.attribution {
+        if !attributions.is_empty() {
+            emap_text_control(
+                &painter,
+                &mut layout,
+                corner,
+                attributions.join(" | "),
+                FontId::proportional(10.0),
+            );
+        }
+    }
+}
+
+/// 绘制地图控件中不需要交互的部分：指北针、比例尺与版权信息，用于导出图片等离屏绘制
+pub fn emap_draw_static_controls(
+    canvas: &dyn MapCanvas,
+    rect: Rect,
+    mvs: &MapViewState,
+    controls: &MapControls,
+    attributions: &[String],
+) {
+    let mut layout = CornerLayout {
+        rect,
+        used: [0.0; 4],
+    };
+    if let Some(corner) = controls.compass {
+        if mvs.bearing != 0.0 {
+            let r = layout.place(corner, Vec2::splat(COMPASS_SIZE));
+            emap_draw_compass(canvas, r, mvs.bearing);
+        }
+    }
+    if let Some(corner) = controls.scale_bar {
+        emap_scale_bar_control(canvas, &mut layout, corner, mvs, controls.scale_bar_width);
+    }
+    if let Some(corner) = controls.attribution {
+        if !attributions.is_empty() {
+            emap_text_control(
+                canvas,
+                &mut layout,
+                corner,
+                attributions.join(" | "),
+                FontId::proportional(10.0),
+            );
+        }
+    }
+}
+
+fn emap_scale_bar_control(
+    canvas: &dyn MapCanvas,
+    layout: &mut CornerLayout,
+    corner: MapCorner,
+    mvs: &MapViewState,
+    max_width: f32,
+) {
+    let Some((meters, width)) = emap_scale_bar_length(mvs, max_width) else {
+        return;
+    };
+    let galley = canvas.layout_no_wrap(
+        format_distance(meters),
+        FontId::proportional(11.0),
+        control_text(),
+    );
+    let size = vec2(
+        width.max(galley.size().x) + CONTROL_PADDING * 2.0,
+        galley.size().y + 10.0,
+    );
+    let r = layout.place(corner, size);
+    canvas.add(Shape::rect_filled(r, CornerRadius::same(2), control_fill()));
+    let left = r.left() + CONTROL_PADDING;
+    let bottom = r.bottom() - 3.0;
+    canvas.add(Shape::line(
+        vec![
+            Pos2::new(left, bottom - 5.0),
+            Pos2::new(left, bottom),
+            Pos2::new(left + width, bottom),
+            Pos2::new(left + width, bottom - 5.0),
+        ],
+        Stroke::new(1.5, control_text()),
+    ));
+    canvas.add(Shape::galley(
+        Pos2::new(left, r.top() + 1.0),
+        galley,
+        control_text(),
+    ));
+}
+
+fn emap_text_control(
+    canvas: &dyn MapCanvas,
+    layout: &mut CornerLayout,
+    corner: MapCorner,
+    text: String,
+    font: FontId,
+) {
+    let galley = canvas.layout_no_wrap(text, font, control_text());
+    let r = layout.place(corner, galley.size() + Vec2::splat(CONTROL_PADDING * 2.0));
+    canvas.add(Shape::rect_filled(r, CornerRadius::same(2), control_fill()));
+    canvas.add(Shape::galley(
+        r.min + Vec2::splat(CONTROL_PADDING),
+        galley,
+        control_text(),
+    ));
+}
+
+/// 视图中心处比例尺的长度，返回不超过`max_width`像素的最长整数距离（米）与其像素宽度
+pub fn emap_scale_bar_length(mvs: &MapViewState, max_width: f32) -> Option<(f64, f32)> {
+    /// 测量距离时在中心两侧各取的像素数
+    const SAMPLE: f64 = 50.0;
+    let [cx, cy] = [mvs.view_size[0] / 2.0, mvs.view_size[1] / 2.0];
+    let a = mvs.view_pos_to_lat_lng([cx - SAMPLE, cy]);
+    let b = mvs.view_pos_to_lat_lng([cx + SAMPLE, cy]);
+    let meters_per_px = a.distance_to(b) / (SAMPLE * 2.0);
+    if !meters_per_px.is_finite() || meters_per_px <= 0.0 {
+        return None;
+    }
+    let max_meters = meters_per_px * max_width as f64;
+    let unit = 10.0_f64.powf(max_meters.log10().floor());
+    let meters = [5.0, 2.0, 1.0]
+        .into_iter()
+        .map(|k| k * unit)
+        .find(|m| *m <= max_meters)?;
+    Some((meters, (meters / meters_per_px) as f32))
+}
+
+fn format_distance(meters: f64) -> String {
+    if meters >= 1000.0 {
+        format!("{} km", meters / 1000.0)
+    } else if meters >= 1.0 {
+        format!("{} m", meters)
+    } else {
+        format!("{} cm", (meters * 100.0).round())
+    }
+}
+
+/// 绘制指北针，红色一端指向北方，`bearing`为地图旋转角度
+pub fn emap_draw_compass(canvas: &dyn MapCanvas, rect: Rect, bearing: f64) {
+    let c = rect.center();
+    let r = rect.width().min(rect.height()) / 2.0;
+    canvas.circle(
+        c,
+        r,
+        Color32::from_black_alpha(0x99),
+        Stroke::new(1.0, Color32::from_gray(0xcc)),
+    );
+    let (sin, cos) = (-bearing.to_radians() as f32).sin_cos();
+    let north = vec2(sin, -cos) * r * 0.75;
+    let side = vec2(cos, sin) * r * 0.25;
+    canvas.add(Shape::convex_polygon(
+        vec![c + north, c + side, c - side],
+        Color32::from_rgb(0xe5, 0x39, 0x35),
+        Stroke::NONE,
+    ));
+    canvas.add(Shape::convex_polygon(
+        vec![c - north, c - side, c + side],
+        Color32::from_gray(0xee),
+        Stroke::NONE,
+    ));
+}
@@ -0,0 +1,509 @@
+use egui::{
+    vec2, Color32, CursorIcon, Key, KeyboardShortcut, Mesh, Modifiers, Painter, Pos2, Rect,
+    Response, Sense, Shape, Stroke, Vec2,
+};
+use rustitude_base::{
+    edit::{EditShape, ShapeEditor},
+    latlng::LatLng,
+    map_view_state::MapViewState,
+};
+
+use crate::{
+    gestures::MapGestures,
+    overlay::{triangulate, ItemStyle},
+    pick::{distance_to_segment, point_in_polygon},
+};
+
+/// 编辑工具
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DrawTool {
+    /// 选中图形后拖动顶点编辑
+    #[default]
+    Select,
+    Point,
+    /// 单击添加顶点，双击或回车结束
+    Line,
+    Polygon,
+    /// 拖出两个对角
+    Rect,
+    /// 从圆心拖出半径
+    Circle,
+}
+
+impl DrawTool {
+    pub const ALL: [DrawTool; 6] = [
+        DrawTool::Select,
+        DrawTool::Point,
+        DrawTool::Line,
+        DrawTool::Polygon,
+        DrawTool::Rect,
+        DrawTool::Circle,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            DrawTool::Select => "Select",
+            DrawTool::Point => "Point",
+            DrawTool::Line => "Line",
+            DrawTool::Polygon => "Polygon",
+            DrawTool::Rect => "Rect",
+            DrawTool::Circle => "Circle",
+        }
+    }
+}
+
+/// 地图上的图形绘制与编辑工具。
+///
+/// 选中图形后拖动顶点移动，拖动边的中点插入顶点，右键或双击顶点删除；
+/// Ctrl+Z撤销，Ctrl+Shift+Z或Ctrl+Y重做，Delete删除选中的图形，Esc取消绘制
+pub struct MapEditor {
+    pub tool: DrawTool,
+    pub shapes: ShapeEditor,
+    pub selected: Option<usize>,
+    pub style: ItemStyle,
+    /// 选中图形与正在绘制的图形的样式
+    pub selected_style: ItemStyle,
+    /// 控制点的半径，单位为像素
+    pub handle_radius: f32,
+    /// 正在绘制的折线或多边形的顶点
+    drawing: Vec<LatLng>,
+    /// 正在拖出的矩形或圆的起点
+    drag_start: Option<LatLng>,
+    /// 正在拖动的顶点
+    dragging: Option<usize>,
+}
+
+impl Default for MapEditor {
+    fn default() -> Self {
+        Self {
+            tool: DrawTool::default(),
+            shapes: ShapeEditor::new(),
+            selected: None,
+            style: ItemStyle::default(),
+            selected_style: ItemStyle::default()
+                .with_stroke(Stroke::new(2.0, Color32::from_rgb(0xff, 0x88, 0x00)))
+                .with_fill(Color32::from_rgba_unmultiplied(0xff, 0x88, 0x00, 0x40)),
+            handle_radius: 5.0,
+            drawing: vec![],
+            drag_start: None,
+            dragging: None,
+        }
+    }
+}
+
+impl MapEditor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_tool(mut self, tool: DrawTool) -> Self {
+        self.set_tool(tool);
+        self
+    }
+
+    pub fn with_style(mut self, style: ItemStyle) -> Self {
+        self.style = style;
+        self
+    }
+
+    /// 切换工具，会放弃正在绘制的图形
+    pub fn set_tool(&mut self, tool: DrawTool) {
+        if self.tool != tool {
+            self.cancel_drawing();
+            self.tool = tool;
+        }
+    }
+
+    pub fn is_drawing(&self) -> bool {
+        !self.drawing.is_empty() || self.drag_start.is_some()
+    }
+
+    pub fn cancel_drawing(&mut self) {
+        self.drawing.clear();
+        self.drag_start = None;
+    }
+
+    /// 结束正在绘制的折线或多边形，顶点不足时放弃
+    pub fn finish_drawing(&mut self) {
+        let points = std::mem::take(&mut self.drawing);
+        let shape = match self.tool {
+            DrawTool::Line if points.len() >= 2 => EditShape::Line(points),
+            DrawTool::Polygon if points.len() >= 3 => EditShape::Polygon(points),
+            _ => return,
+        };
+        self.selected = Some(self.shapes.push(shape));
+    }
+
+    pub fn undo(&mut self) -> bool {
+        self.cancel_drawing();
+        let undone = self.shapes.undo();
+        self.fix_selected();
+        undone
+    }
+
+    pub fn redo(&mut self) -> bool {
+        self.cancel_drawing();
+        let redone = self.shapes.redo();
+        self.fix_selected();
+        redone
+    }
+
+    pub fn remove_selected(&mut self) -> Option<EditShape> {
+        let removed = self.shapes.remove(self.selected.take()?);
+        self.dragging = None;
+        removed
+    }
+
+    /// 撤销或重做后选中的图形可能已不存在
+    fn fix_selected(&mut self) {
+        self.dragging = None;
+        self.selected = self.selected.filter(|s| *s < self.shapes.len());
+    }
+
+    /// 绘制工具会占用地图的拖动与双击，返回绘制时实际生效的手势
+    pub fn adjust_gestures(&self, mut gestures: MapGestures) -> MapGestures {
+        match self.tool {
+            DrawTool::Select => {}
+            DrawTool::Point | DrawTool::Line | DrawTool::Polygon => {
+                gestures.double_click_zoom = false;
+            }
+            DrawTool::Rect | DrawTool::Circle => {
+                gestures.drag_pan = false;
+                gestures.box_zoom = false;
+                gestures.kinetic = false;
+            }
+        }
+        gestures
+    }
+
+    /// `pos`处最上层的图形
+    fn pick(&self, to_screen: impl Fn(LatLng) -> Pos2, pos: Pos2) -> Option<usize> {
+        let tolerance = self.handle_radius;
+        self.shapes
+            .shapes()
+            .iter()
+            .enumerate()
+            .rev()
+            .find(|(_, shape)| {
+                let points: Vec<Pos2> = shape.outline().into_iter().map(&to_screen).collect();
+                shape_distance(shape, &points, &self.style, pos) <= tolerance
+            })
+            .map(|(i, _)| i)
+    }
+}
+
+/// 处理编辑输入并绘制图形，需要在[crate::egui_map::emap_show]之后调用，以便控制点优先响应拖动。
+///
+/// 绘制工具需要的手势应先用[MapEditor::adjust_gestures]调整
+pub fn emap_show_editor(
+    ui: &mut egui::Ui,
+    response: &Response,
+    mvs: &MapViewState,
+    editor: &mut MapEditor,
+) {
+    let rect = response.rect;
+    let painter = ui.painter_at(rect);
+    let to_screen = |lat_lng: LatLng| {
+        let [x, y] = mvs.lat_lng_to_view_pos(lat_lng);
+        rect.left_top() + vec2(x as f32, y as f32)
+    };
+    let to_lat_lng = |p: Pos2| {
+        let p = p - rect.left_top();
+        mvs.view_pos_to_lat_lng([p.x as f64, p.y as f64])
+    };
+
+    if response.hovered() && !ui.ctx().wants_keyboard_input() {
+        emap_editor_keys(ui, editor);
+    }
+
+    let pointer = ui.input(|i| i.pointer.latest_pos());
+    match editor.tool {
+        DrawTool::Select => {
+            if response.clicked() {
+                editor.selected = response
+                    .interact_pointer_pos()
+                    .and_then(|p| editor.pick(to_screen, p));
+            }
+        }
+        DrawTool::Point => {
+            if let Some(p) = response
+                .clicked()
+                .then(|| response.interact_pointer_pos())
+                .flatten()
+            {
+                editor.selected = Some(editor.shapes.push(EditShape::Point(to_lat_lng(p))));
+            }
+        }
+        DrawTool::Line | DrawTool::Polygon => {
+            //双击的第二次单击不再添加顶点
+            if response.double_clicked() {
+                editor.finish_drawing();
+            } else if let Some(p) = response
+                .clicked()
+                .then(|| response.interact_pointer_pos())
+                .flatten()
+            {
+                editor.drawing.push(to_lat_lng(p));
+            }
+        }
+        DrawTool::Rect | DrawTool::Circle => {
+            if response.drag_started() {
+                editor.drag_start = response.interact_pointer_pos().map(to_lat_lng);
+            }
+            if response.drag_stopped() {
+                if let (Some(start), Some(end)) = (editor.drag_start.take(), pointer) {
+                    let too_small = to_screen(start).distance(end) < editor.handle_radius;
+                    if !too_small {
+                        let shape = drag_shape(editor.tool, start, to_lat_lng(end));
+                        editor.selected = Some(editor.shapes.push(shape));
+                    }
+                }
+            }
+        }
+    }
+
+    emap_draw_editor(&painter, rect.left_top(), mvs, editor);
+
+    //正在绘制的图形，最后一个顶点跟随鼠标
+    let preview = match (editor.tool, editor.drag_start, pointer) {
+        (DrawTool::Line, _, _) | (DrawTool::Polygon, _, _) if !editor.drawing.is_empty() => {
+            let mut points = editor.drawing.clone();
+            points.extend(pointer.filter(|p| rect.contains(*p)).map(to_lat_lng));
+            Some(if editor.tool == DrawTool::Line || points.len() < 3 {
+                EditShape::Line(points)
+            } else {
+                EditShape::Polygon(points)
+            })
+        }
+        (DrawTool::Rect, Some(start), Some(p)) | (DrawTool::Circle, Some(start), Some(p)) => {
+            Some(drag_shape(editor.tool, start, to_lat_lng(p)))
+        }
+        _ => None,
+    };
+    if let Some(shape) = preview {
+        let points: Vec<Pos2> = shape.outline().into_iter().map(to_screen).collect();
+        emap_draw_shape(&painter, &shape, &points, &editor.selected_style);
+        editor.drawing.iter().for_each(|p| {
+            draw_handle(
+                &painter,
+                to_screen(*p),
+                editor.handle_radius,
+                &editor.selected_style,
+            )
+        });
+    }
+
+    if editor.tool == DrawTool::Select {
+        emap_editor_handles(ui, &painter, editor, to_screen, to_lat_lng);
+    }
+}
+
+/// 只绘制编辑器中的图形，不处理输入。`origin`为地图左上角在屏幕上的位置，
+/// 地图被部分裁剪时与`painter`的裁剪区域不同
+pub fn emap_draw_editor(painter: &Painter, origin: Pos2, mvs: &MapViewState, editor: &MapEditor) {
+    let offset = origin.to_vec2();
+    editor
+        .shapes
+        .shapes()
+        .iter()
+        .enumerate()
+        .for_each(|(i, shape)| {
+            let style = if editor.selected == Some(i) {
+                &editor.selected_style
+            } else {
+                &editor.style
+            };
+            let points: Vec<Pos2> = shape
+                .outline()
+                .into_iter()
+                .map(|l| {
+                    let [x, y] = mvs.lat_lng_to_view_pos(l);
+                    Pos2::new(x as f32, y as f32) + offset
+                })
+                .collect();
+            emap_draw_shape(painter, shape, &points, style);
+        });
+}
+
+/// 撤销、重做、删除与取消的快捷键
+fn emap_editor_keys(ui: &mut egui::Ui, editor: &mut MapEditor) {
+    let undo = KeyboardShortcut::new(Modifiers::COMMAND, Key::Z);
+    let redo = KeyboardShortcut::new(Modifiers::COMMAND | Modifiers::SHIFT, Key::Z);
+    let redo_y = KeyboardShortcut::new(Modifiers::COMMAND, Key::Y);
+    //先检查带shift的重做，撤销的快捷键匹配时会忽略shift
+    if ui.input_mut(|i| i.consume_shortcut(&redo) || i.consume_shortcut(&redo_y)) {
+        editor.redo();
+    } else if ui.input_mut(|i| i.consume_shortcut(&undo)) {
+        editor.undo();
+    }
+    let (escape, enter, delete) = ui.input(|i| {
+        (
+            i.key_pressed(Key::Escape),
+            i.key_pressed(Key::Enter),
+            i.key_pressed(Key::Delete) || i.key_pressed(Key::Backspace),
+        )
+    });
+    if escape {
+        if editor.is_drawing() {
+            editor.cancel_drawing();
+        } else {
+            editor.selected = None;
+        }
+    }
+    if enter {
+        editor.finish_drawing();
+    }
+    if delete {
+        if !editor.drawing.is_empty() {
+            editor.drawing.pop();
+        } else if editor.dragging.is_none() {
+            editor.remove_selected();
+        }
+    }
+}
+
+/// 选中图形的顶点与边中点的控制点
+fn emap_editor_handles(
+    ui: &mut egui::Ui,
+    painter: &Painter,
+    editor: &mut MapEditor,
+    to_screen: impl Fn(LatLng) -> Pos2,
+    to_lat_lng: impl Fn(Pos2) -> LatLng,
+) {
+    let Some(selected) = editor.selected else {
+        return;
+    };
+    let Some(shape) = editor.shapes.shapes().get(selected).cloned() else {
+        return;
+    };
+    let vertices: Vec<Pos2> = shape.vertices().into_iter().map(&to_screen).collect();
+    let r = editor.handle_radius;
+    let handle_rect = |p: Pos2| Rect::from_center_size(p, Vec2::splat(r * 2.0 + 4.0));
+
+    if shape.is_editable_path() {
+        let n = vertices.len();
+        let segments = if shape.is_closed() { n } else { n - 1 };
+        for i in 0..segments {
+            let mid = vertices[i].lerp(vertices[(i + 1) % n], 0.5);
+            let id = ui.id().with(("emap_edit_midpoint", selected, i));
+            let resp = ui.interact(handle_rect(mid), id, Sense::click_and_drag());
+            if resp.hovered() {
+                ui.ctx().set_cursor_icon(CursorIcon::Crosshair);
+            }
+            if (resp.drag_started() || resp.clicked())
+                && editor
+                    .shapes
+                    .insert_vertex(selected, i + 1, to_lat_lng(mid))
+            {
+                editor.dragging = resp.drag_started().then_some(i + 1);
+            }
+            painter.circle(
+                mid,
+                r * 0.7,
+                Color32::from_white_alpha(0x99),
+                Stroke::new(1.0, editor.selected_style.stroke.color),
+            );
+        }
+    }
+
+    for (i, pos) in vertices.iter().enumerate() {
+        let id = ui.id().with(("emap_edit_vertex", selected, i));
+        let resp = ui.interact(handle_rect(*pos), id, Sense::click_and_drag());
+        if resp.hovered() {
+            ui.ctx().set_cursor_icon(CursorIcon::Grab);
+        }
+        if resp.drag_started() {
+            editor.shapes.checkpoint();
+            editor.dragging = Some(i);
+        }
+        if resp.secondary_clicked() || resp.double_clicked() {
+            editor.shapes.remove_vertex(selected, i);
+        }
+        draw_handle(painter, *pos, r, &editor.selected_style);
+    }
+
+    if let Some(vertex) = editor.dragging {
+        match ui.input(|i| {
+            i.pointer
+                .primary_down()
+                .then(|| i.pointer.latest_pos())
+                .flatten()
+        }) {
+            Some(p) => {
+                ui.ctx().set_cursor_icon(CursorIcon::Grabbing);
+                editor.shapes.move_vertex(selected, vertex, to_lat_lng(p));
+            }
+            None => editor.dragging = None,
+        }
+    }
+}
+
+/// 拖出的矩形或圆
+fn drag_shape(tool: DrawTool, start: LatLng, end: LatLng) -> EditShape {
+    match tool {
+        DrawTool::Circle => EditShape::Circle {
+            center: start,
+            radius: start.distance_to(end),
+        },
+        _ => EditShape::Rect(start, end),
+    }
+}
+
+fn draw_handle(painter: &Painter, pos: Pos2, radius: f32, style: &ItemStyle) {
+    painter.circle(
+        pos,
+        radius,
+        Color32::WHITE,
+        Stroke::new(1.5, style.stroke.color),
+    );
+}
+
+/// 按样式绘制图形，`points`为图形轮廓在屏幕上的位置
+pub fn emap_draw_shape(painter: &Painter, shape: &EditShape, points: &[Pos2], style: &ItemStyle) {
+    let Some(&first) = points.first() else {
+        return;
+    };
+    match shape {
+        EditShape::Point(_) => {
+            painter.circle(first, style.point_radius, style.fill, style.stroke);
+        }
+        EditShape::Line(_) => {
+            painter.add(Shape::line(points.to_vec(), style.stroke));
+        }
+        _ => {
+            if points.len() >= 3 && style.fill != Color32::TRANSPARENT {
+                let mut mesh = Mesh::default();
+                points
+                    .iter()
+                    .for_each(|p| mesh.colored_vertex(*p, style.fill));
+                mesh.indices = triangulate(points);
+                painter.add(mesh);
+            }
+            painter.add(Shape::closed_line(points.to_vec(), style.stroke));
+        }
+    }
+}
+
+/// `pos`到图形的屏幕距离，位于闭合图形内部时为0
+fn shape_distance(shape: &EditShape, points: &[Pos2], style: &ItemStyle, pos: Pos2) -> f32 {
+    let half_width = style.stroke.width / 2.0;
+    let n = points.len();
+    match shape {
+        EditShape::Point(_) => points.first().map_or(f32::INFINITY, |p| {
+            (p.distance(pos) - style.point_radius).max(0.0)
+        }),
+        _ if shape.is_closed() && n >= 3 && point_in_polygon(pos, points) => 0.0,
+        _ => {
+            let segments = if shape.is_closed() {
+                n
+            } else {
+                n.saturating_sub(1)
+            };
+            ((0..segments)
+                .map(|i| distance_to_segment(pos, points[i], points[(i + 1) % n]))
+                .fold(f32::INFINITY, f32::min)
+                - half_width)
+                .max(0.0)
+        }
+    }
+}
@@ -1,15 +1,23 @@
-use std::sync::{Arc, RwLock};
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
 
 use egui::{
-    load::BytesLoader, vec2, Color32, CornerRadius, InnerResponse, Painter, Pos2, Rect, Sense,
-    Stroke,
-};
-use rustitude_base::{
-    map_state::{walk, Location},
-    map_view_state::{MapViewState, TILE_SIZE},
+    load::BytesLoader, Color32, CornerRadius, Id, InnerResponse, Painter, Rect, Sense, Stroke,
 };
+use rustitude_base::{map_view_state::MapViewState, qtree::QTreeKey};
 
-use crate::{clip_from_top_key, EguiMapTileRes};
+use crate::{
+    clip_from_top_key,
+    cluster::{emap_draw_clusters, ClusterLayer},
+    controls::{emap_show_controls, MapControls},
+    gestures::{emap_handle_gestures, MapGestures},
+    map_widget::MapLayer,
+    overlay::{emap_draw_overlay, MapOverlay},
+    tile_drawable::{TileDrawContext, TileQuad, TileStyle},
+    EguiMapTileRes,
+};
 
 pub trait EguiMap {
     fn egui_map(
@@ -19,78 +27,135 @@ pub trait EguiMap {
         other_res: &Vec<Arc<dyn EguiMapTileRes>>,
         debug: bool,
     ) -> egui::Response {
-        let rect = ui.available_rect_before_wrap();
-        let mvs_ref = self.map_view_state();
-        let mut mvs = mvs_ref.write().unwrap();
-        mvs.view_size[0] = rect.width() as f64;
-        mvs.view_size[1] = rect.height() as f64;
-        let painter = Painter::new(ui.ctx().clone(), ui.layer_id(), rect);
-
-        let ppos = ui.input(|i| i.pointer.latest_pos().map(|pos| pos - rect.left_top()));
-        let scroll = ui.input(|i| i.smooth_scroll_delta);
-        let zoom = ui.input(|i| i.zoom_delta());
-        let click = ui.input(|i| i.pointer.any_click());
-        if let Some(p) = ppos {
-            if zoom != 1.0 {
-                mvs.apply_zoom_delta(zoom.into(), [p.x.into(), p.y.into()]);
-            }
-        }
-        let mvs_zoom = mvs.zoom();
-        let mvs_central = mvs.central;
-        if scroll.x != 0.0 || scroll.y != 0.0 {
-            mvs.set_central(Location::new(
-                mvs_central.x - (scroll.x as f64) / (TILE_SIZE * mvs_zoom),
-                mvs_central.y - (scroll.y as f64) / (TILE_SIZE * mvs_zoom),
-            ));
-        }
-        emap_default_impl_draw_map_tile(ui, &painter, &mut mvs, self.map_view_state(), res, true);
-        other_res.iter().for_each(|res| {
+        let layers: Vec<MapLayer> = std::iter::once(MapLayer::base("", res))
+            .chain(other_res.iter().map(|r| MapLayer::new("", r.clone())))
+            .collect();
+        let mvs = self.map_view_state();
+        let response = emap_show(
+            ui,
+            mvs.clone(),
+            &layers.iter().collect::<Vec<_>>(),
+            &[],
+            &[],
+            &self.map_gestures(),
+            debug,
+        );
+        let attributions: Vec<String> = layers.iter().filter_map(|l| l.res.attribution()).collect();
+        emap_show_controls(ui, &response, &mvs, &self.map_controls(), &attributions);
+        response
+    }
+
+    fn map_view_state(&self) -> Arc<RwLock<MapViewState>>;
+
+    /// 地图响应的手势，默认全部开启
+    fn map_gestures(&self) -> MapGestures {
+        MapGestures::default()
+    }
+
+    /// 地图上显示的内置控件
+    fn map_controls(&self) -> MapControls {
+        MapControls::default()
+    }
+}
+
+/// 绘制地图并处理交互，`layers`按顺序从下到上绘制，之后依次绘制`overlays`与`clusters`。
+///
+/// 地图的交互与淡入状态按`ui`的Id保存，同一个`ui`中显示多个地图时应分别放在不同id_salt的子ui中
+pub fn emap_show(
+    ui: &mut egui::Ui,
+    mvs_ref: Arc<RwLock<MapViewState>>,
+    layers: &[&MapLayer],
+    overlays: &[&MapOverlay],
+    clusters: &[&ClusterLayer],
+    gestures: &MapGestures,
+    debug: bool,
+) -> egui::Response {
+    let rect = ui.available_rect_before_wrap();
+    let mut mvs = mvs_ref.write().unwrap();
+    mvs.view_size[0] = rect.width() as f64;
+    mvs.view_size[1] = rect.height() as f64;
+    let painter = Painter::new(ui.ctx().clone(), ui.layer_id(), rect);
+    //先注册地图区域，之后注册的控件（如指北针）会优先响应点击
+    let response = ui.interact(rect, emap_map_id(ui), Sense::click_and_drag());
+
+    let ppos = ui.input(|i| i.pointer.latest_pos().map(|pos| pos - rect.left_top()));
+    let scroll = ui.input(|i| i.smooth_scroll_delta);
+    let zoom = ui.input(|i| i.zoom_delta());
+    let click = ui.input(|i| i.pointer.any_click());
+    emap_handle_gestures(ui, &painter, &response, &mut mvs, gestures);
+    if mvs.tick(ui.input(|i| i.stable_dt) as f64) {
+        ui.ctx().request_repaint();
+    }
+    layers.iter().for_each(|layer| {
+        let style = layer
+            .res
+            .tile_style()
+            .multiply_opacity(layer.opacity)
+            .at_zoom(mvs.zoom_lvl);
+        //裁剪区域保持地图左上角不变，瓦片的偏移不受影响
+        let painter = match layer.clip {
+            Some(clip) => painter.with_clip_rect(Rect::from_min_size(rect.min, rect.size() * clip)),
+            None => painter.clone(),
+        };
+        //完全透明的图层不绘制，也不加载瓦片
+        if style.opacity > 0.0 {
             emap_default_impl_draw_map_tile(
                 ui,
                 &painter,
-                &mut mvs,
-                self.map_view_state(),
-                res.clone(),
-                false,
+                &mvs,
+                mvs_ref.clone(),
+                layer.res.clone(),
+                layer.is_base,
+                style,
             );
-        });
-        if debug {
-            painter.rect_stroke(
-                rect.shrink(1.0),
-                CornerRadius::ZERO,
-                Stroke::new(1.0, Color32::from_rgb(0xff, 0x11, 0)),
-                egui::StrokeKind::Middle,
-            );
-            ui.vertical(|ui| {
-                ui.label(format!("Rect:{}", rect));
-                ui.label(format!(
-                    "Pointer position:{}",
-                    ppos.map(|pos| format!("{}", pos))
-                        .unwrap_or(String::from("None"))
-                ));
-                ui.label(format!(
-                    "Pointer position:{}",
-                    ppos.map(|pos| format!(
-                        "{}",
-                        mvs.view_pos_to_location([pos.x as f64, pos.y as f64])
-                    ))
-                    .unwrap_or(String::from("None"))
-                ));
-                ui.label(format!("Scroll delta:{}", scroll));
-                ui.label(format!("Zoom delta:{}", zoom));
-                ui.label(format!("Click:{}", click));
-                ui.label("--------------------");
-                emap_debug_mvs(ui, &mvs);
-                ui.label("--------------------");
-                emap_debug_loader_size(ui);
-            });
+        } else {
+            layer.res.unpin_view(response.id);
         }
-        ui.allocate_rect(rect, Sense::click_and_drag())
+    });
+    overlays
+        .iter()
+        .for_each(|overlay| emap_draw_overlay(&painter, &mvs, overlay));
+    clusters
+        .iter()
+        .for_each(|layer| emap_draw_clusters(&painter, &mvs, layer));
+    if debug {
+        painter.rect_stroke(
+            rect.shrink(1.0),
+            CornerRadius::ZERO,
+            Stroke::new(1.0, Color32::from_rgb(0xff, 0x11, 0)),
+            egui::StrokeKind::Middle,
+        );
+        ui.vertical(|ui| {
+            ui.label(format!("Rect:{}", rect));
+            ui.label(format!(
+                "Pointer position:{}",
+                ppos.map(|pos| format!("{}", pos))
+                    .unwrap_or(String::from("None"))
+            ));
+            ui.label(format!(
+                "Pointer position:{}",
+                ppos.map(|pos| format!(
+                    "{}",
+                    mvs.view_pos_to_location([pos.x as f64, pos.y as f64])
+                ))
+                .unwrap_or(String::from("None"))
+            ));
+            ui.label(format!("Scroll delta:{}", scroll));
+            ui.label(format!("Zoom delta:{}", zoom));
+            ui.label(format!("Click:{}", click));
+            ui.label("--------------------");
+            emap_debug_mvs(ui, &mvs);
+            ui.label("--------------------");
+            emap_debug_loader_size(ui);
+            ui.label("--------------------");
+            emap_debug_tile_cache(ui, layers);
+        });
     }
-
-    fn map_view_state(&self) -> Arc<RwLock<MapViewState>>;
+    ui.advance_cursor_after_rect(rect);
+    response
 }
 
+/// 绘制一个图层的瓦片，淡入状态按`ui`所在的地图控件与瓦片资源分别保存
 pub fn emap_default_impl_draw_map_tile(
     ui: &mut egui::Ui,
     painter: &Painter,
@@ -98,40 +163,108 @@ pub fn emap_default_impl_draw_map_tile(
     mvs_ref: Arc<RwLock<MapViewState>>,
     res: Arc<dyn EguiMapTileRes>,
     is_base_tile: bool,
+    style: TileStyle,
 ) {
-    walk(mvs.top_left_key(), mvs.bottom_right_key()).for_each(|k| {
-        let lt = mvs.location_to_view_pos(Location::from_qtree_key(k));
-        let screen_zoom = (TILE_SIZE * 2.0_f64.powf(mvs.zoom_lvl - k.depth() as f64)) as f32;
-        let ltpos = Pos2::new(lt[0] as f32, lt[1] as f32) + painter.clip_rect().min.to_vec2();
-        let this_rect = Rect::from_min_size(ltpos, vec2(screen_zoom, screen_zoom));
-        let mut tile = res.get_or_fetch(k, mvs_ref.clone(), ui.ctx());
-        //tile对应的key
-        let mut tile_key = Some(k);
-        while tile.is_none() && tile_key.is_some() {
-            tile_key = tile_key.unwrap().parent();
-            if let Some(k1) = tile_key {
-                tile = res.get_memory_cache(k1);
-                if let Some(t) = tile {
-                    tile = t.clip(clip_from_top_key(k1, k));
-                }
-            }
+    let wcs = res.wcs();
+    let levels = res.levels();
+    let offset = painter.clip_rect().min.to_vec2();
+    let now = ui.input(|i| i.time);
+    //记录每个瓦片第一次可以绘制的时间，用于淡入
+    let fade_id = tile_fade_id(emap_map_id(ui), &res);
+    let shown: HashMap<u64, f64> = ui.data(|d| d.get_temp(fade_id)).unwrap_or_default();
+    let mut next_shown = HashMap::new();
+    let mut fading = false;
+    let visible_tiles = mvs.visible_tiles(wcs.as_ref(), &levels);
+    res.pin_visible(
+        emap_map_id(ui),
+        &visible_tiles.iter().map(|vt| vt.key).collect::<Vec<_>>(),
+    );
+    visible_tiles.into_iter().for_each(|vt| {
+        let k = vt.key;
+        let tile_ctx =
+            TileDrawContext::new(painter, TileQuad::from_visible_tile(&vt, offset), style);
+        let tile = res.get_or_fetch(k, mvs_ref.clone(), ui.ctx());
+        let alpha = if tile.is_some() {
+            let t0 = shown.get(&k.inner_key()).copied().unwrap_or(now);
+            next_shown.insert(k.inner_key(), t0);
+            ((now - t0) / TILE_FADE_DURATION).clamp(0.0, 1.0) as f32
+        } else {
+            0.0
+        };
+        if alpha < 1.0 {
+            //瓦片未加载或正在淡入时，先绘制已缓存的父瓦片与子瓦片
+            emap_draw_tile_fallback(&tile_ctx, res.as_ref(), k, is_base_tile);
         }
         if let Some(t) = tile {
-            t.draw(&painter, this_rect);
-        } else if is_base_tile {
-            painter.rect_filled(
-                this_rect,
-                CornerRadius::ZERO,
-                Color32::from_rgb(
-                    k.depth() * 8,
-                    0xff - k.depth() * 8,
-                    if (k.x() + k.y()) % 2 == 0 {
-                        k.depth()
-                    } else {
-                        0xff - k.depth()
-                    },
-                ),
-            );
+            if alpha < 1.0 {
+                fading = true;
+                t.draw(&tile_ctx.with_style(style.multiply_opacity(alpha)));
+            } else {
+                t.draw(&tile_ctx);
+            }
+        }
+    });
+    ui.data_mut(|d| d.insert_temp(fade_id, next_shown));
+    if fading {
+        ui.ctx().request_repaint();
+    }
+}
+
+/// 地图控件在`ui`中的Id
+fn emap_map_id(ui: &egui::Ui) -> Id {
+    ui.id().with("emap")
+}
+
+/// 淡入状态的Id，多个地图共用同一个瓦片资源时互不影响
+fn tile_fade_id(map_id: Id, res: &Arc<dyn EguiMapTileRes>) -> Id {
+    map_id.with(("emap_tile_fade", Arc::as_ptr(res) as *const () as usize))
+}
+
+/// 瓦片加载完成后淡入的时长，单位为秒，层级切换时父子瓦片在这段时间内交叉淡入淡出
+pub const TILE_FADE_DURATION: f64 = 0.25;
+
+/// 绘制`key`处的替代瓦片：放大时使用裁剪后的父瓦片，缩小时在其上叠加仍在缓存中的子瓦片
+pub(crate) fn emap_draw_tile_fallback(
+    ctx: &TileDrawContext,
+    res: &dyn EguiMapTileRes,
+    key: QTreeKey,
+    is_base_tile: bool,
+) {
+    let mut tile = None;
+    let mut tile_key = Some(key);
+    while tile.is_none() && tile_key.is_some() {
+        tile_key = tile_key.unwrap().parent();
+        if let Some(k1) = tile_key {
+            tile = res
+                .get_memory_cache(k1)
+                .and_then(|t| t.clip(clip_from_top_key(k1, key)));
+        }
+    }
+    if let Some(t) = tile {
+        t.draw(ctx);
+    } else if is_base_tile {
+        ctx.canvas
+            .add(ctx.quad.filled(ctx.style.color(Color32::from_rgb(
+                key.depth() * 8,
+                0xff - key.depth() * 8,
+                if (key.x() + key.y()).is_multiple_of(2) {
+                    key.depth()
+                } else {
+                    0xff - key.depth()
+                },
+            ))));
+    }
+    [
+        key.child_lt(),
+        key.child_rt(),
+        key.child_lb(),
+        key.child_rb(),
+    ]
+    .into_iter()
+    .flatten()
+    .for_each(|child| {
+        if let Some(t) = res.get_memory_cache(child) {
+            t.draw(&ctx.with_quad(ctx.quad.sub_quad(clip_from_top_key(key, child))));
         }
     });
 }
@@ -140,6 +273,8 @@ pub fn emap_debug_mvs(ui: &mut egui::Ui, mvs: &MapViewState) -> InnerResponse<()
     ui.vertical(|ui| {
         ui.label(format!("Center:{}", mvs.central));
         ui.label(format!("Zoom level:{}", mvs.zoom_lvl as u8));
+        ui.label(format!("Bearing:{:.1}", mvs.bearing));
+        ui.label(format!("Pitch:{:.1}", mvs.pitch));
         ui.label(format!("Top left:{}", mvs.top_left_key()));
         ui.label(format!("Bottom right:{}", mvs.bottom_right_key()));
     })
@@ -183,3 +318,80 @@ pub fn emap_debug_loader_size(ui: &mut egui::Ui) -> InnerResponse<()> {
         ));
     })
 }
+
+/// 各图层内存缓存的命中率与大小
+pub fn emap_debug_tile_cache(ui: &mut egui::Ui, layers: &[&MapLayer]) -> InnerResponse<()> {
+    ui.vertical(|ui| {
+        layers.iter().for_each(|layer| {
+            if let Some(stats) = layer.res.cache_stats() {
+                ui.label(format!(
+                    "{} cache:{} tiles {}/{}MB hit:{:.1}% evicted:{}",
+                    layer.name,
+                    stats.entries,
+                    stats.bytes >> 20,
+                    stats.budget >> 20,
+                    stats.hit_rate() * 100.0,
+                    stats.evictions
+                ));
+            }
+        });
+    })
+}
+
+#[test]
+fn test_shared_res_fade() {
+    use egui::{pos2, vec2, RawInput, UiBuilder};
+    use rustitude_base::map_state::Location;
+
+    //两个地图共用同一个瓦片资源，层级不同因此可见的瓦片不同
+    let res: Arc<dyn EguiMapTileRes> = Arc::new(crate::DebugPrintKeyTileRes);
+    let layer = MapLayer::base("debug", res.clone());
+    let views = [1.0, 3.0].map(|zoom_lvl| {
+        Arc::new(RwLock::new(MapViewState::new(
+            Location::new(0.5, 0.5),
+            [256.0, 256.0],
+            zoom_lvl,
+        )))
+    });
+    let ctx = egui::Context::default();
+    let mut map_ids = vec![];
+    for frame in 0..3 {
+        let input = RawInput {
+            time: Some(frame as f64 * TILE_FADE_DURATION),
+            screen_rect: Some(Rect::from_min_size(pos2(0.0, 0.0), vec2(512.0, 256.0))),
+            ..Default::default()
+        };
+        let _ = ctx.run(input, |ctx| {
+            egui::CentralPanel::default().show(ctx, |ui| {
+                map_ids = views
+                    .iter()
+                    .enumerate()
+                    .map(|(i, mvs)| {
+                        let rect =
+                            Rect::from_min_size(pos2(256.0 * i as f32, 0.0), vec2(256.0, 256.0));
+                        let mut child = ui.new_child(UiBuilder::new().id_salt(i).max_rect(rect));
+                        emap_show(
+                            &mut child,
+                            mvs.clone(),
+                            &[&layer],
+                            &[],
+                            &[],
+                            &MapGestures::default(),
+                            false,
+                        )
+                        .id
+                    })
+                    .collect();
+            });
+        });
+    }
+    //每个地图都保留瓦片第一次绘制的时间，最后一帧完全淡入
+    let now = 2.0 * TILE_FADE_DURATION;
+    map_ids.iter().for_each(|id| {
+        let shown: HashMap<u64, f64> = ctx.data(|d| d.get_temp(tile_fade_id(*id, &res))).unwrap();
+        assert!(!shown.is_empty());
+        assert!(shown
+            .values()
+            .all(|t0| (now - t0) / TILE_FADE_DURATION >= 1.0));
+    });
+}
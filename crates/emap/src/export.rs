@@ -0,0 +1,299 @@
+use std::{
+    f64::consts::PI,
+    fmt::Display,
+    fs,
+    io::Cursor,
+    path::Path,
+    thread::{self, JoinHandle},
+};
+
+use egui::{Color32, ColorImage, FontDefinitions};
+use image::{ImageFormat, ImageResult, RgbaImage};
+use rustitude_base::{geodesy::WGS84_A, map_state::Location, map_view_state::MapViewState};
+
+use crate::{
+    canvas::MapCanvas,
+    cluster::ClusterLayer,
+    controls::{emap_draw_static_controls, MapControls},
+    map_widget::MapLayer,
+    overlay::MapOverlay,
+    raster::{emap_render, RasterCanvas},
+};
+
+/// 导出静态地图的设置。
+///
+/// 导出的图片覆盖屏幕上地图的视野，图片大小与屏幕不同时按比例缩放；
+/// DPI越高文字与线条越精细，瓦片也按导出分辨率选择层级
+#[derive(Clone)]
+pub struct MapExport {
+    /// 导出图片的像素大小
+    pub size: [usize; 2],
+    /// 每英寸像素数，[MapExport::SCREEN_DPI]时文字与线条的像素大小与屏幕上一致
+    pub dpi: f32,
+    /// 绘制在图片上的控件，只会绘制指北针、比例尺与版权信息
+    pub controls: MapControls,
+    pub background: Color32,
+    /// 文字使用的字体，None时使用egui的默认字体，默认字体不包含中文
+    pub fonts: Option<FontDefinitions>,
+}
+
+impl MapExport {
+    pub const SCREEN_DPI: f32 = 96.0;
+
+    pub fn new(size: [usize; 2]) -> Self {
+        Self {
+            size,
+            dpi: Self::SCREEN_DPI,
+            controls: MapControls::default(),
+            background: Color32::WHITE,
+            fonts: None,
+        }
+    }
+
+    /// 以`dpi`导出屏幕上`mvs`的视野，图片大小按DPI放大
+    pub fn of_view(mvs: &MapViewState, dpi: f32) -> Self {
+        let scale = (dpi / Self::SCREEN_DPI) as f64;
+        let [w, h] = mvs.view_size.map(|v| (v * scale).round().max(1.0) as usize);
+        Self::new([w, h]).with_dpi(dpi)
+    }
+
+    pub fn with_dpi(mut self, dpi: f32) -> Self {
+        self.dpi = dpi;
+        self
+    }
+
+    pub fn with_controls(mut self, controls: MapControls) -> Self {
+        self.controls = controls;
+        self
+    }
+
+    pub fn with_background(mut self, background: Color32) -> Self {
+        self.background = background;
+        self
+    }
+
+    pub fn with_fonts(mut self, fonts: FontDefinitions) -> Self {
+        self.fonts = Some(fonts);
+        self
+    }
+
+    pub fn pixels_per_point(&self) -> f32 {
+        self.dpi / Self::SCREEN_DPI
+    }
+
+    /// 导出时使用的视图状态，中心与方向与`mvs`相同，视野完整包含`mvs`的视野
+    pub fn view_state(&self, mvs: &MapViewState) -> MapViewState {
+        let ppp = self.pixels_per_point() as f64;
+        let view_size = self.size.map(|v| v as f64 / ppp);
+        let scale = (view_size[0] / mvs.view_size[0]).min(view_size[1] / mvs.view_size[1]);
+        let zoom_lvl = mvs.zoom_lvl + scale.log2();
+        let mut state = MapViewState::new(mvs.central, view_size, zoom_lvl);
+        state.wcs = mvs.wcs.clone();
+        state.bearing = mvs.bearing;
+        state.pitch = mvs.pitch;
+        state.min_zoom = mvs.min_zoom.min(zoom_lvl);
+        state.max_zoom = mvs.max_zoom.max(zoom_lvl);
+        //每点对应多个像素，瓦片层级相应提高
+        state.level_bias = mvs.level_bias + ppp.log2();
+        state
+    }
+
+    /// 离屏绘制`mvs`的视野，瓦片通过[crate::EguiMapTileRes::load_blocking]获取，
+    /// 可能需要从网络获取瓦片，在UI线程中导出应使用[MapExport::spawn]
+    pub fn render(
+        &self,
+        mvs: &MapViewState,
+        layers: &[&MapLayer],
+        overlays: &[&MapOverlay],
+        clusters: &[&ClusterLayer],
+    ) -> ExportedMap {
+        self.render_state(&self.view_state(mvs), layers, overlays, clusters)
+    }
+
+    /// 在后台线程中导出`mvs`的视野，完成后调用`on_done`。
+    ///
+    /// 视野在调用时确定，图层等需要复制一份传入
+    pub fn spawn(
+        &self,
+        mvs: &MapViewState,
+        layers: Vec<MapLayer>,
+        overlays: Vec<MapOverlay>,
+        clusters: Vec<ClusterLayer>,
+        on_done: impl FnOnce(ExportedMap) + Send + 'static,
+    ) -> JoinHandle<()> {
+        let export = self.clone();
+        let state = self.view_state(mvs);
+        thread::spawn(move || {
+            on_done(export.render_state(
+                &state,
+                &layers.iter().collect::<Vec<_>>(),
+                &overlays.iter().collect::<Vec<_>>(),
+                &clusters.iter().collect::<Vec<_>>(),
+            ))
+        })
+    }
+
+    /// `state`为[MapExport::view_state]的结果
+    fn render_state(
+        &self,
+        state: &MapViewState,
+        layers: &[&MapLayer],
+        overlays: &[&MapOverlay],
+        clusters: &[&ClusterLayer],
+    ) -> ExportedMap {
+        let ppp = self.pixels_per_point();
+        let mut canvas = RasterCanvas::new(self.size, ppp);
+        if let Some(fonts) = &self.fonts {
+            canvas = canvas.with_fonts(fonts.clone());
+        }
+        canvas.fill(self.background);
+        emap_render(&canvas, state, layers, overlays, clusters);
+        let attributions: Vec<String> = layers.iter().filter_map(|l| l.res.attribution()).collect();
+        emap_draw_static_controls(
+            &canvas,
+            canvas.clip_rect(),
+            state,
+            &self.controls,
+            &attributions,
+        );
+        ExportedMap {
+            image: canvas.to_image(),
+            world_file: WorldFile::from_view(state, ppp),
+        }
+    }
+}
+
+/// 导出的地图图片
+pub struct ExportedMap {
+    pub image: ColorImage,
+    /// 俯仰时画面与坐标之间不是仿射变换，没有世界文件
+    pub world_file: Option<WorldFile>,
+}
+
+impl ExportedMap {
+    /// 编码为PNG
+    pub fn to_png(&self) -> ImageResult<Vec<u8>> {
+        let [w, h] = self.image.size;
+        let pixels = self
+            .image
+            .pixels
+            .iter()
+            .flat_map(|c| c.to_srgba_unmultiplied())
+            .collect();
+        let img = RgbaImage::from_raw(w as u32, h as u32, pixels)
+            .expect("pixel count matches image size");
+        let mut png = Cursor::new(vec![]);
+        img.write_to(&mut png, ImageFormat::Png)?;
+        Ok(png.into_inner())
+    }
+
+    /// 保存PNG，有世界文件时在同一目录写入同名的.pgw世界文件与.prj投影文件
+    pub fn save(&self, path: impl AsRef<Path>) -> ImageResult<()> {
+        let path = path.as_ref();
+        fs::write(path, self.to_png()?)?;
+        if let Some(world_file) = &self.world_file {
+            fs::write(path.with_extension("pgw"), world_file.to_string())?;
+            fs::write(path.with_extension("prj"), WorldFile::WEB_MERCATOR_PRJ)?;
+        }
+        Ok(())
+    }
+}
+
+/// 世界文件的六个参数，将像素坐标仿射变换为Web墨卡托（EPSG:3857）坐标，单位为米。
+///
+/// 只支持Web墨卡托投影的视图，GCJ-02等带偏移的坐标系导出的坐标也带有相同的偏移
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WorldFile {
+    /// 每个像素在x方向上的长度
+    pub a: f64,
+    /// 旋转项
+    pub d: f64,
+    pub b: f64,
+    /// 每个像素在y方向上的长度，通常为负数
+    pub e: f64,
+    /// 左上角像素中心的坐标
+    pub c: f64,
+    pub f: f64,
+}
+
+impl WorldFile {
+    /// ESRI格式的Web墨卡托投影定义，写入.prj文件
+    pub const WEB_MERCATOR_PRJ: &str = r#"PROJCS["WGS_1984_Web_Mercator_Auxiliary_Sphere",GEOGCS["GCS_WGS_1984",DATUM["D_WGS_1984",SPHEROID["WGS_1984",6378137.0,298.257223563]],PRIMEM["Greenwich",0.0],UNIT["Degree",0.0174532925199433]],PROJECTION["Mercator_Auxiliary_Sphere"],PARAMETER["False_Easting",0.0],PARAMETER["False_Northing",0.0],PARAMETER["Central_Meridian",0.0],PARAMETER["Standard_Parallel_1",0.0],PARAMETER["Auxiliary_Sphere_Type",0.0],UNIT["Meter",1.0]]"#;
+
+    /// `mvs`绘制到每点`pixels_per_point`像素的图片上时的世界文件，俯仰或不是Web墨卡托投影时为None
+    pub fn from_view(mvs: &MapViewState, pixels_per_point: f32) -> Option<Self> {
+        if mvs.pitch != 0.0 || !mvs.wcs.is_web_mercator() {
+            return None;
+        }
+        let ppp = pixels_per_point as f64;
+        let [w, h] = mvs.view_size;
+        let meters = |[x, y]: [f64; 2]| {
+            let l: Location = mvs.view_pos_to_location([x / ppp, y / ppp]);
+            [
+                (l.x - 0.5) * 2.0 * PI * WGS84_A,
+                (0.5 - l.y) * 2.0 * PI * WGS84_A,
+            ]
+        };
+        //用整幅图片的宽高计算每像素的长度，减小误差
+        let (pw, ph) = (w * ppp, h * ppp);
+        let origin = meters([0.5, 0.5]);
+        let right = meters([pw + 0.5, 0.5]);
+        let down = meters([0.5, ph + 0.5]);
+        Some(Self {
+            a: (right[0] - origin[0]) / pw,
+            d: (right[1] - origin[1]) / pw,
+            b: (down[0] - origin[0]) / ph,
+            e: (down[1] - origin[1]) / ph,
+            c: origin[0],
+            f: origin[1],
+        })
+    }
+
+    /// 像素坐标对应的Web墨卡托坐标
+    pub fn apply(&self, x: f64, y: f64) -> [f64; 2] {
+        [
+            self.a * x + self.b * y + self.c,
+            self.d * x + self.e * y + self.f,
+        ]
+    }
+}
+
+impl Display for WorldFile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        [self.a, self.d, self.b, self.e, self.c, self.f]
+            .iter()
+            .try_for_each(|v| writeln!(f, "{:.10}", v))
+    }
+}
+
+#[test]
+fn test_world_file() {
+    let mut mvs = MapViewState::new(Location::new(0.5, 0.5), [256.0, 256.0], 0.0);
+    mvs.min_zoom = 0.0;
+    //缩放级别0时一个256像素的瓦片覆盖整个世界
+    let wf = WorldFile::from_view(&mvs, 1.0).unwrap();
+    let world = 2.0 * PI * WGS84_A;
+    assert!((wf.a - world / 256.0).abs() < 1e-6);
+    assert!((wf.e + world / 256.0).abs() < 1e-6);
+    assert!(wf.b.abs() < 1e-6 && wf.d.abs() < 1e-6);
+    let [x, y] = wf.apply(127.5, 127.5);
+    assert!(x.abs() < 1e-6 && y.abs() < 1e-6);
+
+    //导出为两倍DPI时视野不变，像素长度减半，瓦片层级加一
+    let export = MapExport::of_view(&mvs, MapExport::SCREEN_DPI * 2.0);
+    assert_eq!(export.size, [512, 512]);
+    let state = export.view_state(&mvs);
+    assert_eq!(state.tile_depth(), mvs.tile_depth() + 1);
+    let wf2 = WorldFile::from_view(&state, export.pixels_per_point()).unwrap();
+    assert!((wf2.a * 2.0 - wf.a).abs() < 1e-6);
+
+    mvs.pitch = 30.0;
+    assert!(WorldFile::from_view(&mvs, 1.0).is_none());
+
+    //等经纬度投影不能用Web墨卡托的世界文件描述
+    mvs.pitch = 0.0;
+    mvs.wcs = std::sync::Arc::new(rustitude_base::crs::Equirectangular);
+    assert!(WorldFile::from_view(&mvs, 1.0).is_none());
+    mvs.wcs = std::sync::Arc::new(rustitude_base::crs::Gcj02Mercator);
+    assert!(WorldFile::from_view(&mvs, 1.0).is_some());
+}
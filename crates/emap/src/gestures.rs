@@ -0,0 +1,247 @@
+use egui::{Color32, Key, Painter, PointerButton, Pos2, Rect, Response, Stroke, Vec2};
+use rustitude_base::{camera::Easing, map_view_state::MapViewState};
+
+/// 地图交互手势配置，每一项都可以单独关闭
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MapGestures {
+    /// 鼠标或单指拖动平移
+    pub drag_pan: bool,
+    /// 触控板或滚轮滚动平移
+    pub scroll_pan: bool,
+    /// 捏合或按住ctrl滚动缩放
+    pub pinch_zoom: bool,
+    /// 双指旋转
+    pub touch_rotate: bool,
+    /// 双击放大，按住shift双击缩小
+    pub double_click_zoom: bool,
+    /// 按住shift拖出矩形，松开后缩放到该范围
+    pub box_zoom: bool,
+    /// 松开拖动后惯性滑动
+    pub kinetic: bool,
+    /// 方向键平移，+/-缩放，仅在鼠标悬停于地图且没有输入框获得焦点时生效
+    pub keyboard: bool,
+    /// 惯性滑动每秒保留的速度比例
+    pub kinetic_decay: f32,
+    /// 方向键每次平移的像素数
+    pub keyboard_pan_step: f32,
+}
+
+impl Default for MapGestures {
+    fn default() -> Self {
+        Self {
+            drag_pan: true,
+            scroll_pan: true,
+            pinch_zoom: true,
+            touch_rotate: true,
+            double_click_zoom: true,
+            box_zoom: true,
+            kinetic: true,
+            keyboard: true,
+            kinetic_decay: 0.02,
+            keyboard_pan_step: 100.0,
+        }
+    }
+}
+
+impl MapGestures {
+    /// 关闭所有手势，地图只能通过代码控制
+    pub fn disabled() -> Self {
+        Self {
+            drag_pan: false,
+            scroll_pan: false,
+            pinch_zoom: false,
+            touch_rotate: false,
+            double_click_zoom: false,
+            box_zoom: false,
+            kinetic: false,
+            keyboard: false,
+            ..Default::default()
+        }
+    }
+}
+
+/// 跨帧保存的手势状态
+#[derive(Clone, Copy, Default)]
+struct GestureState {
+    /// 惯性滑动速度，单位为像素每秒
+    velocity: Vec2,
+    /// 框选缩放的起点
+    box_start: Option<Pos2>,
+}
+
+/// 惯性滑动速度低于该值时停止
+const MIN_KINETIC_SPEED: f32 = 20.0;
+/// 双击、键盘缩放与框选缩放的动画时长
+const ZOOM_DURATION: f64 = 0.25;
+
+/// 处理地图上的手势输入，`response`为地图区域的交互结果。
+///
+/// 有任何用户输入时会中断正在进行的相机动画。
+pub fn emap_handle_gestures(
+    ui: &egui::Ui,
+    painter: &Painter,
+    response: &Response,
+    mvs: &mut MapViewState,
+    gestures: &MapGestures,
+) {
+    let rect = response.rect;
+    let id = response.id.with("emap_gestures");
+    let mut state: GestureState = ui.data(|d| d.get_temp(id)).unwrap_or_default();
+    let to_view = |p: Pos2| {
+        let p = p - rect.left_top();
+        [p.x as f64, p.y as f64]
+    };
+
+    let hovered = response.hovered();
+    let dt = ui.input(|i| i.stable_dt);
+    let scroll = ui.input(|i| i.smooth_scroll_delta);
+    let zoom = ui.input(|i| i.zoom_delta());
+    let touch = ui.input(|i| i.multi_touch());
+    let shift = ui.input(|i| i.modifiers.shift);
+    let keyboard = gestures.keyboard && hovered && !ui.ctx().wants_keyboard_input();
+    let key_input = keyboard
+        && ui.input(|i| {
+            [
+                Key::ArrowLeft,
+                Key::ArrowRight,
+                Key::ArrowUp,
+                Key::ArrowDown,
+                Key::Plus,
+                Key::Equals,
+                Key::Minus,
+            ]
+            .iter()
+            .any(|k| i.key_pressed(*k))
+        });
+    if response.is_pointer_button_down_on()
+        || touch.is_some()
+        || key_input
+        || (hovered && (zoom != 1.0 || scroll != Vec2::ZERO))
+    {
+        mvs.stop_animation();
+        state.velocity = Vec2::ZERO;
+    }
+
+    if hovered {
+        if let Some(p) = response.hover_pos() {
+            if gestures.pinch_zoom && zoom != 1.0 {
+                mvs.apply_zoom_delta(zoom.into(), to_view(p));
+            }
+        }
+        if gestures.scroll_pan && scroll != Vec2::ZERO {
+            mvs.pan_by([scroll.x.into(), scroll.y.into()]);
+        }
+    }
+
+    //双指旋转，手指顺时针转动时地图随之顺时针转动，方位角减小
+    if let Some(touch) = touch {
+        if gestures.touch_rotate && touch.rotation_delta != 0.0 {
+            mvs.rotate_around(
+                -(touch.rotation_delta as f64).to_degrees(),
+                to_view(touch.center_pos),
+            );
+        }
+    }
+
+    if response.drag_started_by(PointerButton::Primary) && gestures.box_zoom && shift {
+        state.box_start = response.interact_pointer_pos();
+    }
+    if let Some(start) = state.box_start {
+        let end = response.interact_pointer_pos().unwrap_or(start);
+        let selection = Rect::from_two_pos(start, end);
+        if response.drag_stopped() {
+            state.box_start = None;
+            if selection.width() > 4.0 && selection.height() > 4.0 {
+                let fit =
+                    (rect.width() / selection.width()).min(rect.height() / selection.height());
+                let central = mvs.view_pos_to_location(to_view(selection.center()));
+                let camera = mvs.camera();
+                mvs.ease_to(
+                    central,
+                    mvs.clamp_zoom(camera.zoom_lvl + (fit as f64).log2()),
+                    camera.bearing,
+                    ZOOM_DURATION,
+                    Easing::EaseOut,
+                );
+            }
+        } else {
+            painter.rect(
+                selection,
+                0.0,
+                Color32::from_rgba_unmultiplied(0x33, 0x88, 0xff, 0x33),
+                Stroke::new(1.0, Color32::from_rgb(0x33, 0x88, 0xff)),
+                egui::StrokeKind::Inside,
+            );
+        }
+    } else if gestures.drag_pan && touch.is_none() && response.dragged_by(PointerButton::Primary) {
+        let delta = response.drag_delta();
+        mvs.pan_by([delta.x.into(), delta.y.into()]);
+    } else if response.drag_stopped_by(PointerButton::Primary) && gestures.kinetic {
+        state.velocity = ui.input(|i| i.pointer.velocity());
+    }
+
+    if state.velocity.length() > MIN_KINETIC_SPEED && !response.dragged() {
+        let delta = state.velocity * dt;
+        mvs.pan_by([delta.x.into(), delta.y.into()]);
+        state.velocity *= gestures.kinetic_decay.powf(dt);
+        ui.ctx().request_repaint();
+    } else {
+        state.velocity = Vec2::ZERO;
+    }
+
+    if gestures.double_click_zoom && response.double_clicked_by(PointerButton::Primary) {
+        if let Some(p) = response.interact_pointer_pos() {
+            let delta = if shift { -1.0 } else { 1.0 };
+            let target = mvs.camera_zoomed_around(delta, to_view(p));
+            mvs.ease_to(
+                target.central,
+                target.zoom_lvl,
+                target.bearing,
+                ZOOM_DURATION,
+                Easing::EaseOut,
+            );
+        }
+    }
+
+    if key_input {
+        let step = gestures.keyboard_pan_step;
+        let (pan, zoom_delta) = ui.input(|i| {
+            let mut pan = Vec2::ZERO;
+            if i.key_pressed(Key::ArrowLeft) {
+                pan.x += step;
+            }
+            if i.key_pressed(Key::ArrowRight) {
+                pan.x -= step;
+            }
+            if i.key_pressed(Key::ArrowUp) {
+                pan.y += step;
+            }
+            if i.key_pressed(Key::ArrowDown) {
+                pan.y -= step;
+            }
+            let zoom_delta = if i.key_pressed(Key::Plus) || i.key_pressed(Key::Equals) {
+                1.0
+            } else if i.key_pressed(Key::Minus) {
+                -1.0
+            } else {
+                0.0
+            };
+            (pan, zoom_delta)
+        });
+        if pan != Vec2::ZERO {
+            mvs.pan_by([pan.x.into(), pan.y.into()]);
+        }
+        if zoom_delta != 0.0 {
+            let target = mvs.camera_zoomed_around(zoom_delta, to_view(rect.center()));
+            mvs.ease_to(
+                target.central,
+                target.zoom_lvl,
+                target.bearing,
+                ZOOM_DURATION,
+                Easing::EaseOut,
+            );
+        }
+    }
+
+    ui.data_mut(|d| d.insert_temp(id, state));
+}
@@ -1,4 +1,19 @@
+pub mod canvas;
+pub mod cluster;
+pub mod controls;
+pub mod editor;
 pub mod egui_map;
+#[cfg(feature = "export")]
+pub mod export;
+pub mod gestures;
+pub mod map_widget;
+pub mod measure;
+pub mod minimap;
+pub mod overlay;
+pub mod pick;
+pub mod raster;
+pub mod swipe;
+pub mod sync;
 pub mod tile_drawable;
 
 use std::{
@@ -6,11 +21,16 @@ use std::{
     sync::{Arc, RwLock},
 };
 
-use egui::{pos2, vec2, Context, Rect};
-use rustitude_base::{map_view_state::MapViewState, qtree::QTreeKey};
-use tile_drawable::CommonEguiTileDrawable;
+use egui::{pos2, vec2, Context, Id, Rect};
+use rustitude_base::{
+    latlng::CommonWCS,
+    map_view_state::{MapViewState, TileLevels},
+    qtree::QTreeKey,
+};
+use tile_drawable::{CommonEguiTileDrawable, TileStyle};
 
-pub trait EguiMapTileRes {
+/// 瓦片资源，需要能在加载线程与后台导出线程中使用
+pub trait EguiMapTileRes: Send + Sync {
     /// 获取内存缓存，没有就算了
     fn get_memory_cache(&self, key: QTreeKey) -> Option<CommonEguiTileDrawable>;
 
@@ -21,6 +41,75 @@ pub trait EguiMapTileRes {
         mvs: Arc<RwLock<MapViewState>>,
         ctx: &Context,
     ) -> Option<CommonEguiTileDrawable>;
+
+    /// 瓦片所在的坐标系，None表示与视图的坐标系相同
+    fn wcs(&self) -> Option<CommonWCS> {
+        None
+    }
+
+    /// 瓦片源提供的瓦片层级与显示的缩放级别范围
+    fn levels(&self) -> TileLevels {
+        TileLevels::default()
+    }
+
+    /// 瓦片的绘制样式，会与图层的不透明度叠加
+    fn tile_style(&self) -> TileStyle {
+        TileStyle::default()
+    }
+
+    /// 瓦片源的版权信息，显示在地图角落
+    fn attribution(&self) -> Option<String> {
+        None
+    }
+
+    /// 不依赖egui同步获取瓦片，用于离屏绘制，返回值与`keys`一一对应。
+    /// 只返回[tile_drawable::EguiTileDrawable::is_cpu_drawable]的瓦片，纹理瓦片会被跳过。
+    ///
+    /// 默认只取内存缓存，内存缓存中是纹理瓦片时应重写该方法
+    fn load_blocking(&self, keys: &[QTreeKey]) -> Vec<Option<CommonEguiTileDrawable>> {
+        keys.iter()
+            .map(|k| self.get_memory_cache(*k).filter(|t| t.is_cpu_drawable()))
+            .collect()
+    }
+
+    /// 每帧绘制前传入地图控件`view`中可见的瓦片，内存缓存淘汰时保留它们及其祖先。
+    /// 共享资源的多个视图各自固定，互不覆盖。默认忽略
+    fn pin_visible(&self, _view: Id, _keys: &[QTreeKey]) {}
+
+    /// 取消地图控件`view`的固定，视图关闭或图层不再绘制时调用。默认忽略
+    fn unpin_view(&self, _view: Id) {}
+
+    /// 内存缓存的统计信息，显示在调试面板中。默认没有
+    fn cache_stats(&self) -> Option<TileCacheStats> {
+        None
+    }
+}
+
+/// 瓦片内存缓存的统计信息
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TileCacheStats {
+    /// 绘制时在内存缓存中找到瓦片的次数
+    pub hits: u64,
+    pub misses: u64,
+    /// 因超出大小上限被淘汰的瓦片数
+    pub evictions: u64,
+    pub entries: usize,
+    /// 估算的缓存大小，单位为字节
+    pub bytes: usize,
+    /// 缓存大小上限，单位为字节
+    pub budget: usize,
+}
+
+impl TileCacheStats {
+    /// 命中率，没有访问时为0
+    pub fn hit_rate(&self) -> f64 {
+        let total = self.hits + self.misses;
+        if total == 0 {
+            0.0
+        } else {
+            self.hits as f64 / total as f64
+        }
+    }
 }
 
 pub struct DebugPrintKeyTileRes;
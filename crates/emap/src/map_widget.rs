@@ -0,0 +1,663 @@
+use std::{
+    hash::Hash,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, RwLock, RwLockReadGuard, RwLockWriteGuard,
+    },
+};
+
+use egui::{Area, Frame, Id, Order, Pos2, Response, UiBuilder, Widget};
+use rustitude_base::{
+    camera::Easing, latlng::LatLng, map_state::Location, map_view_state::MapViewState,
+};
+
+use crate::{
+    cluster::ClusterLayer,
+    controls::{emap_show_controls, MapControls},
+    editor::{emap_draw_editor, emap_show_editor, DrawTool, MapEditor},
+    egui_map::emap_show,
+    gestures::MapGestures,
+    measure::{emap_show_measure, MeasureTool},
+    minimap::Minimap,
+    overlay::MapOverlay,
+    pick::{emap_feature_ui, emap_pick, PickSource, PickedFeature},
+    swipe::{emap_show_swipe, SwipeCompare},
+    sync::CameraSync,
+    EguiMapTileRes,
+};
+
+/// 地图中的一个图层
+#[derive(Clone)]
+pub struct MapLayer {
+    /// 图层名，用于查找图层
+    pub name: String,
+    pub res: Arc<dyn EguiMapTileRes>,
+    /// 不透明度，0为完全透明
+    pub opacity: f32,
+    pub visible: bool,
+    /// 绘制顺序，值大的图层绘制在上方，相同时按添加顺序
+    pub z_index: i32,
+    /// 底图在瓦片缺失时绘制占位色块
+    pub is_base: bool,
+    /// 只绘制地图区域左上角宽高分别为该比例的部分，用于卷帘对比
+    pub clip: Option<egui::Vec2>,
+}
+
+impl MapLayer {
+    pub fn new(name: impl Into<String>, res: Arc<dyn EguiMapTileRes>) -> Self {
+        Self {
+            name: name.into(),
+            res,
+            opacity: 1.0,
+            visible: true,
+            z_index: 0,
+            is_base: false,
+            clip: None,
+        }
+    }
+
+    /// 底图图层，z_index最低
+    pub fn base(name: impl Into<String>, res: Arc<dyn EguiMapTileRes>) -> Self {
+        Self {
+            z_index: i32::MIN,
+            is_base: true,
+            ..Self::new(name, res)
+        }
+    }
+
+    pub fn with_opacity(mut self, opacity: f32) -> Self {
+        self.opacity = opacity.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn with_z_index(mut self, z_index: i32) -> Self {
+        self.z_index = z_index;
+        self
+    }
+
+    pub fn with_visible(mut self, visible: bool) -> Self {
+        self.visible = visible;
+        self
+    }
+}
+
+/// 地图上的一个位置
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MapPos {
+    /// 相对于地图左上角的视图位置
+    pub view_pos: Pos2,
+    /// 视图坐标系下的位置，圆柱投影下x已绕回[0,1)
+    pub location: Location,
+    pub lat_lng: LatLng,
+}
+
+impl MapPos {
+    fn at(mvs: &MapViewState, view_pos: Pos2) -> Self {
+        let location = mvs.view_pos_to_location([view_pos.x as f64, view_pos.y as f64]);
+        Self {
+            view_pos,
+            location: if mvs.wcs.wraps_x() {
+                location.wrap_x()
+            } else {
+                location
+            },
+            lat_lng: mvs.view_pos_to_lat_lng([view_pos.x as f64, view_pos.y as f64]),
+        }
+    }
+}
+
+/// [MapWidget::show]的返回值
+pub struct MapResponse {
+    pub response: Response,
+    /// 鼠标悬停处的位置
+    pub hovered: Option<MapPos>,
+    /// 本帧点击处的位置
+    pub clicked: Option<MapPos>,
+    /// 本帧右键点击处的位置
+    pub secondary_clicked: Option<MapPos>,
+    /// 鼠标悬停处的要素，拖动地图时为空
+    pub hovered_features: Vec<PickedFeature>,
+    /// 本帧点击处的要素
+    pub clicked_features: Vec<PickedFeature>,
+}
+
+/// 自带视图状态与图层列表的地图控件。
+///
+/// ```ignore
+/// let mut map = MapWidget::new(MapViewState::new(Location::new(0.5, 0.5), [800.0, 600.0], 2.0))
+///     .with_layer(MapLayer::base("img", img_res));
+/// // 每帧
+/// let resp = map.show(ui);
+/// // 或者 ui.add(&mut map);
+/// ```
+pub struct MapWidget {
+    /// 地图在所在`ui`中的Id，交互、瓦片淡入与内存缓存的固定都按它区分。默认每个控件各不相同
+    pub id_salt: Id,
+    state: Arc<RwLock<MapViewState>>,
+    pub layers: Vec<MapLayer>,
+    /// 矢量图层，绘制在所有瓦片图层之上
+    pub overlays: Vec<MapOverlay>,
+    /// 聚合点图层，绘制在矢量图层之上
+    pub clusters: Vec<ClusterLayer>,
+    pub gestures: MapGestures,
+    pub controls: MapControls,
+    pub debug: bool,
+    /// 拾取要素的像素容差
+    pub pick_tolerance: f32,
+    /// 悬停在要素上时显示属性提示
+    pub feature_tooltip: bool,
+    /// 点击要素后在该位置弹出属性窗口，点击空白处关闭
+    pub feature_popup: bool,
+    /// 图形绘制与编辑工具，绘制在所有图层之上
+    pub editor: Option<MapEditor>,
+    /// 测量工具，不为None时单击用于添加测量点，编辑器只绘制不响应输入
+    pub measure: Option<MeasureTool>,
+    /// 停靠在角落的鹰眼图
+    pub minimap: Option<Minimap>,
+    /// 与其他地图同步相机
+    pub sync: Option<CameraSync>,
+    /// 卷帘对比，不为None时每帧按它设置各图层的[MapLayer::clip]，改为None后清除各图层的裁剪
+    pub swipe: Option<SwipeCompare>,
+    /// 上一帧是否按卷帘设置了裁剪
+    swipe_clipped: bool,
+    /// 当前弹窗所在的位置与要素
+    popup: Option<(Location, Vec<PickedFeature>)>,
+    /// 上一次显示时地图的Id，释放时用于取消图层对瓦片的固定
+    map_id: Option<Id>,
+}
+
+impl MapWidget {
+    pub fn new(state: MapViewState) -> Self {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+        Self {
+            id_salt: Id::new(("emap_map_widget", NEXT_ID.fetch_add(1, Ordering::Relaxed))),
+            state: Arc::new(RwLock::new(state)),
+            layers: vec![],
+            overlays: vec![],
+            clusters: vec![],
+            gestures: MapGestures::default(),
+            controls: MapControls::default(),
+            debug: false,
+            pick_tolerance: 4.0,
+            feature_tooltip: true,
+            feature_popup: true,
+            editor: None,
+            measure: None,
+            minimap: None,
+            sync: None,
+            swipe: None,
+            swipe_clipped: false,
+            popup: None,
+            map_id: None,
+        }
+    }
+
+    pub fn with_layer(mut self, layer: MapLayer) -> Self {
+        self.layers.push(layer);
+        self
+    }
+
+    pub fn with_overlay(mut self, overlay: MapOverlay) -> Self {
+        self.overlays.push(overlay);
+        self
+    }
+
+    pub fn with_cluster_layer(mut self, layer: ClusterLayer) -> Self {
+        self.clusters.push(layer);
+        self
+    }
+
+    /// 使用固定的Id，重新创建控件后仍保留交互状态
+    pub fn with_id_salt(mut self, id_salt: impl Hash) -> Self {
+        self.id_salt = Id::new(id_salt);
+        self
+    }
+
+    pub fn with_gestures(mut self, gestures: MapGestures) -> Self {
+        self.gestures = gestures;
+        self
+    }
+
+    pub fn with_controls(mut self, controls: MapControls) -> Self {
+        self.controls = controls;
+        self
+    }
+
+    pub fn with_minimap(mut self, minimap: Minimap) -> Self {
+        self.minimap = Some(minimap);
+        self
+    }
+
+    pub fn with_sync(mut self, sync: CameraSync) -> Self {
+        self.sync = Some(sync);
+        self
+    }
+
+    pub fn with_swipe(mut self, swipe: SwipeCompare) -> Self {
+        self.swipe = Some(swipe);
+        self
+    }
+
+    pub fn with_editor(mut self, editor: MapEditor) -> Self {
+        self.editor = Some(editor);
+        self
+    }
+
+    pub fn add_layer(&mut self, layer: MapLayer) {
+        self.layers.push(layer);
+    }
+
+    pub fn remove_layer(&mut self, name: &str) -> Option<MapLayer> {
+        let index = self.layers.iter().position(|l| l.name == name)?;
+        Some(self.layers.remove(index))
+    }
+
+    pub fn layer(&self, name: &str) -> Option<&MapLayer> {
+        self.layers.iter().find(|l| l.name == name)
+    }
+
+    pub fn layer_mut(&mut self, name: &str) -> Option<&mut MapLayer> {
+        self.layers.iter_mut().find(|l| l.name == name)
+    }
+
+    pub fn add_overlay(&mut self, overlay: MapOverlay) {
+        self.overlays.push(overlay);
+    }
+
+    pub fn remove_overlay(&mut self, name: &str) -> Option<MapOverlay> {
+        let index = self.overlays.iter().position(|o| o.name == name)?;
+        Some(self.overlays.remove(index))
+    }
+
+    pub fn overlay_mut(&mut self, name: &str) -> Option<&mut MapOverlay> {
+        self.overlays.iter_mut().find(|o| o.name == name)
+    }
+
+    pub fn cluster_layer(&self, name: &str) -> Option<&ClusterLayer> {
+        self.clusters.iter().find(|c| c.name == name)
+    }
+
+    pub fn cluster_layer_mut(&mut self, name: &str) -> Option<&mut ClusterLayer> {
+        self.clusters.iter_mut().find(|c| c.name == name)
+    }
+
+    pub fn state(&self) -> RwLockReadGuard<'_, MapViewState> {
+        self.state.read().unwrap()
+    }
+
+    pub fn state_mut(&self) -> RwLockWriteGuard<'_, MapViewState> {
+        self.state.write().unwrap()
+    }
+
+    /// 视图状态的共享引用，瓦片加载任务通过它判断瓦片是否仍然可见
+    pub fn shared_state(&self) -> Arc<RwLock<MapViewState>> {
+        self.state.clone()
+    }
+
+    /// 拾取`view_pos`处的要素，`view_pos`为相对于地图左上角的视图位置，上层的要素排在前面
+    pub fn pick(&self, view_pos: Pos2, tolerance: f32) -> Vec<PickedFeature> {
+        let overlays: Vec<&MapOverlay> = self.overlays.iter().filter(|o| o.visible).collect();
+        let clusters: Vec<&ClusterLayer> = self.clusters.iter().filter(|c| c.visible).collect();
+        emap_pick(
+            &self.state(),
+            &Self::sorted_layers(&self.layers),
+            &overlays,
+            &clusters,
+            view_pos,
+            tolerance,
+        )
+    }
+
+    /// 导出当前视野的静态地图，只包含可见的图层
+    #[cfg(feature = "export")]
+    pub fn export(&self, export: &crate::export::MapExport) -> crate::export::ExportedMap {
+        let overlays: Vec<&MapOverlay> = self.overlays.iter().filter(|o| o.visible).collect();
+        let clusters: Vec<&ClusterLayer> = self.clusters.iter().filter(|c| c.visible).collect();
+        export.render(
+            &self.state(),
+            &Self::sorted_layers(&self.layers),
+            &overlays,
+            &clusters,
+        )
+    }
+
+    /// 在后台线程中导出当前视野，完成后调用`on_done`，见[crate::export::MapExport::spawn]
+    #[cfg(feature = "export")]
+    pub fn export_in_background(
+        &self,
+        export: &crate::export::MapExport,
+        on_done: impl FnOnce(crate::export::ExportedMap) + Send + 'static,
+    ) -> std::thread::JoinHandle<()> {
+        export.spawn(
+            &self.state(),
+            Self::sorted_layers(&self.layers)
+                .into_iter()
+                .cloned()
+                .collect(),
+            self.overlays
+                .iter()
+                .filter(|o| o.visible)
+                .cloned()
+                .collect(),
+            self.clusters
+                .iter()
+                .filter(|c| c.visible)
+                .cloned()
+                .collect(),
+            on_done,
+        )
+    }
+
+    pub fn close_popup(&mut self) {
+        self.popup = None;
+    }
+
+    /// 按z_index排序后的可见图层
+    fn sorted_layers(layers: &[MapLayer]) -> Vec<&MapLayer> {
+        let mut layers: Vec<&MapLayer> = layers
+            .iter()
+            .filter(|l| l.visible && l.opacity > 0.0)
+            .collect();
+        layers.sort_by_key(|l| l.z_index);
+        layers
+    }
+
+    /// 在`ui`的剩余区域中显示地图，地图及鹰眼图等附属控件位于按[Self::id_salt]区分的子ui中
+    pub fn show(&mut self, ui: &mut egui::Ui) -> MapResponse {
+        ui.scope_builder(UiBuilder::new().id_salt(self.id_salt), |ui| {
+            self.show_map(ui)
+        })
+        .inner
+    }
+
+    fn show_map(&mut self, ui: &mut egui::Ui) -> MapResponse {
+        if let Some(sync) = self.sync.as_mut() {
+            sync.pull(&mut self.state.write().unwrap());
+        }
+        if let Some(swipe) = &self.swipe {
+            let clip = swipe.clip();
+            self.layers
+                .iter_mut()
+                .for_each(|l| l.clip = (l.name == swipe.layer).then_some(clip));
+            self.swipe_clipped = true;
+        } else if self.swipe_clipped {
+            self.layers.iter_mut().for_each(|l| l.clip = None);
+            self.swipe_clipped = false;
+        }
+        let layers = Self::sorted_layers(&self.layers);
+        let overlays: Vec<&MapOverlay> = self.overlays.iter().filter(|o| o.visible).collect();
+        let clusters: Vec<&ClusterLayer> = self.clusters.iter().filter(|c| c.visible).collect();
+        let gestures = match (&self.measure, &self.editor) {
+            (Some(_), _) => MapGestures {
+                double_click_zoom: false,
+                ..self.gestures
+            },
+            (None, Some(editor)) => editor.adjust_gestures(self.gestures),
+            (None, None) => self.gestures,
+        };
+        let mut response = emap_show(
+            ui,
+            self.state.clone(),
+            &layers,
+            &overlays,
+            &clusters,
+            &gestures,
+            self.debug,
+        );
+        //隐藏的图层不再固定瓦片
+        self.map_id = Some(response.id);
+        self.layers
+            .iter()
+            .filter(|l| !l.visible || l.opacity <= 0.0)
+            .for_each(|l| l.res.unpin_view(response.id));
+        match (self.measure.as_mut(), self.editor.as_mut()) {
+            (Some(measure), editor) => {
+                let mvs = self.state.read().unwrap();
+                if let Some(editor) = editor {
+                    let painter = ui.painter_at(response.rect);
+                    emap_draw_editor(&painter, response.rect.left_top(), &mvs, editor);
+                }
+                emap_show_measure(ui, &response, &mvs, measure);
+            }
+            (None, Some(editor)) => {
+                emap_show_editor(ui, &response, &self.state.read().unwrap(), editor)
+            }
+            (None, None) => {}
+        }
+        let attributions: Vec<String> = layers.iter().filter_map(|l| l.res.attribution()).collect();
+        if let Some(swipe) = self.swipe.as_mut() {
+            emap_show_swipe(ui, response.rect, swipe);
+        }
+        if let Some(minimap) = self.minimap.as_mut() {
+            minimap.show_in(ui, response.rect, &self.state);
+        }
+        emap_show_controls(ui, &response, &self.state, &self.controls, &attributions);
+        let rect = response.rect;
+        let to_view = |p: Pos2| (p - rect.left_top()).to_pos2();
+        let click_pos = response
+            .clicked()
+            .then(|| response.interact_pointer_pos())
+            .flatten();
+        let secondary_pos = response
+            .secondary_clicked()
+            .then(|| response.interact_pointer_pos())
+            .flatten();
+        if let Some(sync) = self.sync.as_mut() {
+            sync.push(&self.state.read().unwrap());
+        }
+        let mvs = self.state();
+        let pos_at = |p: Option<Pos2>| p.map(|p| MapPos::at(&mvs, to_view(p)));
+        let pick_at = |p: Option<Pos2>| {
+            p.map(|p| {
+                emap_pick(
+                    &mvs,
+                    &layers,
+                    &overlays,
+                    &clusters,
+                    to_view(p),
+                    self.pick_tolerance,
+                )
+            })
+            .unwrap_or_default()
+        };
+        let hovered = pos_at(response.hover_pos());
+        let clicked = pos_at(click_pos);
+        let secondary_clicked = pos_at(secondary_pos);
+        let hovered_features = if response.dragged() {
+            vec![]
+        } else {
+            pick_at(response.hover_pos())
+        };
+        let clicked_features = pick_at(click_pos);
+        drop(mvs);
+        if self.feature_tooltip && !hovered_features.is_empty() {
+            response = response.on_hover_ui_at_pointer(|ui| emap_feature_ui(ui, &hovered_features));
+        }
+        //绘制图形或测量时点击只用于添加顶点
+        let drawing = self.measure.is_some()
+            || self
+                .editor
+                .as_ref()
+                .is_some_and(|e| e.tool != DrawTool::Select);
+        let expanded = !drawing && self.expand_cluster(clicked_features.first());
+        if self.feature_popup && !expanded && !drawing {
+            if let Some(pos) = clicked {
+                self.popup = (!clicked_features.is_empty())
+                    .then(|| (pos.location, clicked_features.clone()));
+            }
+            self.show_popup(ui, rect);
+        }
+        MapResponse {
+            response,
+            hovered,
+            clicked,
+            secondary_clicked,
+            hovered_features,
+            clicked_features,
+        }
+    }
+
+    /// 点击的是可以展开的簇时放大到簇拆开的缩放级别
+    fn expand_cluster(&mut self, feature: Option<&PickedFeature>) -> bool {
+        let Some(PickSource::Cluster { layer, cluster }) = feature.map(|f| &f.source) else {
+            return false;
+        };
+        let Some(layer) = self.cluster_layer(layer) else {
+            return false;
+        };
+        if cluster.count <= 1 || !layer.expand_on_click {
+            return false;
+        }
+        let Some(zoom_lvl) = layer.clusters.expansion_zoom(cluster.key) else {
+            return false;
+        };
+        let mut mvs = self.state_mut();
+        let camera = mvs.camera();
+        //簇位于[0,1)内，换算到离当前视图中心最近的世界副本
+        let central = mvs.nearest_copy(cluster.location);
+        let zoom_lvl = mvs.clamp_zoom(zoom_lvl.max(camera.zoom_lvl + 1.0));
+        mvs.ease_to(central, zoom_lvl, camera.bearing, 0.3, Easing::EaseOut);
+        drop(mvs);
+        self.popup = None;
+        true
+    }
+
+    /// 在弹窗对应的地图位置绘制弹窗，位置移出地图范围时暂不显示
+    fn show_popup(&mut self, ui: &egui::Ui, rect: egui::Rect) {
+        let Some((location, features)) = &self.popup else {
+            return;
+        };
+        let mvs = self.state();
+        //弹窗位置位于[0,1)内，换算到离当前视图中心最近的世界副本
+        let [x, y] = mvs.location_to_view_pos(mvs.nearest_copy(*location));
+        drop(mvs);
+        let pos = rect.left_top() + egui::vec2(x as f32, y as f32);
+        if !rect.contains(pos) {
+            return;
+        }
+        let mut close = false;
+        Area::new(ui.id().with("emap_popup"))
+            .order(Order::Foreground)
+            .fixed_pos(pos)
+            .constrain_to(rect)
+            .show(ui.ctx(), |ui| {
+                Frame::popup(ui.style()).show(ui, |ui| {
+                    ui.set_max_width(240.0);
+                    emap_feature_ui(ui, features);
+                    close = ui.small_button("×").clicked();
+                });
+            });
+        if close {
+            self.popup = None;
+        }
+    }
+}
+
+impl Drop for MapWidget {
+    fn drop(&mut self) {
+        if let Some(id) = self.map_id {
+            self.layers.iter().for_each(|l| l.res.unpin_view(id));
+        }
+    }
+}
+
+impl Widget for &mut MapWidget {
+    fn ui(self, ui: &mut egui::Ui) -> Response {
+        self.show(ui).response
+    }
+}
+
+#[test]
+fn test_map_widget_id() {
+    use egui::{pos2, vec2, RawInput, Rect};
+
+    //同一个ui中的两个地图不共用交互与淡入状态
+    let new_map = || {
+        MapWidget::new(MapViewState::new(
+            Location::new(0.5, 0.5),
+            [256.0, 256.0],
+            1.0,
+        ))
+    };
+    let (mut a, mut b) = (new_map(), new_map());
+    let mut c = new_map().with_id_salt("c");
+    let ctx = egui::Context::default();
+    let mut ids = vec![];
+    let input = RawInput {
+        screen_rect: Some(Rect::from_min_size(pos2(0.0, 0.0), vec2(512.0, 256.0))),
+        ..Default::default()
+    };
+    let _ = ctx.run(input, |ctx| {
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ids = [&mut a, &mut b, &mut c]
+                .map(|m| m.show(ui).response.id)
+                .to_vec();
+        });
+    });
+    assert_ne!(ids[0], ids[1]);
+    //重新创建时使用相同的id_salt得到相同的Id
+    assert_eq!(c.id_salt, new_map().with_id_salt("c").id_salt);
+}
+
+#[test]
+fn test_unpin_view() {
+    use crate::tile_drawable::CommonEguiTileDrawable;
+    use egui::{pos2, vec2, Context, RawInput, Rect};
+    use rustitude_base::qtree::QTreeKey;
+    use std::{collections::HashSet, sync::Mutex};
+
+    //记录固定了瓦片的视图
+    #[derive(Default)]
+    struct PinRes(Mutex<HashSet<Id>>);
+    impl EguiMapTileRes for PinRes {
+        fn get_memory_cache(&self, key: QTreeKey) -> Option<CommonEguiTileDrawable> {
+            Some(Arc::new(key))
+        }
+        fn get_or_fetch(
+            &self,
+            key: QTreeKey,
+            _mvs: Arc<RwLock<MapViewState>>,
+            _ctx: &Context,
+        ) -> Option<CommonEguiTileDrawable> {
+            Some(Arc::new(key))
+        }
+        fn pin_visible(&self, view: Id, _keys: &[QTreeKey]) {
+            self.0.lock().unwrap().insert(view);
+        }
+        fn unpin_view(&self, view: Id) {
+            self.0.lock().unwrap().remove(&view);
+        }
+    }
+
+    let res = Arc::new(PinRes::default());
+    let mut map = MapWidget::new(MapViewState::new(
+        Location::new(0.5, 0.5),
+        [256.0, 256.0],
+        1.0,
+    ))
+    .with_layer(MapLayer::base("pin", res.clone()));
+    let ctx = Context::default();
+    let show = |map: &mut MapWidget| {
+        let input = RawInput {
+            screen_rect: Some(Rect::from_min_size(pos2(0.0, 0.0), vec2(256.0, 256.0))),
+            ..Default::default()
+        };
+        let _ = ctx.run(input, |ctx| {
+            egui::CentralPanel::default().show(ctx, |ui| {
+                map.show(ui);
+            });
+        });
+    };
+    show(&mut map);
+    assert_eq!(res.0.lock().unwrap().len(), 1);
+    //隐藏图层后取消固定
+    map.layers[0].visible = false;
+    show(&mut map);
+    assert!(res.0.lock().unwrap().is_empty());
+    //地图释放后取消固定
+    map.layers[0].visible = true;
+    show(&mut map);
+    assert_eq!(res.0.lock().unwrap().len(), 1);
+    drop(map);
+    assert!(res.0.lock().unwrap().is_empty());
+}
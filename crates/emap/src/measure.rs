@@ -0,0 +1,219 @@
+use egui::{
+    vec2, Align2, Color32, CornerRadius, FontId, Key, Mesh, Painter, Pos2, Response, Shape, Stroke,
+    StrokeKind, Vec2,
+};
+use rustitude_base::{
+    geodesy::{path_length, polygon_area, UnitSystem},
+    latlng::LatLng,
+    map_view_state::MapViewState,
+};
+
+use crate::overlay::triangulate;
+
+/// 测距与测面积工具。
+///
+/// 依次单击添加测量点，每段旁显示距离与方位角，最后一个点旁显示总距离与面积；
+/// 双击结束本次测量，之后单击开始新的测量。Backspace删除最后一个点，Esc清除
+#[derive(Clone, Debug)]
+pub struct MeasureTool {
+    pub points: Vec<LatLng>,
+    /// 首尾闭合测量面积
+    pub area: bool,
+    pub units: UnitSystem,
+    /// 每段旁显示起点处的方位角
+    pub show_bearing: bool,
+    pub stroke: Stroke,
+    pub fill: Color32,
+    /// 本次测量已结束
+    finished: bool,
+}
+
+impl Default for MeasureTool {
+    fn default() -> Self {
+        Self {
+            points: vec![],
+            area: false,
+            units: UnitSystem::default(),
+            show_bearing: true,
+            stroke: Stroke::new(2.0, Color32::from_rgb(0xe5, 0x39, 0x35)),
+            fill: Color32::from_rgba_unmultiplied(0xe5, 0x39, 0x35, 0x30),
+            finished: false,
+        }
+    }
+}
+
+impl MeasureTool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_units(mut self, units: UnitSystem) -> Self {
+        self.units = units;
+        self
+    }
+
+    pub fn with_area(mut self, area: bool) -> Self {
+        self.area = area;
+        self
+    }
+
+    pub fn clear(&mut self) {
+        self.points.clear();
+        self.finished = false;
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// 总距离（米），测面积时包含闭合的一段
+    pub fn total_distance(&self) -> f64 {
+        measure_distance(&self.points, self.area)
+    }
+
+    /// 面积（平方米），不测面积或点数不足时为0
+    pub fn polygon_area(&self) -> f64 {
+        if self.area {
+            polygon_area(&self.points)
+        } else {
+            0.0
+        }
+    }
+}
+
+fn measure_distance(points: &[LatLng], closed: bool) -> f64 {
+    let closing = match (closed && points.len() >= 3, points.first(), points.last()) {
+        (true, Some(first), Some(last)) => last.distance_to(*first),
+        _ => 0.0,
+    };
+    path_length(points) + closing
+}
+
+/// 处理测量输入并绘制测量结果，需要在[crate::egui_map::emap_show]之后调用。
+///
+/// 测量时地图的双击缩放应关闭
+pub fn emap_show_measure(
+    ui: &mut egui::Ui,
+    response: &Response,
+    mvs: &MapViewState,
+    tool: &mut MeasureTool,
+) {
+    let rect = response.rect;
+    let painter = ui.painter_at(rect);
+    let to_screen = |lat_lng: LatLng| {
+        let [x, y] = mvs.lat_lng_to_view_pos(lat_lng);
+        rect.left_top() + vec2(x as f32, y as f32)
+    };
+    let to_lat_lng = |p: Pos2| {
+        let p = p - rect.left_top();
+        mvs.view_pos_to_lat_lng([p.x as f64, p.y as f64])
+    };
+
+    if response.hovered() && !ui.ctx().wants_keyboard_input() {
+        let (escape, delete) = ui.input(|i| {
+            (
+                i.key_pressed(Key::Escape),
+                i.key_pressed(Key::Backspace) || i.key_pressed(Key::Delete),
+            )
+        });
+        if escape {
+            tool.clear();
+        }
+        if delete {
+            tool.points.pop();
+            tool.finished = false;
+        }
+    }
+    //双击的第二次单击不再添加测量点
+    if response.double_clicked() {
+        tool.finished = !tool.points.is_empty();
+    } else if let Some(p) = response
+        .clicked()
+        .then(|| response.interact_pointer_pos())
+        .flatten()
+    {
+        if tool.finished {
+            tool.clear();
+        }
+        tool.points.push(to_lat_lng(p));
+    }
+
+    //未结束时最后一个点跟随鼠标
+    let mut points = tool.points.clone();
+    if !tool.finished && !points.is_empty() {
+        points.extend(response.hover_pos().map(to_lat_lng));
+    }
+    let Some(&last) = points.last() else {
+        return;
+    };
+    let screen: Vec<Pos2> = points.iter().map(|p| to_screen(*p)).collect();
+    let closed = tool.area && points.len() >= 3;
+    if closed {
+        let mut mesh = Mesh::default();
+        screen
+            .iter()
+            .for_each(|p| mesh.colored_vertex(*p, tool.fill));
+        mesh.indices = triangulate(&screen);
+        painter.add(mesh);
+        painter.add(Shape::closed_line(screen.clone(), tool.stroke));
+    } else {
+        painter.add(Shape::line(screen.clone(), tool.stroke));
+    }
+    screen.iter().for_each(|p| {
+        painter.circle(*p, 3.5, Color32::WHITE, tool.stroke);
+    });
+
+    let mut segments: Vec<(LatLng, LatLng)> = points.windows(2).map(|w| (w[0], w[1])).collect();
+    if closed {
+        segments.push((last, points[0]));
+    }
+    if segments.len() > 1 || closed {
+        segments.iter().for_each(|(a, b)| {
+            let mut text = tool.units.format_distance(a.distance_to(*b));
+            if tool.show_bearing {
+                text = format!("{}  {:.1}°", text, a.initial_bearing_to(*b));
+            }
+            let mid = to_screen(*a).lerp(to_screen(*b), 0.5);
+            draw_label(&painter, mid, Align2::CENTER_CENTER, text, false);
+        });
+    }
+    let mut text = tool
+        .units
+        .format_distance(measure_distance(&points, tool.area));
+    if segments.len() == 1 && !closed && tool.show_bearing {
+        let (a, b) = segments[0];
+        text = format!("{}  {:.1}°", text, a.initial_bearing_to(b));
+    }
+    if closed {
+        text = format!(
+            "{}\n{}",
+            text,
+            tool.units.format_area(polygon_area(&points))
+        );
+    }
+    draw_label(
+        &painter,
+        to_screen(last) + vec2(8.0, 8.0),
+        Align2::LEFT_TOP,
+        text,
+        true,
+    );
+}
+
+/// 带背景的测量标签，`strong`用于总距离与面积
+fn draw_label(painter: &Painter, pos: Pos2, align: Align2, text: String, strong: bool) {
+    let font = FontId::proportional(if strong { 13.0 } else { 11.0 });
+    let galley = painter.layout_no_wrap(text, font, Color32::from_gray(0x20));
+    let size = galley.size() + Vec2::splat(6.0);
+    let r = align.anchor_size(pos, size);
+    painter.rect_filled(r, CornerRadius::same(3), Color32::from_white_alpha(0xe0));
+    if strong {
+        painter.rect_stroke(
+            r,
+            CornerRadius::same(3),
+            Stroke::new(1.0, Color32::from_gray(0x99)),
+            StrokeKind::Inside,
+        );
+    }
+    painter.galley(r.min + Vec2::splat(3.0), galley, Color32::from_gray(0x20));
+}
@@ -0,0 +1,194 @@
+use std::sync::{Arc, RwLock};
+
+use egui::{
+    vec2, Color32, CornerRadius, Id, Pos2, Rect, Response, Sense, Shape, Stroke, StrokeKind,
+};
+use rustitude_base::{
+    camera::Easing,
+    map_state::Location,
+    map_view_state::{MapViewState, TILE_SIZE},
+};
+
+use crate::{
+    controls::MapCorner, egui_map::emap_show, gestures::MapGestures, map_widget::MapLayer,
+};
+
+/// 鹰眼图，以固定的缩放级别差跟随主地图，并用矩形标出主地图的视野。
+///
+/// 图层可以直接使用主地图的[MapLayer]，瓦片源与内存缓存是共享的，不会重复下载。
+/// 拖动鹰眼图平移主地图，单击将主地图移动到该位置
+pub struct Minimap {
+    state: Arc<RwLock<MapViewState>>,
+    pub layers: Vec<MapLayer>,
+    /// 相对于主地图的缩放级别差，通常为负数
+    pub zoom_offset: f64,
+    pub size: egui::Vec2,
+    /// 作为[crate::map_widget::MapWidget]的一部分显示时停靠的角落
+    pub corner: MapCorner,
+    pub viewport_stroke: Stroke,
+    pub viewport_fill: Color32,
+    /// 上一次显示时地图的Id，释放时用于取消图层对瓦片的固定
+    map_id: Option<Id>,
+}
+
+impl Minimap {
+    pub fn new(layers: Vec<MapLayer>) -> Self {
+        let mut state = MapViewState::new(Location::new(0.5, 0.5), [200.0, 150.0], 0.0);
+        state.min_zoom = 0.0;
+        Self {
+            state: Arc::new(RwLock::new(state)),
+            layers,
+            zoom_offset: -4.0,
+            size: vec2(200.0, 150.0),
+            corner: MapCorner::LeftTop,
+            viewport_stroke: Stroke::new(1.5, Color32::from_rgb(0xe5, 0x39, 0x35)),
+            viewport_fill: Color32::from_rgba_unmultiplied(0xe5, 0x39, 0x35, 0x20),
+            map_id: None,
+        }
+    }
+
+    pub fn with_zoom_offset(mut self, zoom_offset: f64) -> Self {
+        self.zoom_offset = zoom_offset;
+        self
+    }
+
+    pub fn with_size(mut self, size: egui::Vec2) -> Self {
+        self.size = size;
+        self
+    }
+
+    pub fn with_corner(mut self, corner: MapCorner) -> Self {
+        self.corner = corner;
+        self
+    }
+
+    /// 鹰眼图自身的视图状态
+    pub fn state(&self) -> Arc<RwLock<MapViewState>> {
+        self.state.clone()
+    }
+
+    /// 在`map_rect`的角落显示鹰眼图，`map_rect`为主地图的区域
+    pub fn show_in(
+        &mut self,
+        ui: &mut egui::Ui,
+        map_rect: Rect,
+        main: &Arc<RwLock<MapViewState>>,
+    ) -> Response {
+        /// 与地图边缘的距离
+        const MARGIN: f32 = 8.0;
+        let min = match self.corner {
+            MapCorner::LeftTop => map_rect.left_top() + vec2(MARGIN, MARGIN),
+            MapCorner::RightTop => map_rect.right_top() + vec2(-MARGIN - self.size.x, MARGIN),
+            MapCorner::LeftBottom => map_rect.left_bottom() + vec2(MARGIN, -MARGIN - self.size.y),
+            MapCorner::RightBottom => map_rect.right_bottom() - self.size - vec2(MARGIN, MARGIN),
+        };
+        let rect = Rect::from_min_size(min, self.size);
+        let mut child = ui.new_child(
+            egui::UiBuilder::new()
+                .id_salt("emap_minimap")
+                .max_rect(rect),
+        );
+        self.show(&mut child, main)
+    }
+
+    /// 在`ui`的剩余区域中显示鹰眼图，`main`为主地图的视图状态
+    pub fn show(&mut self, ui: &mut egui::Ui, main: &Arc<RwLock<MapViewState>>) -> Response {
+        //主地图视野的四个角，旋转与俯仰时为任意四边形
+        let viewport: Vec<Location> = {
+            let main = main.read().unwrap();
+            let mut mvs = self.state.write().unwrap();
+            mvs.wcs = main.wcs.clone();
+            mvs.central = main.central;
+            mvs.bearing = 0.0;
+            mvs.pitch = 0.0;
+            mvs.zoom_lvl = mvs.clamp_zoom(main.zoom_lvl + self.zoom_offset);
+            let [w, h] = main.view_size;
+            [[0.0, 0.0], [w, 0.0], [w, h], [0.0, h]]
+                .into_iter()
+                .map(|p| main.view_pos_to_location(p))
+                .collect()
+        };
+        let layers: Vec<&MapLayer> = self
+            .layers
+            .iter()
+            .filter(|l| l.visible && l.opacity > 0.0)
+            .collect();
+        let map_response = emap_show(
+            ui,
+            self.state.clone(),
+            &layers,
+            &[],
+            &[],
+            &MapGestures::disabled(),
+            false,
+        );
+        self.map_id = Some(map_response.id);
+        self.layers
+            .iter()
+            .filter(|l| !l.visible || l.opacity <= 0.0)
+            .for_each(|l| l.res.unpin_view(map_response.id));
+        let rect = map_response.rect;
+        let painter = ui.painter_at(rect);
+        let mvs = self.state.read().unwrap();
+
+        let viewport: Vec<Pos2> = viewport
+            .into_iter()
+            .map(|l| {
+                let [x, y] = mvs.location_to_view_pos(l);
+                rect.left_top() + vec2(x as f32, y as f32)
+            })
+            .collect();
+        painter.add(Shape::convex_polygon(
+            viewport,
+            self.viewport_fill,
+            self.viewport_stroke,
+        ));
+        painter.rect_stroke(
+            rect,
+            CornerRadius::ZERO,
+            Stroke::new(1.0, Color32::from_gray(0x88)),
+            StrokeKind::Inside,
+        );
+
+        let response = ui.interact(
+            rect,
+            ui.id().with("emap_minimap_drag"),
+            Sense::click_and_drag(),
+        );
+        let scale = TILE_SIZE * mvs.zoom();
+        drop(mvs);
+        if response.dragged() {
+            let delta = response.drag_delta();
+            let mut main = main.write().unwrap();
+            main.stop_animation();
+            let central = main.central;
+            main.set_central(Location::new(
+                central.x + delta.x as f64 / scale,
+                central.y + delta.y as f64 / scale,
+            ));
+        } else if let Some(p) = response
+            .clicked()
+            .then(|| response.interact_pointer_pos())
+            .flatten()
+        {
+            let p = p - rect.left_top();
+            let target = self
+                .state
+                .read()
+                .unwrap()
+                .view_pos_to_location([p.x as f64, p.y as f64]);
+            let mut main = main.write().unwrap();
+            let (zoom_lvl, bearing) = (main.zoom_lvl, main.bearing);
+            main.ease_to(target, zoom_lvl, bearing, 0.3, Easing::EaseOut);
+        }
+        response
+    }
+}
+
+impl Drop for Minimap {
+    fn drop(&mut self) {
+        if let Some(id) = self.map_id {
+            self.layers.iter().for_each(|l| l.res.unpin_view(id));
+        }
+    }
+}
@@ -0,0 +1,361 @@
+use std::sync::{Arc, Mutex};
+
+use egui::{vec2, Align2, Color32, FontId, Mesh, Pos2, Shape, Stroke};
+use rustitude_base::{
+    latlng::CommonWCS,
+    map_state::{simplify_locations, Location, MapItem},
+    map_view_state::{MapViewState, TILE_SIZE},
+};
+
+use crate::canvas::MapCanvas;
+
+/// 矢量要素的绘制样式
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ItemStyle {
+    /// 线、多边形边框与点的描边
+    pub stroke: Stroke,
+    /// 多边形与点的填充色
+    pub fill: Color32,
+    /// 点的半径，单位为像素
+    pub point_radius: f32,
+    /// 是否在要素旁绘制名称
+    pub show_label: bool,
+}
+
+impl Default for ItemStyle {
+    fn default() -> Self {
+        Self {
+            stroke: Stroke::new(2.0, Color32::from_rgb(0x33, 0x88, 0xff)),
+            fill: Color32::from_rgba_unmultiplied(0x33, 0x88, 0xff, 0x40),
+            point_radius: 5.0,
+            show_label: false,
+        }
+    }
+}
+
+impl ItemStyle {
+    pub fn with_stroke(mut self, stroke: impl Into<Stroke>) -> Self {
+        self.stroke = stroke.into();
+        self
+    }
+
+    pub fn with_fill(mut self, fill: Color32) -> Self {
+        self.fill = fill;
+        self
+    }
+
+    pub fn with_point_radius(mut self, radius: f32) -> Self {
+        self.point_radius = radius;
+        self
+    }
+
+    pub fn with_label(mut self, show_label: bool) -> Self {
+        self.show_label = show_label;
+        self
+    }
+}
+
+#[derive(Clone)]
+pub struct OverlayItem {
+    pub item: Arc<MapItem>,
+    pub style: ItemStyle,
+    /// 上一次使用的抽稀与三角化结果，替换`item`后重新计算
+    geometry: Arc<Mutex<Option<Arc<ItemGeometry>>>>,
+}
+
+impl OverlayItem {
+    pub fn new(item: impl Into<Arc<MapItem>>, style: ItemStyle) -> Self {
+        Self {
+            item: item.into(),
+            style,
+            geometry: Arc::default(),
+        }
+    }
+
+    /// 按整数缩放级别`zoom`抽稀后的几何，同一级别内复用
+    fn geometry(&self, zoom: i32, tolerance: f64) -> Arc<ItemGeometry> {
+        let mut cached = self.geometry.lock().unwrap();
+        if let Some(g) = cached.as_ref().filter(|g| {
+            g.zoom == zoom && g.tolerance == tolerance && Arc::ptr_eq(&g.item, &self.item)
+        }) {
+            return g.clone();
+        }
+        let locations = simplify_locations(self.item.locations(), tolerance);
+        //视图变换保持顶点的环绕顺序，在Location空间三角化的结果可以直接用于屏幕上的顶点
+        let triangles = match self.item.as_ref() {
+            MapItem::Polygon { .. } if locations.len() >= 3 => {
+                triangulate_xy(&locations.iter().map(|l| [l.x, l.y]).collect::<Vec<_>>())
+            }
+            _ => vec![],
+        };
+        let g = Arc::new(ItemGeometry {
+            zoom,
+            tolerance,
+            item: self.item.clone(),
+            locations,
+            triangles,
+        });
+        *cached = Some(g.clone());
+        g
+    }
+}
+
+/// 要素在某个缩放级别下抽稀后的顶点与多边形填充的三角形
+struct ItemGeometry {
+    zoom: i32,
+    tolerance: f64,
+    /// 计算时的要素，持有引用使要素被修改时必然换成新的[Arc]
+    item: Arc<MapItem>,
+    locations: Vec<Location>,
+    /// 三角形的顶点下标，不是多边形时为空
+    triangles: Vec<u32>,
+}
+
+/// 绘制在瓦片图层之上的矢量图层
+#[derive(Clone)]
+pub struct MapOverlay {
+    /// 图层名，用于查找图层
+    pub name: String,
+    pub items: Vec<OverlayItem>,
+    /// 要素坐标所在的坐标系，None表示与视图的坐标系相同
+    pub wcs: Option<CommonWCS>,
+    pub visible: bool,
+    /// 抽稀容差，单位为像素，0表示不抽稀
+    pub simplify_tolerance: f32,
+}
+
+impl MapOverlay {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            items: vec![],
+            wcs: None,
+            visible: true,
+            simplify_tolerance: 1.0,
+        }
+    }
+
+    pub fn with_wcs(mut self, wcs: CommonWCS) -> Self {
+        self.wcs = Some(wcs);
+        self
+    }
+
+    pub fn with_item(mut self, item: impl Into<Arc<MapItem>>, style: ItemStyle) -> Self {
+        self.add_item(item, style);
+        self
+    }
+
+    pub fn add_item(&mut self, item: impl Into<Arc<MapItem>>, style: ItemStyle) {
+        self.items.push(OverlayItem::new(item, style));
+    }
+
+    pub fn clear(&mut self) {
+        self.items.clear();
+    }
+}
+
+/// 视图外额外保留的像素范围，避免点的半径与线宽被过早裁掉
+const CULL_MARGIN: f64 = 64.0;
+
+/// 在屏幕空间绘制矢量图层，圆柱投影下每个可见的世界副本都会绘制一次
+pub fn emap_draw_overlay(canvas: &dyn MapCanvas, mvs: &MapViewState, overlay: &MapOverlay) {
+    let offset = canvas.clip_rect().min.to_vec2();
+    emap_project_overlay(mvs, overlay, |_, oi, mut points, triangles| {
+        points.iter_mut().for_each(|p| *p += offset);
+        draw_item(canvas, &oi.item, &points, Some(triangles), &oi.style);
+    });
+}
+
+/// 对视图内的每个要素调用`f`，参数为要素下标、要素、抽稀后顶点的视图位置（相对于地图左上角）
+/// 与多边形填充的三角形。
+///
+/// 抽稀与三角化的结果按整数缩放级别缓存在要素中。圆柱投影下要素在每个可见的世界副本中各出现一次
+pub(crate) fn emap_project_overlay(
+    mvs: &MapViewState,
+    overlay: &MapOverlay,
+    mut f: impl FnMut(usize, &OverlayItem, Vec<Pos2>, &[u32]),
+) {
+    let wcs = overlay.wcs.as_ref();
+    let scale = TILE_SIZE * 2.0_f64.powf(mvs.zoom_lvl);
+    let margin = CULL_MARGIN / scale;
+    //按向上取整的缩放级别抽稀，缩放级别之间的误差不超过容差
+    let zoom = mvs.zoom_lvl.ceil() as i32;
+    let tolerance = overlay.simplify_tolerance as f64 / (TILE_SIZE * 2.0_f64.powi(zoom));
+    let (lt, rb) = mvs.location_range_in(wcs);
+    let worlds = mvs.visible_worlds();
+    overlay.items.iter().enumerate().for_each(|(index, oi)| {
+        let Some((item_lt, item_rb)) = oi.item.bounds() else {
+            return;
+        };
+        if item_rb.y < lt.y - margin || item_lt.y > rb.y + margin {
+            return;
+        }
+        let mut geometry: Option<Arc<ItemGeometry>> = None;
+        for world in worlds.clone() {
+            let w = world as f64;
+            if item_rb.x + w < lt.x - margin || item_lt.x + w > rb.x + margin {
+                continue;
+            }
+            let geometry = geometry.get_or_insert_with(|| oi.geometry(zoom, tolerance));
+            let points = geometry
+                .locations
+                .iter()
+                .map(|l| {
+                    let l = mvs.location_from_wcs(Location::new(l.x + w, l.y), wcs);
+                    let [x, y] = mvs.location_to_view_pos(l);
+                    Pos2::new(x as f32, y as f32)
+                })
+                .collect();
+            f(index, oi, points, &geometry.triangles);
+        }
+    });
+}
+
+/// 按样式绘制单个要素，`points`为要素顶点在屏幕上的位置
+pub fn emap_draw_item(canvas: &dyn MapCanvas, item: &MapItem, points: &[Pos2], style: &ItemStyle) {
+    draw_item(canvas, item, points, None, style);
+}
+
+/// `triangles`为None时按`points`三角化
+fn draw_item(
+    canvas: &dyn MapCanvas,
+    item: &MapItem,
+    points: &[Pos2],
+    triangles: Option<&[u32]>,
+    style: &ItemStyle,
+) {
+    let Some(&first) = points.first() else {
+        return;
+    };
+    let label_pos = match item {
+        MapItem::Point { .. } => {
+            canvas.circle(first, style.point_radius, style.fill, style.stroke);
+            first + vec2(style.point_radius + 2.0, 0.0)
+        }
+        MapItem::Line { .. } => {
+            canvas.add(Shape::line(points.to_vec(), style.stroke));
+            first
+        }
+        MapItem::Polygon { .. } => {
+            if points.len() >= 3 && style.fill != Color32::TRANSPARENT {
+                let mut mesh = Mesh::default();
+                points
+                    .iter()
+                    .for_each(|p| mesh.colored_vertex(*p, style.fill));
+                mesh.indices = match triangles {
+                    Some(t) => t.to_vec(),
+                    None => triangulate(points),
+                };
+                canvas.add(mesh.into());
+            }
+            canvas.add(Shape::closed_line(points.to_vec(), style.stroke));
+            let sum = points.iter().fold(vec2(0.0, 0.0), |s, p| s + p.to_vec2());
+            (sum / points.len() as f32).to_pos2()
+        }
+    };
+    if style.show_label && !item.data().name.is_empty() {
+        canvas.text(
+            label_pos,
+            if matches!(item, MapItem::Point { .. }) {
+                Align2::LEFT_CENTER
+            } else {
+                Align2::CENTER_CENTER
+            },
+            &item.data().name,
+            FontId::proportional(12.0),
+            style.stroke.color,
+        );
+    }
+}
+
+/// 耳切法三角化简单多边形，返回三角形的顶点下标，多边形可以是凹的
+pub(crate) fn triangulate(points: &[Pos2]) -> Vec<u32> {
+    triangulate_xy(
+        &points
+            .iter()
+            .map(|p| [p.x as f64, p.y as f64])
+            .collect::<Vec<_>>(),
+    )
+}
+
+/// 与[triangulate]相同，使用双精度坐标，用于在[Location]空间中三角化
+fn triangulate_xy(points: &[[f64; 2]]) -> Vec<u32> {
+    let cross = |a: [f64; 2], b: [f64; 2], c: [f64; 2]| {
+        (b[0] - a[0]) * (c[1] - a[1]) - (b[1] - a[1]) * (c[0] - a[0])
+    };
+    let n = points.len();
+    let area: f64 = (0..n)
+        .map(|i| {
+            let (a, b) = (points[i], points[(i + 1) % n]);
+            a[0] * b[1] - b[0] * a[1]
+        })
+        .sum();
+    let sign = area.signum();
+    let mut ring: Vec<usize> = (0..n).collect();
+    let mut triangles = vec![];
+    while ring.len() > 3 {
+        let len = ring.len();
+        let corner = |i: usize| (ring[(i + len - 1) % len], ring[i], ring[(i + 1) % len]);
+        let ear = (0..len).find(|&i| {
+            let (ia, ib, ic) = corner(i);
+            let (a, b, c) = (points[ia], points[ib], points[ic]);
+            if cross(a, b, c) * sign <= 0.0 {
+                return false;
+            }
+            ring.iter().all(|&j| {
+                let p = points[j];
+                j == ia
+                    || j == ib
+                    || j == ic
+                    || cross(a, b, p) * sign < 0.0
+                    || cross(b, c, p) * sign < 0.0
+                    || cross(c, a, p) * sign < 0.0
+            })
+        });
+        match ear {
+            Some(i) => {
+                let (ia, ib, ic) = corner(i);
+                triangles.extend([ia as u32, ib as u32, ic as u32]);
+                ring.remove(i);
+            }
+            None => {
+                //没有可切的耳朵时去掉共线的顶点，仍然没有则说明多边形自相交，放弃剩下的部分
+                let Some(i) = (0..len).find(|&i| {
+                    let (ia, ib, ic) = corner(i);
+                    cross(points[ia], points[ib], points[ic]) == 0.0
+                }) else {
+                    break;
+                };
+                ring.remove(i);
+            }
+        }
+    }
+    if ring.len() == 3 {
+        triangles.extend(ring.iter().map(|i| *i as u32));
+    }
+    triangles
+}
+
+#[test]
+fn test_item_geometry_cache() {
+    use rustitude_base::map_state::MapItemData;
+
+    //凹多边形
+    let ring = [(0.1, 0.1), (0.3, 0.1), (0.2, 0.15), (0.3, 0.3), (0.1, 0.3)]
+        .map(|(x, y)| Location::new(x, y))
+        .to_vec();
+    let mut oi = OverlayItem::new(
+        MapItem::polygon(ring.clone(), MapItemData::default()),
+        ItemStyle::default(),
+    );
+    let g = oi.geometry(3, 0.0);
+    assert_eq!(g.triangles.len(), 3 * (ring.len() - 2));
+    //同一缩放级别复用，缩放级别或要素改变后重新计算
+    assert!(Arc::ptr_eq(&g, &oi.geometry(3, 0.0)));
+    assert!(!Arc::ptr_eq(&g, &oi.geometry(4, 0.0)));
+    let g = oi.geometry(4, 0.0);
+    oi.item = Arc::new(MapItem::line(ring, MapItemData::default()));
+    let line = oi.geometry(4, 0.0);
+    assert!(!Arc::ptr_eq(&g, &line));
+    assert!(line.triangles.is_empty());
+}
@@ -0,0 +1,263 @@
+use std::sync::Arc;
+
+use egui::{Pos2, Vec2};
+use rustitude_base::{
+    cluster::Cluster, map_state::MapItem, map_view_state::MapViewState, qtree::QTreeKey,
+};
+
+use crate::{
+    cluster::{emap_pick_clusters, ClusterLayer},
+    map_widget::MapLayer,
+    overlay::{emap_project_overlay, ItemStyle, MapOverlay},
+    tile_drawable::TileQuad,
+};
+
+/// 要素的描述信息
+#[derive(Clone, Debug, Default)]
+pub struct FeatureInfo {
+    /// 要素id，同一要素跨越多个瓦片时用于去重
+    pub id: Option<u64>,
+    pub name: String,
+    pub props: Vec<(String, String)>,
+    /// 到拾取位置的屏幕距离，位于要素内部时为0
+    pub distance: f32,
+}
+
+/// 拾取到的要素所在的位置
+#[derive(Clone)]
+pub enum PickSource {
+    /// 矢量图层中的要素，`index`为其在[MapOverlay::items]中的下标
+    Overlay {
+        overlay: String,
+        index: usize,
+        item: Arc<MapItem>,
+    },
+    /// 瓦片中的要素
+    Tile { layer: String, key: QTreeKey },
+    /// 聚合点图层中的点或簇
+    Cluster {
+        layer: String,
+        cluster: Cluster<u64>,
+    },
+}
+
+#[derive(Clone)]
+pub struct PickedFeature {
+    pub source: PickSource,
+    pub info: FeatureInfo,
+}
+
+/// 拾取`pos`处的要素，`pos`为相对于地图左上角的视图位置，`tolerance`为像素容差。
+///
+/// 上层的要素排在前面，同一图层内按距离从近到远排列
+pub fn emap_pick(
+    mvs: &MapViewState,
+    layers: &[&MapLayer],
+    overlays: &[&MapOverlay],
+    clusters: &[&ClusterLayer],
+    pos: Pos2,
+    tolerance: f32,
+) -> Vec<PickedFeature> {
+    let mut picked = vec![];
+    clusters
+        .iter()
+        .rev()
+        .for_each(|c| picked.extend(emap_pick_clusters(mvs, c, pos, tolerance)));
+    overlays
+        .iter()
+        .rev()
+        .for_each(|o| picked.extend(emap_pick_overlay(mvs, o, pos, tolerance)));
+    let mut layers = layers.to_vec();
+    layers.sort_by_key(|l| std::cmp::Reverse(l.z_index));
+    layers
+        .iter()
+        .for_each(|l| picked.extend(emap_pick_tiles(mvs, l, pos, tolerance)));
+    picked
+}
+
+pub fn emap_pick_overlay(
+    mvs: &MapViewState,
+    overlay: &MapOverlay,
+    pos: Pos2,
+    tolerance: f32,
+) -> Vec<PickedFeature> {
+    let mut picked: Vec<PickedFeature> = vec![];
+    emap_project_overlay(mvs, overlay, |index, oi, points, _| {
+        let distance = item_distance(&oi.item, &points, &oi.style, pos);
+        if distance > tolerance {
+            return;
+        }
+        //同一要素可能出现在多个世界副本中，只保留最近的一个
+        if let Some(p) = picked
+            .iter_mut()
+            .find(|p| matches!(p.source, PickSource::Overlay { index: i, .. } if i == index))
+        {
+            p.info.distance = p.info.distance.min(distance);
+            return;
+        }
+        let data = oi.item.data();
+        let mut props: Vec<(String, String)> = data
+            .props
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        props.sort();
+        picked.push(PickedFeature {
+            source: PickSource::Overlay {
+                overlay: overlay.name.clone(),
+                index,
+                item: oi.item.clone(),
+            },
+            info: FeatureInfo {
+                id: None,
+                name: data.name.clone(),
+                props,
+                distance,
+            },
+        });
+    });
+    picked.sort_by(|a, b| a.info.distance.total_cmp(&b.info.distance));
+    picked
+}
+
+/// 拾取瓦片图层中已加载的瓦片内的要素
+pub fn emap_pick_tiles(
+    mvs: &MapViewState,
+    layer: &MapLayer,
+    pos: Pos2,
+    tolerance: f32,
+) -> Vec<PickedFeature> {
+    let style = layer
+        .res
+        .tile_style()
+        .multiply_opacity(layer.opacity)
+        .at_zoom(mvs.zoom_lvl);
+    if !layer.visible || style.opacity <= 0.0 {
+        return vec![];
+    }
+    let mut picked: Vec<PickedFeature> = vec![];
+    mvs.visible_tiles(layer.res.wcs().as_ref(), &layer.res.levels())
+        .into_iter()
+        .for_each(|vt| {
+            let quad = TileQuad::from_visible_tile(&vt, Vec2::ZERO);
+            if !quad.bounding_rect().expand(tolerance).contains(pos) {
+                return;
+            }
+            let Some(tile) = layer.res.get_memory_cache(vt.key) else {
+                return;
+            };
+            tile.pick(quad, pos, tolerance)
+                .into_iter()
+                .for_each(|info| {
+                    //跨越瓦片边界的要素在相邻瓦片中各有一份
+                    let duplicated =
+                        info.id.is_some() && picked.iter().any(|p| p.info.id == info.id);
+                    if !duplicated {
+                        picked.push(PickedFeature {
+                            source: PickSource::Tile {
+                                layer: layer.name.clone(),
+                                key: vt.key,
+                            },
+                            info,
+                        });
+                    }
+                });
+        });
+    picked.sort_by(|a, b| a.info.distance.total_cmp(&b.info.distance));
+    picked
+}
+
+/// `pos`到要素的屏幕距离，`points`为要素顶点的屏幕位置
+pub fn item_distance(item: &MapItem, points: &[Pos2], style: &ItemStyle, pos: Pos2) -> f32 {
+    let half_width = style.stroke.width / 2.0;
+    match item {
+        MapItem::Point { .. } => points.first().map_or(f32::INFINITY, |p| {
+            (p.distance(pos) - style.point_radius).max(0.0)
+        }),
+        MapItem::Line { .. } => {
+            if points.len() == 1 {
+                return (points[0].distance(pos) - half_width).max(0.0);
+            }
+            (points
+                .windows(2)
+                .map(|w| distance_to_segment(pos, w[0], w[1]))
+                .fold(f32::INFINITY, f32::min)
+                - half_width)
+                .max(0.0)
+        }
+        MapItem::Polygon { .. } => {
+            if points.len() >= 3 && point_in_polygon(pos, points) {
+                return 0.0;
+            }
+            let n = points.len();
+            ((0..n)
+                .map(|i| distance_to_segment(pos, points[i], points[(i + 1) % n]))
+                .fold(f32::INFINITY, f32::min)
+                - half_width)
+                .max(0.0)
+        }
+    }
+}
+
+pub fn distance_to_segment(p: Pos2, a: Pos2, b: Pos2) -> f32 {
+    let ab = b - a;
+    let len2 = ab.length_sq();
+    let t = if len2 > 0.0 {
+        ((p - a).dot(ab) / len2).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    p.distance(a + ab * t)
+}
+
+/// 奇偶规则判断点是否在多边形内
+pub fn point_in_polygon(p: Pos2, points: &[Pos2]) -> bool {
+    let n = points.len();
+    let mut inside = false;
+    for i in 0..n {
+        let (a, b) = (points[i], points[(i + n - 1) % n]);
+        if (a.y > p.y) != (b.y > p.y) && p.x < (b.x - a.x) * (p.y - a.y) / (b.y - a.y) + a.x {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+/// 要素信息面板，用于悬停提示与点击弹窗
+pub fn emap_feature_ui(ui: &mut egui::Ui, features: &[PickedFeature]) {
+    /// 面板中最多显示的要素数
+    const MAX_FEATURES: usize = 5;
+    features
+        .iter()
+        .take(MAX_FEATURES)
+        .enumerate()
+        .for_each(|(i, f)| {
+            if i > 0 {
+                ui.separator();
+            }
+            let title = match (&f.info.name, f.info.id) {
+                (name, _) if !name.is_empty() => name.clone(),
+                (_, Some(id)) => format!("#{}", id),
+                _ => String::from("-"),
+            };
+            ui.strong(title);
+            let source = match &f.source {
+                PickSource::Overlay { overlay, .. } => overlay.clone(),
+                PickSource::Tile { layer, key } => format!("{} {}", layer, key),
+                PickSource::Cluster { layer, .. } => layer.clone(),
+            };
+            ui.weak(source);
+            egui::Grid::new(("emap_feature", i))
+                .num_columns(2)
+                .show(ui, |ui| {
+                    f.info.props.iter().for_each(|(k, v)| {
+                        ui.label(k);
+                        ui.label(v);
+                        ui.end_row();
+                    });
+                });
+        });
+    if features.len() > MAX_FEATURES {
+        ui.weak(format!("+{}", features.len() - MAX_FEATURES));
+    }
+}
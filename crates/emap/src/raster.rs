@@ -0,0 +1,482 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use egui::{
+    epaint::{Fonts, ImageData, TessellationOptions, Tessellator},
+    pos2, vec2, Color32, ColorImage, FontDefinitions, FontId, Galley, Mesh, Pos2, Rect, Shape,
+    TextureId, Vec2,
+};
+use rustitude_base::{map_view_state::MapViewState, qtree::QTreeKey};
+
+use crate::{
+    canvas::MapCanvas,
+    clip_from_top_key,
+    cluster::{emap_draw_clusters, ClusterLayer},
+    map_widget::MapLayer,
+    overlay::{emap_draw_overlay, MapOverlay},
+    tile_drawable::{TileDrawContext, TileQuad},
+};
+
+/// 画布的像素与字体图集
+struct RasterTarget {
+    image: ColorImage,
+    /// 字体图集的副本，左上角的像素为白色，无纹理的图形也从这里取色
+    font_image: ColorImage,
+}
+
+/// 三角形的取色来源
+#[derive(Clone, Copy)]
+enum Texture<'a> {
+    Font,
+    Image(&'a ColorImage),
+}
+
+/// 在内存中绘制的画布，不需要GPU。
+///
+/// 图形先由epaint细分为三角形网格，再逐像素光栅化并按预乘透明度混合，
+/// 因此与egui中的绘制结果基本一致。克隆后共用同一块画布，类似于[egui::Painter]
+#[derive(Clone)]
+pub struct RasterCanvas {
+    target: Arc<Mutex<RasterTarget>>,
+    fonts: Fonts,
+    pixels_per_point: f32,
+    clip_rect: Rect,
+}
+
+impl RasterCanvas {
+    /// 字体图集的最大边长
+    const MAX_TEXTURE_SIDE: usize = 4096;
+
+    /// `size`为像素大小，绘制时的坐标单位为点，每点对应`pixels_per_point`个像素
+    pub fn new(size: [usize; 2], pixels_per_point: f32) -> Self {
+        let fonts = Fonts::new(
+            pixels_per_point,
+            Self::MAX_TEXTURE_SIDE,
+            FontDefinitions::default(),
+        );
+        Self {
+            target: Arc::new(Mutex::new(RasterTarget {
+                image: ColorImage::new(size, Color32::TRANSPARENT),
+                font_image: ColorImage::new([1, 1], Color32::WHITE),
+            })),
+            fonts,
+            pixels_per_point,
+            clip_rect: Rect::from_min_size(Pos2::ZERO, vec2(size[0] as f32, size[1] as f32))
+                / pixels_per_point,
+        }
+    }
+
+    /// 替换字体，默认字体不包含中文
+    pub fn with_fonts(mut self, definitions: FontDefinitions) -> Self {
+        self.fonts = Fonts::new(self.pixels_per_point, Self::MAX_TEXTURE_SIDE, definitions);
+        self
+    }
+
+    /// 共用画布，只在`rect`与当前绘制区域的交集内绘制
+    pub fn with_clip_rect(&self, rect: Rect) -> Self {
+        Self {
+            clip_rect: self.clip_rect.intersect(rect),
+            ..self.clone()
+        }
+    }
+
+    /// 画布大小，单位为点
+    pub fn size(&self) -> Vec2 {
+        let [w, h] = self.target.lock().unwrap().image.size;
+        vec2(w as f32, h as f32) / self.pixels_per_point
+    }
+
+    pub fn pixels_per_point(&self) -> f32 {
+        self.pixels_per_point
+    }
+
+    /// 用`color`覆盖绘制区域
+    pub fn fill(&self, color: Color32) {
+        let rect = self.pixel_clip_rect();
+        let mut target = self.target.lock().unwrap();
+        for y in rect.min.y as usize..rect.max.y as usize {
+            for x in rect.min.x as usize..rect.max.x as usize {
+                target.image[(x, y)] = color;
+            }
+        }
+    }
+
+    /// 当前画布内容的副本，像素为预乘透明度的sRGB颜色
+    pub fn to_image(&self) -> ColorImage {
+        self.target.lock().unwrap().image.clone()
+    }
+
+    /// 绘制区域对应的像素范围，已取整并限制在画布内
+    fn pixel_clip_rect(&self) -> Rect {
+        let [w, h] = self.target.lock().unwrap().image.size;
+        let rect = self.clip_rect * self.pixels_per_point;
+        Rect::from_min_max(
+            pos2(rect.min.x.max(0.0).round(), rect.min.y.max(0.0).round()),
+            pos2(
+                rect.max.x.min(w as f32).round(),
+                rect.max.y.min(h as f32).round(),
+            ),
+        )
+    }
+
+    /// 排版文字后字体图集可能有变化，同步到本地的副本
+    fn sync_font_image(target: &mut RasterTarget, fonts: &Fonts) {
+        let Some(delta) = fonts.font_image_delta() else {
+            return;
+        };
+        let patch = match delta.image {
+            ImageData::Color(image) => image.as_ref().clone(),
+            ImageData::Font(image) => ColorImage {
+                size: image.size,
+                pixels: image.srgba_pixels(None).collect(),
+            },
+        };
+        match delta.pos {
+            None => target.font_image = patch,
+            Some([x0, y0]) => {
+                for y in 0..patch.size[1] {
+                    for x in 0..patch.size[0] {
+                        target.font_image[(x0 + x, y0 + y)] = patch[(x, y)];
+                    }
+                }
+            }
+        }
+    }
+
+    /// 光栅化网格，顶点位置的单位为点
+    fn draw_mesh(&self, mesh: &Mesh, texture: Texture) {
+        let clip = self.pixel_clip_rect();
+        if clip.is_negative() || mesh.indices.is_empty() {
+            return;
+        }
+        let mut target = self.target.lock().unwrap();
+        if matches!(texture, Texture::Font) {
+            Self::sync_font_image(&mut target, &self.fonts);
+        }
+        let RasterTarget { image, font_image } = &mut *target;
+        let texture = match texture {
+            Texture::Font => font_image,
+            Texture::Image(image) => image,
+        };
+        let ppp = self.pixels_per_point;
+        mesh.indices.chunks_exact(3).for_each(|tri| {
+            let [a, b, c] = [tri[0], tri[1], tri[2]].map(|i| &mesh.vertices[i as usize]);
+            let [pa, pb, pc] = [a.pos, b.pos, c.pos].map(|p| pos2(p.x * ppp, p.y * ppp));
+            let area = edge(pa, pb, pc);
+            if area.abs() < f32::EPSILON {
+                return;
+            }
+            let bounds = Rect::from_points(&[pa, pb, pc]).intersect(clip);
+            if bounds.is_negative() {
+                return;
+            }
+            let colors = [a.color, b.color, c.color].map(rgba);
+            for y in bounds.min.y.floor() as usize..bounds.max.y.ceil() as usize {
+                for x in bounds.min.x.floor() as usize..bounds.max.x.ceil() as usize {
+                    let p = pos2(x as f32 + 0.5, y as f32 + 0.5);
+                    //重心坐标，三角形为顺时针时符号相反
+                    let w = [edge(pb, pc, p), edge(pc, pa, p), edge(pa, pb, p)].map(|w| w / area);
+                    let edges = [(pb, pc), (pc, pa), (pa, pb)];
+                    let inside = w
+                        .iter()
+                        .zip(edges)
+                        .all(|(w, (e0, e1))| *w > 0.0 || (*w == 0.0 && owns_edge(e0, e1, area)));
+                    if !inside {
+                        continue;
+                    }
+                    let uv = a.uv.to_vec2() * w[0] + b.uv.to_vec2() * w[1] + c.uv.to_vec2() * w[2];
+                    let texel = rgba(sample(texture, uv.to_pos2()));
+                    let src: [f32; 4] = std::array::from_fn(|i| {
+                        texel[i] * (colors[0][i] * w[0] + colors[1][i] * w[1] + colors[2][i] * w[2])
+                    });
+                    let dst = rgba(image[(x, y)]);
+                    let out: [f32; 4] = std::array::from_fn(|i| src[i] + dst[i] * (1.0 - src[3]));
+                    image[(x, y)] = Color32::from_rgba_premultiplied(
+                        to_u8(out[0]),
+                        to_u8(out[1]),
+                        to_u8(out[2]),
+                        to_u8(out[3]),
+                    );
+                }
+            }
+        });
+    }
+}
+
+/// `p`在有向边`a`→`b`的哪一侧，与三角形面积同号时在三角形内
+fn edge(a: Pos2, b: Pos2, p: Pos2) -> f32 {
+    (b.x - a.x) * (p.y - a.y) - (b.y - a.y) * (p.x - a.x)
+}
+
+/// 像素中心恰好落在边上时，只由共用这条边的两个三角形之一绘制，避免半透明的边被绘制两次
+fn owns_edge(a: Pos2, b: Pos2, area: f32) -> bool {
+    let d = (b - a) * area.signum();
+    d.y > 0.0 || (d.y == 0.0 && d.x > 0.0)
+}
+
+fn rgba(c: Color32) -> [f32; 4] {
+    c.to_array().map(|v| v as f32 / 255.0)
+}
+
+fn to_u8(v: f32) -> u8 {
+    (v * 255.0).round().clamp(0.0, 255.0) as u8
+}
+
+/// 双线性采样，`uv`为归一化坐标，超出范围时取边缘像素
+fn sample(image: &ColorImage, uv: Pos2) -> Color32 {
+    let [w, h] = image.size;
+    let x = uv.x * w as f32 - 0.5;
+    let y = uv.y * h as f32 - 0.5;
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fx, fy) = (x - x0, y - y0);
+    let px = |x: f32| (x.max(0.0) as usize).min(w - 1);
+    let py = |y: f32| (y.max(0.0) as usize).min(h - 1);
+    let [c00, c10, c01, c11] = [
+        (x0, y0),
+        (x0 + 1.0, y0),
+        (x0, y0 + 1.0),
+        (x0 + 1.0, y0 + 1.0),
+    ]
+    .map(|(x, y)| rgba(image[(px(x), py(y))]));
+    let c: [f32; 4] = std::array::from_fn(|i| {
+        let top = c00[i] + (c10[i] - c00[i]) * fx;
+        let bottom = c01[i] + (c11[i] - c01[i]) * fx;
+        top + (bottom - top) * fy
+    });
+    Color32::from_rgba_premultiplied(to_u8(c[0]), to_u8(c[1]), to_u8(c[2]), to_u8(c[3]))
+}
+
+impl MapCanvas for RasterCanvas {
+    fn clip_rect(&self) -> Rect {
+        self.clip_rect
+    }
+
+    /// 只能绘制不带纹理或使用字体图集的图形
+    fn add(&self, shape: Shape) {
+        match shape {
+            Shape::Vec(shapes) => shapes.into_iter().for_each(|s| self.add(s)),
+            Shape::Mesh(mesh) if mesh.texture_id != TextureId::default() => {}
+            shape => {
+                let options = TessellationOptions {
+                    prerasterized_discs: false,
+                    ..Default::default()
+                };
+                let mut tessellator = Tessellator::new(
+                    self.pixels_per_point,
+                    options,
+                    self.fonts.font_image_size(),
+                    vec![],
+                );
+                tessellator.set_clip_rect(self.clip_rect);
+                let mut mesh = Mesh::default();
+                tessellator.tessellate_shape(shape, &mut mesh);
+                self.draw_mesh(&mesh, Texture::Font);
+            }
+        }
+    }
+
+    fn layout_no_wrap(&self, text: String, font_id: FontId, color: Color32) -> Arc<Galley> {
+        self.fonts.layout_no_wrap(text, font_id, color)
+    }
+
+    /// 显存中的纹理无法读取，不绘制
+    fn texture(&self, _texture_id: TextureId, _uv: Rect, _quad: TileQuad, _tint: Color32) {}
+
+    fn image(&self, image: &Arc<ColorImage>, uv: Rect, quad: TileQuad, tint: Color32) {
+        let mesh = quad.textured_mesh(TextureId::User(0), uv, tint);
+        self.draw_mesh(&mesh, Texture::Image(image));
+    }
+}
+
+/// 离屏绘制地图，`layers`按顺序从下到上绘制，之后依次绘制`overlays`与`clusters`。
+///
+/// 瓦片通过[crate::EguiMapTileRes::load_blocking]同步获取，`mvs`的视图大小应与画布大小一致。
+/// 缺失的瓦片用同样同步获取的祖先瓦片裁剪后代替，最多向上查找[RENDER_FALLBACK_LEVELS]层；
+/// 内存缓存中的瓦片可能只有纹理，无法离屏绘制，因此不使用
+pub fn emap_render(
+    canvas: &RasterCanvas,
+    mvs: &MapViewState,
+    layers: &[&MapLayer],
+    overlays: &[&MapOverlay],
+    clusters: &[&ClusterLayer],
+) {
+    let offset = canvas.clip_rect().min.to_vec2();
+    layers.iter().for_each(|layer| {
+        let style = layer
+            .res
+            .tile_style()
+            .multiply_opacity(layer.opacity)
+            .at_zoom(mvs.zoom_lvl);
+        if style.opacity <= 0.0 {
+            return;
+        }
+        let canvas = match layer.clip {
+            Some(clip) => canvas.with_clip_rect(Rect::from_min_size(
+                canvas.clip_rect().min,
+                canvas.clip_rect().size() * clip,
+            )),
+            None => canvas.clone(),
+        };
+        let tiles = mvs.visible_tiles(layer.res.wcs().as_ref(), &layer.res.levels());
+        let keys: Vec<_> = tiles.iter().map(|vt| vt.key).collect();
+        let loaded = layer.res.load_blocking(&keys);
+        //(瓦片位置, 缺失的瓦片, 正在查找的祖先)
+        let mut missing: Vec<(TileQuad, QTreeKey, QTreeKey)> = vec![];
+        tiles.iter().zip(loaded).for_each(|(vt, tile)| {
+            let quad = TileQuad::from_visible_tile(vt, offset);
+            match tile {
+                Some(t) => t.draw(&TileDrawContext::new(&canvas, quad, style)),
+                None => missing.push((quad, vt.key, vt.key)),
+            }
+        });
+        for _ in 0..RENDER_FALLBACK_LEVELS {
+            missing = missing
+                .into_iter()
+                .filter_map(|(quad, key, top)| Some((quad, key, top.parent()?)))
+                .collect();
+            if missing.is_empty() {
+                break;
+            }
+            let mut parents: Vec<QTreeKey> = missing.iter().map(|m| m.2).collect();
+            parents.sort_by_key(|k| k.inner_key());
+            parents.dedup();
+            let loaded: HashMap<u64, _> = parents
+                .iter()
+                .copied()
+                .zip(layer.res.load_blocking(&parents))
+                .filter_map(|(k, t)| Some((k.inner_key(), t?)))
+                .collect();
+            missing.retain(|(quad, key, top)| {
+                match loaded
+                    .get(&top.inner_key())
+                    .and_then(|t| t.clip(clip_from_top_key(*top, *key)))
+                {
+                    Some(t) => {
+                        t.draw(&TileDrawContext::new(&canvas, *quad, style));
+                        false
+                    }
+                    None => true,
+                }
+            });
+        }
+    });
+    overlays
+        .iter()
+        .for_each(|overlay| emap_draw_overlay(canvas, mvs, overlay));
+    clusters
+        .iter()
+        .for_each(|layer| emap_draw_clusters(canvas, mvs, layer));
+}
+
+/// 离屏绘制时缺失的瓦片最多向上查找祖先瓦片的层数
+pub const RENDER_FALLBACK_LEVELS: usize = 3;
+
+#[test]
+fn test_raster_canvas() {
+    use egui::{Align2, Stroke};
+
+    let canvas = RasterCanvas::new([40, 20], 2.0);
+    assert_eq!(canvas.size(), vec2(20.0, 10.0));
+    canvas.fill(Color32::WHITE);
+    canvas.add(Shape::convex_polygon(
+        vec![
+            pos2(0.0, 0.0),
+            pos2(10.0, 0.0),
+            pos2(10.0, 10.0),
+            pos2(0.0, 10.0),
+        ],
+        Color32::RED,
+        Stroke::NONE,
+    ));
+    let image = canvas.to_image();
+    assert_eq!(image[(5, 10)], Color32::RED);
+    assert_eq!(image[(30, 10)], Color32::WHITE);
+
+    //半透明的图片按预乘透明度混合，只绘制在裁剪区域内
+    let half = Arc::new(ColorImage::new([2, 2], Color32::from_black_alpha(128)));
+    let right = canvas.with_clip_rect(Rect::from_min_max(pos2(15.0, 0.0), pos2(20.0, 10.0)));
+    right.image(
+        &half,
+        Rect::from_min_max(pos2(0.0, 0.0), pos2(1.0, 1.0)),
+        TileQuad::from_rect(Rect::from_min_max(pos2(10.0, 0.0), pos2(20.0, 10.0))),
+        Color32::WHITE,
+    );
+    let image = canvas.to_image();
+    assert_eq!(image[(25, 10)], Color32::WHITE);
+    assert_eq!(image[(35, 10)], Color32::from_gray(127));
+
+    //文字使用字体图集绘制
+    canvas.text(
+        pos2(5.0, 5.0),
+        Align2::CENTER_CENTER,
+        "■",
+        FontId::proportional(8.0),
+        Color32::BLUE,
+    );
+    let image = canvas.to_image();
+    assert!(image.pixels.iter().any(|c| c.b() > c.r()));
+}
+
+#[test]
+fn test_render_missing_tiles() {
+    use crate::{
+        tile_drawable::{CommonEguiTileDrawable, ImageTile},
+        EguiMapTileRes,
+    };
+    use egui::load::SizedTexture;
+    use rustitude_base::map_state::Location;
+
+    /// 只有`depth`层以上的瓦片可以同步获取，内存缓存中的瓦片只有纹理
+    struct ShallowRes {
+        depth: u8,
+    }
+    impl EguiMapTileRes for ShallowRes {
+        fn get_memory_cache(&self, _key: QTreeKey) -> Option<CommonEguiTileDrawable> {
+            Some(Arc::new(SizedTexture::new(
+                TextureId::Managed(1),
+                vec2(256.0, 256.0),
+            )))
+        }
+
+        fn get_or_fetch(
+            &self,
+            key: QTreeKey,
+            _mvs: Arc<std::sync::RwLock<MapViewState>>,
+            _ctx: &egui::Context,
+        ) -> Option<CommonEguiTileDrawable> {
+            self.get_memory_cache(key)
+        }
+
+        fn load_blocking(&self, keys: &[QTreeKey]) -> Vec<Option<CommonEguiTileDrawable>> {
+            keys.iter()
+                .map(|k| {
+                    (k.depth() < self.depth).then(|| {
+                        Arc::new(ImageTile::new(ColorImage::new([4, 4], Color32::RED)))
+                            as CommonEguiTileDrawable
+                    })
+                })
+                .collect()
+        }
+    }
+
+    let mut mvs = MapViewState::new(Location::new(0.5, 0.5), [64.0, 64.0], 4.0);
+    mvs.min_zoom = 0.0;
+    let depth = mvs.tile_depth();
+    let render = |res: ShallowRes| {
+        let layer = MapLayer::new("shallow", Arc::new(res));
+        let canvas = RasterCanvas::new([64, 64], 1.0);
+        canvas.fill(Color32::WHITE);
+        emap_render(&canvas, &mvs, &[&layer], &[], &[]);
+        canvas.to_image()
+    };
+    //缺失的瓦片用父瓦片代替，不留空洞
+    let image = render(ShallowRes { depth });
+    assert!(image.pixels.iter().all(|c| *c == Color32::RED));
+    //祖先超出查找层数时保持空白，内存缓存中的纹理瓦片不会被使用
+    let image = render(ShallowRes {
+        depth: depth.saturating_sub(RENDER_FALLBACK_LEVELS as u8),
+    });
+    assert!(image.pixels.iter().all(|c| *c == Color32::WHITE));
+}
@@ -0,0 +1,94 @@
+use egui::{vec2, Color32, CursorIcon, Pos2, Rect, Sense, Stroke, Vec2};
+
+/// 卷帘对比，`layer`图层只绘制在分割线的左侧（或上方），拖动分割线改变对比的位置
+#[derive(Clone, Debug, PartialEq)]
+pub struct SwipeCompare {
+    /// 被裁剪的图层名
+    pub layer: String,
+    /// 分割线在地图区域中的位置比例，0到1之间
+    pub position: f32,
+    /// 为true时分割线为水平方向，图层绘制在上方
+    pub horizontal: bool,
+}
+
+impl SwipeCompare {
+    pub fn new(layer: impl Into<String>) -> Self {
+        Self {
+            layer: layer.into(),
+            position: 0.5,
+            horizontal: false,
+        }
+    }
+
+    pub fn with_horizontal(mut self, horizontal: bool) -> Self {
+        self.horizontal = horizontal;
+        self
+    }
+
+    /// 被裁剪图层的[crate::map_widget::MapLayer::clip]
+    pub fn clip(&self) -> Vec2 {
+        if self.horizontal {
+            vec2(1.0, self.position)
+        } else {
+            vec2(self.position, 1.0)
+        }
+    }
+}
+
+/// 绘制分割线并处理拖动，需要在[crate::egui_map::emap_show]之后调用
+pub fn emap_show_swipe(ui: &mut egui::Ui, rect: Rect, swipe: &mut SwipeCompare) {
+    /// 分割线两侧可以拖动的像素范围
+    const GRAB_WIDTH: f32 = 6.0;
+    const KNOB_RADIUS: f32 = 10.0;
+    let (line, grab) = if swipe.horizontal {
+        let y = rect.top() + rect.height() * swipe.position;
+        (
+            [Pos2::new(rect.left(), y), Pos2::new(rect.right(), y)],
+            Rect::from_x_y_ranges(rect.x_range(), y - GRAB_WIDTH..=y + GRAB_WIDTH),
+        )
+    } else {
+        let x = rect.left() + rect.width() * swipe.position;
+        (
+            [Pos2::new(x, rect.top()), Pos2::new(x, rect.bottom())],
+            Rect::from_x_y_ranges(x - GRAB_WIDTH..=x + GRAB_WIDTH, rect.y_range()),
+        )
+    };
+    let knob = line[0].lerp(line[1], 0.5);
+    let grab = grab.union(Rect::from_center_size(knob, Vec2::splat(KNOB_RADIUS * 2.0)));
+    let response = ui.interact(grab, ui.id().with("emap_swipe"), Sense::drag());
+    if response.hovered() || response.dragged() {
+        ui.ctx().set_cursor_icon(if swipe.horizontal {
+            CursorIcon::ResizeVertical
+        } else {
+            CursorIcon::ResizeHorizontal
+        });
+    }
+    if let Some(p) = response
+        .dragged()
+        .then(|| response.interact_pointer_pos())
+        .flatten()
+    {
+        swipe.position = if swipe.horizontal {
+            (p.y - rect.top()) / rect.height()
+        } else {
+            (p.x - rect.left()) / rect.width()
+        }
+        .clamp(0.0, 1.0);
+    }
+    let painter = ui.painter_at(rect);
+    painter.line_segment(line, Stroke::new(3.0, Color32::WHITE));
+    painter.line_segment(line, Stroke::new(1.0, Color32::from_gray(0x44)));
+    painter.circle(
+        knob,
+        KNOB_RADIUS,
+        Color32::WHITE,
+        Stroke::new(1.0, Color32::from_gray(0x44)),
+    );
+    painter.text(
+        knob,
+        egui::Align2::CENTER_CENTER,
+        if swipe.horizontal { "↕" } else { "↔" },
+        egui::FontId::proportional(12.0),
+        Color32::from_gray(0x44),
+    );
+}
@@ -0,0 +1,109 @@
+use std::sync::{Arc, RwLock};
+
+use rustitude_base::{camera::CameraState, latlng::LatLng, map_view_state::MapViewState};
+
+/// 同步方式
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SyncMode {
+    /// 任一地图的相机变化都会同步到其他地图
+    #[default]
+    Share,
+    /// 只跟随其他地图，自身的变化不会同步出去
+    Follow,
+}
+
+/// 相机中心以经纬度保存，不同坐标系的地图之间也可以同步
+#[derive(Clone, Copy)]
+struct LinkedCamera {
+    central: LatLng,
+    zoom_lvl: f64,
+    bearing: f64,
+    pitch: f64,
+}
+
+#[derive(Default)]
+struct LinkState {
+    camera: Option<LinkedCamera>,
+    /// 每次发布相机后加一
+    version: u64,
+}
+
+/// 多个地图共用的相机，克隆后指向同一个相机。
+///
+/// 每个地图仍然持有自己的[MapViewState]，因此视图大小互不影响
+#[derive(Clone, Default)]
+pub struct CameraLink {
+    inner: Arc<RwLock<LinkState>>,
+}
+
+impl CameraLink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 创建一个加入该同步组的同步器
+    pub fn sync(&self, mode: SyncMode) -> CameraSync {
+        CameraSync {
+            link: self.clone(),
+            mode,
+            seen: 0,
+            last: None,
+        }
+    }
+}
+
+/// 单个地图与[CameraLink]之间的同步器，绘制前调用[CameraSync::pull]，绘制后调用[CameraSync::push]
+pub struct CameraSync {
+    link: CameraLink,
+    pub mode: SyncMode,
+    /// 已应用的相机版本
+    seen: u64,
+    /// 上次同步后本地图的相机，用于判断本地图是否有变化
+    last: Option<CameraState>,
+}
+
+impl CameraSync {
+    pub fn link(&self) -> &CameraLink {
+        &self.link
+    }
+
+    /// 应用其他地图发布的相机，有更新时返回true
+    pub fn pull(&mut self, mvs: &mut MapViewState) -> bool {
+        let state = self.link.inner.read().unwrap();
+        let Some(camera) = state.camera.filter(|_| state.version != self.seen) else {
+            return false;
+        };
+        self.seen = state.version;
+        drop(state);
+        mvs.stop_animation();
+        mvs.set_camera(CameraState {
+            central: mvs.wcs.to_location(camera.central),
+            zoom_lvl: camera.zoom_lvl,
+            bearing: camera.bearing,
+            pitch: camera.pitch,
+        });
+        self.last = Some(mvs.camera());
+        true
+    }
+
+    /// 本地图的相机有变化时发布给其他地图，[SyncMode::Follow]时只记录不发布
+    pub fn push(&mut self, mvs: &MapViewState) {
+        let camera = mvs.camera();
+        if self.last == Some(camera) {
+            return;
+        }
+        self.last = Some(camera);
+        if self.mode == SyncMode::Follow {
+            return;
+        }
+        let mut state = self.link.inner.write().unwrap();
+        state.camera = Some(LinkedCamera {
+            central: mvs.central_lat_lng(),
+            zoom_lvl: camera.zoom_lvl,
+            bearing: camera.bearing,
+            pitch: camera.pitch,
+        });
+        state.version += 1;
+        self.seen = state.version;
+    }
+}
@@ -1,27 +1,285 @@
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock};
 
 use egui::{
-    epaint::CornerRadiusF32, load::SizedTexture, pos2, vec2, Align2, Color32, FontId, Painter,
-    Rect, Stroke, Vec2,
+    epaint::Vertex, load::SizedTexture, pos2, vec2, Align2, Color32, ColorImage, FontId, Mesh,
+    Pos2, Rect, Shape, Stroke, TextureHandle, TextureId, Vec2,
 };
-use rustitude_base::qtree::QTreeKey;
+use rustitude_base::{map_view_state::VisibleTile, qtree::QTreeKey};
+
+use crate::{canvas::MapCanvas, pick::FeatureInfo};
 
 pub const TILE_SIZE_VEC2: Vec2 = vec2(256.0, 256.0);
 
+/// 瓦片在屏幕上占据的四边形，顶点依次为左上、右上、右下、左下
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TileQuad(pub [Pos2; 4]);
+
+impl TileQuad {
+    pub fn from_rect(rect: Rect) -> Self {
+        Self([
+            rect.left_top(),
+            rect.right_top(),
+            rect.right_bottom(),
+            rect.left_bottom(),
+        ])
+    }
+
+    /// 由视图中的瓦片构造，`offset`为视图左上角在屏幕中的位置
+    pub fn from_visible_tile(tile: &VisibleTile, offset: Vec2) -> Self {
+        Self(tile.corners.map(|[x, y]| pos2(x as f32, y as f32) + offset))
+    }
+
+    /// 从单位正方形到四边形的透视变换系数，见Heckbert的square-to-quad推导
+    fn homography(&self) -> [f32; 8] {
+        let [p0, p1, p2, p3] = self.0;
+        let s = (p0 - p1) + (p2 - p3);
+        if s.x == 0.0 && s.y == 0.0 {
+            return [
+                p1.x - p0.x,
+                p3.x - p0.x,
+                p0.x,
+                p1.y - p0.y,
+                p3.y - p0.y,
+                p0.y,
+                0.0,
+                0.0,
+            ];
+        }
+        let d1 = p1 - p2;
+        let d2 = p3 - p2;
+        let den = d1.x * d2.y - d2.x * d1.y;
+        let g = (s.x * d2.y - d2.x * s.y) / den;
+        let h = (d1.x * s.y - s.x * d1.y) / den;
+        [
+            p1.x - p0.x + g * p1.x,
+            p3.x - p0.x + h * p3.x,
+            p0.x,
+            p1.y - p0.y + g * p1.y,
+            p3.y - p0.y + h * p3.y,
+            p0.y,
+            g,
+            h,
+        ]
+    }
+
+    /// 瓦片内归一化坐标(u,v)在屏幕上的位置，俯仰时按透视变换插值
+    pub fn project(&self, u: f32, v: f32) -> Pos2 {
+        Self::apply(&self.homography(), u, v)
+    }
+
+    fn apply(m: &[f32; 8], u: f32, v: f32) -> Pos2 {
+        let w = m[6] * u + m[7] * v + 1.0;
+        pos2(
+            (m[0] * u + m[1] * v + m[2]) / w,
+            (m[3] * u + m[4] * v + m[5]) / w,
+        )
+    }
+
+    /// 四边形是否为平行四边形，此时透视变换退化为仿射变换
+    pub fn is_parallelogram(&self) -> bool {
+        let [p0, p1, p2, p3] = self.0;
+        let s = (p0 - p1) + (p2 - p3);
+        s.length_sq() < 1e-6
+    }
+
+    pub fn bounding_rect(&self) -> Rect {
+        Rect::from_points(&self.0)
+    }
+
+    /// 四边形是否为与屏幕坐标轴对齐的矩形
+    pub fn is_axis_aligned(&self) -> bool {
+        let [lt, rt, rb, lb] = self.0;
+        lt.y == rt.y && lb.y == rb.y && lt.x == lb.x && rt.x == rb.x && lt.x < rt.x
+    }
+
+    /// 将四边形内的子区域映射为新的四边形，`rect`为归一化坐标
+    pub fn sub_quad(&self, rect: Rect) -> Self {
+        Self([
+            self.project(rect.min.x, rect.min.y),
+            self.project(rect.max.x, rect.min.y),
+            self.project(rect.max.x, rect.max.y),
+            self.project(rect.min.x, rect.max.y),
+        ])
+    }
+
+    pub fn filled(&self, color: Color32) -> Shape {
+        Shape::convex_polygon(self.0.to_vec(), color, Stroke::NONE)
+    }
+
+    pub fn outline(&self, stroke: Stroke) -> Shape {
+        Shape::closed_line(self.0.to_vec(), stroke)
+    }
+
+    /// 生成贴图网格，`uv`为纹理中对应的区域。
+    ///
+    /// 两个三角形只能做仿射贴图，透视四边形会被细分为网格以减小纹理扭曲。
+    pub fn textured_mesh(&self, texture_id: TextureId, uv: Rect, tint: Color32) -> Mesh {
+        let n = if self.is_parallelogram() {
+            1
+        } else {
+            MESH_SUBDIVISIONS
+        };
+        let m = self.homography();
+        let mut mesh = Mesh::with_texture(texture_id);
+        for j in 0..=n {
+            for i in 0..=n {
+                let (u, v) = (i as f32 / n as f32, j as f32 / n as f32);
+                mesh.vertices.push(Vertex {
+                    pos: Self::apply(&m, u, v),
+                    uv: pos2(uv.min.x + uv.width() * u, uv.min.y + uv.height() * v),
+                    color: tint,
+                });
+            }
+        }
+        for j in 0..n {
+            for i in 0..n {
+                let a = j * (n + 1) + i;
+                let b = a + n + 1;
+                mesh.add_triangle(a, a + 1, b + 1);
+                mesh.add_triangle(a, b + 1, b);
+            }
+        }
+        mesh
+    }
+}
+
+/// 透视四边形贴图时每条边的细分数
+const MESH_SUBDIVISIONS: u32 = 8;
+
+/// 瓦片资源的绘制样式
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TileStyle {
+    /// 不透明度，0为完全透明
+    pub opacity: f32,
+    /// 与瓦片颜色相乘的颜色，白色表示保持原色
+    pub tint: Color32,
+    /// 缩放级别低于该值时隐藏，并在其上方[TileStyle::HIDE_FADE_ZOOM]级内渐隐，None表示不隐藏。
+    ///
+    /// 与[rustitude_base::map_view_state::TileLevels::min_zoom]不同，这里是逐渐淡出而不是立即消失
+    pub hide_below_zoom: Option<f64>,
+}
+
+impl Default for TileStyle {
+    fn default() -> Self {
+        Self {
+            opacity: 1.0,
+            tint: Color32::WHITE,
+            hide_below_zoom: None,
+        }
+    }
+}
+
+impl TileStyle {
+    /// 隐藏前渐隐的缩放级别范围
+    pub const HIDE_FADE_ZOOM: f64 = 0.5;
+
+    pub fn with_opacity(mut self, opacity: f32) -> Self {
+        self.opacity = opacity.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn with_tint(mut self, tint: Color32) -> Self {
+        self.tint = tint;
+        self
+    }
+
+    pub fn with_hide_below_zoom(mut self, zoom_lvl: f64) -> Self {
+        self.hide_below_zoom = Some(zoom_lvl);
+        self
+    }
+
+    /// 在`zoom_lvl`下实际使用的样式，不透明度已计入缩小时的渐隐
+    pub fn at_zoom(&self, zoom_lvl: f64) -> Self {
+        let fade = self.hide_below_zoom.map_or(1.0, |z| {
+            ((zoom_lvl - z) / Self::HIDE_FADE_ZOOM).clamp(0.0, 1.0) as f32
+        });
+        self.with_opacity(self.opacity * fade)
+    }
+
+    /// 不透明度乘以`factor`
+    pub fn multiply_opacity(mut self, factor: f32) -> Self {
+        self.opacity = (self.opacity * factor).clamp(0.0, 1.0);
+        self
+    }
+
+    /// 将样式应用到颜色上
+    pub fn color(&self, color: Color32) -> Color32 {
+        (color * self.tint).linear_multiply(self.opacity)
+    }
+}
+
+/// 绘制单个瓦片时的上下文
+#[derive(Clone, Copy)]
+pub struct TileDrawContext<'a> {
+    pub canvas: &'a dyn MapCanvas,
+    pub quad: TileQuad,
+    pub style: TileStyle,
+}
+
+impl<'a> TileDrawContext<'a> {
+    pub fn new(canvas: &'a dyn MapCanvas, quad: TileQuad, style: TileStyle) -> Self {
+        Self {
+            canvas,
+            quad,
+            style,
+        }
+    }
+
+    pub fn with_quad(&self, quad: TileQuad) -> Self {
+        Self { quad, ..*self }
+    }
+
+    pub fn with_style(&self, style: TileStyle) -> Self {
+        Self { style, ..*self }
+    }
+}
+
 pub trait EguiTileDrawable: Send + Sync {
-    fn draw(&self, painter: &Painter, rect: Rect);
+    fn draw(&self, ctx: &TileDrawContext);
     fn clip(&self, rect: Rect) -> Option<CommonEguiTileDrawable>;
+
+    /// 瓦片绘制在`quad`处时，拾取距离`pos`不超过`tolerance`像素的要素，栅格瓦片没有要素
+    fn pick(&self, _quad: TileQuad, _pos: Pos2, _tolerance: f32) -> Vec<FeatureInfo> {
+        vec![]
+    }
+
+    /// 估算占用的内存或显存字节数，用于限制内存缓存的大小
+    fn byte_size(&self) -> usize {
+        0
+    }
+
+    /// 是否不依赖显存中的纹理，可以绘制到离屏的[MapCanvas]，如导出图片
+    fn is_cpu_drawable(&self) -> bool {
+        true
+    }
+}
+
+/// 绘制纹理中的`uv`区域
+fn draw_texture(ctx: &TileDrawContext, texture_id: TextureId, uv: Rect) {
+    ctx.canvas
+        .texture(texture_id, uv, ctx.quad, ctx.style.color(Color32::WHITE));
+}
+
+/// `rect`为`uv`内的归一化区域，返回其在整张图片中的区域
+fn sub_uv(uv: Rect, rect: Rect) -> Rect {
+    let size = uv.size();
+    Rect::from_min_size(
+        pos2(
+            uv.min.x + size.x * rect.min.x,
+            uv.min.y + size.y * rect.min.y,
+        ),
+        vec2(size.x * rect.width(), size.y * rect.height()),
+    )
 }
 
 pub type CommonEguiTileDrawable = Arc<dyn EguiTileDrawable>;
 
 impl EguiTileDrawable for SizedTexture {
-    fn draw(&self, painter: &Painter, rect: Rect) {
-        painter.image(
+    fn draw(&self, ctx: &TileDrawContext) {
+        draw_texture(
+            ctx,
             self.id,
-            rect,
             Rect::from_min_max(pos2(0.0, 0.0), pos2(1.0, 1.0)),
-            Color32::WHITE,
         );
     }
 
@@ -29,46 +287,143 @@ impl EguiTileDrawable for SizedTexture {
         let d = (self.clone(), rect);
         Some(Arc::new(d))
     }
+
+    fn byte_size(&self) -> usize {
+        (self.size.x * self.size.y * 4.0) as usize
+    }
+
+    fn is_cpu_drawable(&self) -> bool {
+        false
+    }
 }
 
 impl EguiTileDrawable for (SizedTexture, Rect) {
-    fn draw(&self, painter: &Painter, rect: Rect) {
-        painter.image(self.0.id, rect, self.1, Color32::WHITE);
+    fn draw(&self, ctx: &TileDrawContext) {
+        draw_texture(ctx, self.0.id, self.1);
     }
 
     fn clip(&self, rect: Rect) -> Option<CommonEguiTileDrawable> {
-        let self_size = self.1.size();
-        let rect_size = rect.size();
-        Some(Arc::new((
-            self.0,
-            Rect::from_min_size(
-                pos2(
-                    self.1.min.x + self_size.x * rect.min.x,
-                    self.1.min.y + self_size.y * rect.min.y,
-                ),
-                vec2(self_size.x * rect_size.x, self_size.y * rect_size.y),
+        Some(Arc::new((self.0, sub_uv(self.1, rect))))
+    }
+
+    fn is_cpu_drawable(&self) -> bool {
+        false
+    }
+}
+
+/// 像素保存在内存中的图片瓦片，不依赖egui的纹理，可以离屏绘制。
+///
+/// 第一次在屏幕上绘制时上传纹理并保留，裁剪出的子瓦片共用同一个纹理
+#[derive(Clone)]
+pub struct ImageTile {
+    pub image: Arc<ColorImage>,
+    /// 绘制的图片区域，归一化坐标
+    pub uv: Rect,
+    texture: Arc<OnceLock<TextureHandle>>,
+}
+
+impl ImageTile {
+    pub fn new(image: ColorImage) -> Self {
+        Self {
+            image: Arc::new(image),
+            uv: Rect::from_min_max(pos2(0.0, 0.0), pos2(1.0, 1.0)),
+            texture: Arc::new(OnceLock::new()),
+        }
+    }
+
+    /// 已上传或可以上传时返回纹理
+    fn texture(&self, canvas: &dyn MapCanvas) -> Option<TextureHandle> {
+        if let Some(texture) = self.texture.get() {
+            return Some(texture.clone());
+        }
+        let texture = canvas.load_texture(&self.image)?;
+        Some(self.texture.get_or_init(|| texture).clone())
+    }
+}
+
+impl EguiTileDrawable for ImageTile {
+    fn draw(&self, ctx: &TileDrawContext) {
+        match self.texture(ctx.canvas) {
+            Some(texture) => draw_texture(ctx, texture.id(), self.uv),
+            None => ctx.canvas.image(
+                &self.image,
+                self.uv,
+                ctx.quad,
+                ctx.style.color(Color32::WHITE),
             ),
-        )))
+        }
+    }
+
+    fn clip(&self, rect: Rect) -> Option<CommonEguiTileDrawable> {
+        Some(Arc::new(Self {
+            image: self.image.clone(),
+            uv: sub_uv(self.uv, rect),
+            texture: self.texture.clone(),
+        }))
+    }
+
+    /// 始终计入上传后纹理的大小，放入内存缓存后估算值不随绘制变化
+    fn byte_size(&self) -> usize {
+        2 * std::mem::size_of_val(self.image.pixels.as_slice())
+    }
+}
+
+/// 持有纹理的图片瓦片，最后一个引用释放时显存中的纹理随之释放。
+///
+/// 裁剪出的子瓦片共用同一个纹理
+#[derive(Clone)]
+pub struct TextureTile {
+    pub texture: TextureHandle,
+    /// 绘制的纹理区域，归一化坐标
+    pub uv: Rect,
+}
+
+impl TextureTile {
+    pub fn new(texture: TextureHandle) -> Self {
+        Self {
+            texture,
+            uv: Rect::from_min_max(pos2(0.0, 0.0), pos2(1.0, 1.0)),
+        }
+    }
+}
+
+impl EguiTileDrawable for TextureTile {
+    fn draw(&self, ctx: &TileDrawContext) {
+        draw_texture(ctx, self.texture.id(), self.uv);
+    }
+
+    fn clip(&self, rect: Rect) -> Option<CommonEguiTileDrawable> {
+        Some(Arc::new(Self {
+            texture: self.texture.clone(),
+            uv: sub_uv(self.uv, rect),
+        }))
+    }
+
+    fn byte_size(&self) -> usize {
+        self.texture.byte_size()
+    }
+
+    fn is_cpu_drawable(&self) -> bool {
+        false
     }
 }
 
 impl EguiTileDrawable for QTreeKey {
-    fn draw(&self, painter: &Painter, rect: Rect) {
-        painter.rect_stroke(
-            rect,
-            CornerRadiusF32::same(0.0),
-            Stroke::new(1.0, Color32::from_rgb(0xff, 0x11, 0)),
-            egui::StrokeKind::Inside,
+    fn draw(&self, ctx: &TileDrawContext) {
+        let (canvas, quad) = (ctx.canvas, ctx.quad);
+        canvas.quad_stroke(
+            quad,
+            Stroke::new(1.0, ctx.style.color(Color32::from_rgb(0xff, 0x11, 0))),
         );
-        painter.text(
-            rect.min,
+        canvas.text(
+            quad.0[0],
             Align2::LEFT_TOP,
-            format!("{}", self),
+            &format!("{}", self),
             FontId {
                 size: 8.0,
                 family: egui::FontFamily::Monospace,
             },
-            Color32::from_gray(0xff),
+            ctx.style.color(Color32::from_gray(0xff)),
         );
     }
 
@@ -76,3 +431,37 @@ impl EguiTileDrawable for QTreeKey {
         None
     }
 }
+
+#[test]
+fn test_image_tile_texture() {
+    let ctx = egui::Context::default();
+    let painter = egui::Painter::new(
+        ctx.clone(),
+        egui::LayerId::background(),
+        Rect::from_min_max(pos2(0.0, 0.0), pos2(256.0, 256.0)),
+    );
+    let allocated = || ctx.tex_manager().read().num_allocated();
+    let before = allocated();
+    let tile = ImageTile::new(ColorImage::new([4, 4], Color32::RED));
+    let size = tile.byte_size();
+    let clipped = tile
+        .clip(Rect::from_min_max(pos2(0.0, 0.0), pos2(0.5, 0.5)))
+        .unwrap();
+    let draw_ctx = TileDrawContext::new(
+        &painter,
+        TileQuad::from_rect(painter.clip_rect()),
+        TileStyle::default(),
+    );
+    //多次绘制与裁剪出的子瓦片只上传一次纹理
+    (0..3).for_each(|_| {
+        tile.draw(&draw_ctx);
+        clipped.draw(&draw_ctx);
+    });
+    assert_eq!(allocated(), before + 1);
+    //估算大小在上传前后相同，与内存缓存记录的一致
+    assert_eq!(tile.byte_size(), size);
+    assert_eq!(size, 2 * 4 * 4 * 4);
+    drop(clipped);
+    drop(tile);
+    assert_eq!(allocated(), before);
+}
@@ -6,48 +6,90 @@ use crate::BinTileCache;
 
 pub struct DiskDirTileCache {
     pub cache_path_prefix: String,
+    /// 默认的扩展名，[BinTileCache::save]时使用
     pub file_ext: String,
+    /// 同一瓦片可能使用的其他扩展名，查找与删除时一并检查
+    pub other_exts: Vec<String>,
 }
 
 impl DiskDirTileCache {
-    fn path_of(&self, key: QTreeKey) -> String {
+    pub fn new(cache_path_prefix: impl Into<String>, file_ext: impl Into<String>) -> Self {
+        Self {
+            cache_path_prefix: cache_path_prefix.into(),
+            file_ext: file_ext.into(),
+            other_exts: vec![],
+        }
+    }
+
+    pub fn with_other_exts(mut self, exts: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.other_exts = exts
+            .into_iter()
+            .map(Into::into)
+            .filter(|e| *e != self.file_ext)
+            .collect();
+        self
+    }
+
+    fn path_of(&self, key: QTreeKey, ext: &str) -> String {
         format!(
             "{}/{}_{}_{}.{}",
             self.cache_path_prefix.as_str(),
             key.depth(),
             key.x(),
             key.y(),
-            self.file_ext.as_str()
+            ext
         )
     }
+
+    fn all_paths(&self, key: QTreeKey) -> impl Iterator<Item = String> + '_ {
+        std::iter::once(self.file_ext.as_str())
+            .chain(self.other_exts.iter().map(String::as_str))
+            .map(move |ext| self.path_of(key, ext))
+    }
+
+    /// 已缓存的文件路径
+    fn find(&self, key: QTreeKey) -> Option<String> {
+        self.all_paths(key)
+            .find(|p| fs::exists(p.as_str()).unwrap_or(false))
+    }
 }
 
 impl BinTileCache for DiskDirTileCache {
     fn save(&self, key: QTreeKey, value: Arc<[u8]>) {
+        self.save_as(key, value, self.file_ext.as_str());
+    }
+
+    /// 瓦片格式变化时删除旧扩展名的文件
+    fn save_as(&self, key: QTreeKey, value: Arc<[u8]>, ext: &str) {
         if !fs::exists(self.cache_path_prefix.as_str()).unwrap_or(false) {
             let _ = fs::create_dir_all(self.cache_path_prefix.as_str());
         }
-        let cache_file_path = self.path_of(key);
+        let cache_file_path = self.path_of(key, ext);
         let lock_file_path = format!("{}.tmp", cache_file_path.as_str());
         if fs::exists(lock_file_path.as_str()).unwrap_or(false) {
             return;
         }
         fs::write(lock_file_path.clone(), value).unwrap();
         let _ = fs::rename(lock_file_path, cache_file_path.as_str());
+        self.all_paths(key)
+            .filter(|p| *p != cache_file_path)
+            .for_each(|p| {
+                let _ = fs::remove_file(p);
+            });
     }
 
     fn load(&self, key: QTreeKey) -> Option<Arc<[u8]>> {
-        let cache_file_path = self.path_of(key);
+        let cache_file_path = self.find(key)?;
         return fs::read(cache_file_path.as_str()).map(|v| v.into()).ok();
     }
 
     fn exist(&self, key: QTreeKey) -> bool {
-        let cache_file_path = self.path_of(key);
-        fs::exists(cache_file_path.as_str()).unwrap_or(false)
+        self.find(key).is_some()
     }
 
     fn delete(&self, key: QTreeKey) {
-        let cache_file_path = self.path_of(key);
-        let _ = fs::remove_file(cache_file_path.as_str());
+        self.all_paths(key).for_each(|p| {
+            let _ = fs::remove_file(p);
+        });
     }
 }
@@ -1,20 +1,38 @@
 use std::{
     cell::OnceCell,
-    sync::{Arc, Mutex, RwLock},
+    fmt::Display,
+    sync::{Arc, RwLock},
 };
 
 use dir_tile_cache::DiskDirTileCache;
-use egui::Context;
+use egui::{Context, Id};
 use ehttp::{Request, Response};
-use emap::{tile_drawable::CommonEguiTileDrawable, EguiMapTileRes};
-use rustc_hash::{FxHashMap, FxHashSet};
-use rustitude_base::{curr_time_millis, map_view_state::MapViewState, qtree::QTreeKey};
+use emap::{
+    tile_drawable::{CommonEguiTileDrawable, TileStyle},
+    EguiMapTileRes, TileCacheStats,
+};
+use rustc_hash::FxHashSet;
+use rustitude_base::{
+    latlng::CommonWCS,
+    map_view_state::{MapViewState, TileLevels},
+    qtree::QTreeKey,
+};
 
 pub mod dir_tile_cache;
+pub mod mem_cache;
 #[cfg(feature = "mvt")]
 pub mod mvt;
 #[cfg(feature = "png")]
 pub mod png;
+#[cfg(feature = "raster")]
+pub mod raster;
+
+pub use mem_cache::MemoryDrawableCache;
+
+/// 加载瓦片失败时输出到标准错误
+pub(crate) fn report_error(key: QTreeKey, err: impl Display) {
+    eprintln!("tile {} error:{}", key, err);
+}
 
 pub trait RequestBuilder: Send + Sync {
     fn build_req(&self, typ: &str, x: u32, y: u32, z: u8) -> Request;
@@ -22,77 +40,53 @@ pub trait RequestBuilder: Send + Sync {
     fn decode_response(&self, resp: Response) -> Arc<[u8]> {
         resp.bytes.into()
     }
+
+    /// 瓦片源使用的坐标系，None表示与视图的坐标系相同
+    fn wcs(&self) -> Option<CommonWCS> {
+        None
+    }
+
+    /// 瓦片源提供的瓦片层级与显示的缩放级别范围
+    fn levels(&self) -> TileLevels {
+        TileLevels::default()
+    }
+
+    /// 瓦片源的版权信息
+    fn attribution(&self) -> Option<String> {
+        None
+    }
 }
 
 pub trait TileLoader: Send + Sync {
     fn mem_cache(self: &Self) -> &MemoryDrawableCache;
     fn load_img(self: &Self, key: QTreeKey, ctx: Context, vec: Arc<[u8]>) -> bool;
-}
 
-pub trait BinTileCache: Send + Sync {
-    fn save(&self, key: QTreeKey, value: Arc<[u8]>);
-    fn load(&self, key: QTreeKey) -> Option<Arc<[u8]>>;
-    fn exist(&self, key: QTreeKey) -> bool;
-    fn delete(&self, key: QTreeKey);
-}
-
-pub struct MemoryDrawableCache {
-    data_map: Arc<RwLock<FxHashMap<QTreeKey, CommonEguiTileDrawable>>>,
-    /// 记录每个key的最后一次访问时间，用于清理过期的内存缓存
-    hot_map: Arc<Mutex<FxHashMap<QTreeKey, u128>>>,
-}
-
-impl MemoryDrawableCache {
-    pub fn new() -> Self {
-        Self {
-            data_map: Arc::new(RwLock::new(FxHashMap::default())),
-            hot_map: Arc::new(Mutex::new(FxHashMap::default())),
-        }
+    /// 不依赖egui解码瓦片，用于离屏绘制，结果不放入内存缓存。默认不支持
+    fn decode(&self, _key: QTreeKey, _vec: Arc<[u8]>) -> Option<CommonEguiTileDrawable> {
+        None
     }
 
-    fn get(&self, key: QTreeKey) -> Option<CommonEguiTileDrawable> {
-        if let Some(img) = self.data_map.read().unwrap().get(&key) {
-            if let Ok(mut hm) = self.hot_map.try_lock() {
-                hm.insert(key, curr_time_millis());
-            }
-            return Some(img.clone());
-        }
-        return None;
+    /// 瓦片写入磁盘缓存时使用的扩展名，`content_type`为响应的Content-Type。
+    /// None时使用[EguiMapBinResImpl::new]传入的扩展名
+    fn file_ext(&self, _content_type: Option<&str>, _vec: &[u8]) -> Option<&'static str> {
+        None
     }
 
-    fn put(&self, key: QTreeKey, value: CommonEguiTileDrawable) -> Vec<QTreeKey> {
-        {
-            let mut m = self.data_map.write().unwrap();
-            m.insert(key, value);
-        }
-        let time = curr_time_millis();
-        let mut hot_map = self.hot_map.lock().unwrap();
-        hot_map.insert(key, time);
-        let mut will_del: Vec<QTreeKey> = vec![];
-        if hot_map.len() > 500 {
-            hot_map.iter().for_each(|t| {
-                if t.0.depth() > 3 && time.checked_sub(t.1.clone()).unwrap_or(0) > 20_000 {
-                    will_del.push(t.0.clone());
-                }
-            });
-            if will_del.len() > 0 {
-                let mut m = self.data_map.write().unwrap();
-                will_del.iter().for_each(|k| {
-                    m.remove(k);
-                    hot_map.remove(k);
-                });
-            }
-        }
-        return will_del;
+    /// [TileLoader::file_ext]可能返回的所有扩展名，读取磁盘缓存时依次查找
+    fn file_exts(&self) -> Vec<&'static str> {
+        vec![]
     }
+}
 
-    fn remove(&self, key: QTreeKey) {
-        let mut m = self.data_map.write().unwrap();
-        m.remove(&key);
-
-        let mut hot_map = self.hot_map.lock().unwrap();
-        hot_map.remove(&key);
+pub trait BinTileCache: Send + Sync {
+    fn save(&self, key: QTreeKey, value: Arc<[u8]>);
+    /// 以指定扩展名保存，默认忽略扩展名
+    fn save_as(&self, key: QTreeKey, value: Arc<[u8]>, _ext: &str) {
+        self.save(key, value)
     }
+    fn load(&self, key: QTreeKey) -> Option<Arc<[u8]>>;
+    fn exist(&self, key: QTreeKey) -> bool;
+    fn delete(&self, key: QTreeKey);
 }
 
 struct _EguiMapBinResImpl {
@@ -110,10 +104,13 @@ struct _EguiMapBinResImpl {
 #[derive(Clone)]
 pub struct EguiMapBinResImpl {
     inner: Arc<_EguiMapBinResImpl>,
+    style: TileStyle,
 }
 
 impl EguiMapBinResImpl {
     const TOKIO_RT: OnceCell<Arc<tokio::runtime::Runtime>> = OnceCell::new();
+    /// 离屏绘制时同时获取瓦片的线程数
+    const BLOCKING_CONCURRENCY: usize = 8;
 
     pub fn new(
         typ: &str,
@@ -139,10 +136,9 @@ impl EguiMapBinResImpl {
                 cache: cache_path_prefix
                     .map(|s| format!("{}/{}", s, typ))
                     .map(|s| {
-                        Arc::new(DiskDirTileCache {
-                            cache_path_prefix: s,
-                            file_ext: file_ext.into(),
-                        }) as Arc<dyn BinTileCache>
+                        Arc::new(
+                            DiskDirTileCache::new(s, file_ext).with_other_exts(loader.file_exts()),
+                        ) as Arc<dyn BinTileCache>
                     }),
                 request_builder: request_builder,
 
@@ -151,13 +147,75 @@ impl EguiMapBinResImpl {
                 loader: loader,
                 typ: String::from(typ),
             }),
+            style: TileStyle::default(),
+        }
+    }
+
+    pub fn with_style(mut self, style: TileStyle) -> Self {
+        self.style = style;
+        self
+    }
+
+    /// 写入磁盘缓存，扩展名由[TileLoader::file_ext]决定
+    fn save_to_cache(
+        &self,
+        cache: &dyn BinTileCache,
+        key: QTreeKey,
+        content_type: Option<&str>,
+        bytes: Arc<[u8]>,
+    ) {
+        match self.inner.loader.file_ext(content_type, &bytes) {
+            Some(ext) => cache.save_as(key, bytes, ext),
+            None => cache.save(key, bytes),
         }
     }
+
+    /// 依次从内存缓存、磁盘缓存与网络同步获取瓦片，从网络获取的瓦片会写入磁盘缓存。
+    ///
+    /// 内存缓存中只有纹理的瓦片无法离屏绘制，会跳过并重新解码
+    fn load_one_blocking(&self, key: QTreeKey) -> Option<CommonEguiTileDrawable> {
+        let inner = &self.inner;
+        if let Some(tile) = inner.loader.mem_cache().peek(key) {
+            if tile.is_cpu_drawable() {
+                return Some(tile);
+            }
+        }
+        if let Some(cache) = inner.cache.as_ref() {
+            if let Some(vec) = cache.load(key) {
+                match inner.loader.decode(key, vec) {
+                    Some(tile) => return Some(tile),
+                    None => cache.delete(key),
+                }
+            }
+        }
+        let req =
+            inner
+                .request_builder
+                .build_req(inner.typ.as_str(), key.x(), key.y(), key.depth());
+        let resp = match ehttp::fetch_blocking(&req) {
+            Ok(r) if r.status == 200 => r,
+            Ok(r) => {
+                report_error(key, format_args!("response status {}", r.status));
+                return None;
+            }
+            Err(e) => {
+                report_error(key, e);
+                return None;
+            }
+        };
+        let content_type = resp.content_type().map(str::to_owned);
+        let bytes = inner.request_builder.decode_response(resp);
+        let tile = inner.loader.decode(key, bytes.clone())?;
+        if let Some(cache) = inner.cache.as_ref() {
+            self.save_to_cache(cache.as_ref(), key, content_type.as_deref(), bytes);
+        }
+        Some(tile)
+    }
 }
 
 impl EguiMapTileRes for EguiMapBinResImpl {
     fn get_memory_cache(&self, key: QTreeKey) -> Option<CommonEguiTileDrawable> {
-        return self.inner.loader.mem_cache().get(key);
+        return self.inner.loader.mem_cache().peek(key);
     }
 
     fn get_or_fetch(
@@ -166,7 +224,7 @@ impl EguiMapTileRes for EguiMapBinResImpl {
         mvs: Arc<RwLock<MapViewState>>,
         ctx: &Context,
     ) -> Option<CommonEguiTileDrawable> {
-        let weak = self.get_memory_cache(key);
+        let weak = self.inner.loader.mem_cache().get(key);
         if weak.is_some() {
             return weak;
         }
@@ -175,6 +233,8 @@ impl EguiMapTileRes for EguiMapBinResImpl {
         let y = key.y();
         let c = ctx.clone();
         let s = self.clone();
+        let wcs = self.wcs();
+        let levels = self.levels();
         let mut loading_locks = self.inner.loading_lock.write().unwrap();
         let is_loading = loading_locks.contains(&key.inner_key());
         if let Some(cache) = self.inner.cache.clone() {
@@ -184,14 +244,11 @@ impl EguiMapTileRes for EguiMapBinResImpl {
                 //存在缓存
                 loading_locks.insert(key.inner_key());
                 self.inner.rt.spawn(async move {
-                    let lt;
-                    let rb;
-                    {
-                        let mvs = mvs.read().unwrap();
-                        lt = mvs.top_left_key();
-                        rb = mvs.bottom_right_key();
-                    }
-                    if z == lt.depth() && lt.x() <= x && x <= rb.x() && lt.y() <= y && y <= rb.y() {
+                    let visible = mvs
+                        .read()
+                        .unwrap()
+                        .is_key_visible(key, wcs.as_ref(), &levels);
+                    if visible {
                         if let Some(vec) = cache.load(key) {
                             if !s.inner.loader.load_img(key, c.clone(), vec) {
                                 cache.delete(key);
@@ -209,14 +266,11 @@ impl EguiMapTileRes for EguiMapBinResImpl {
             } else {
                 loading_locks.insert(key.inner_key());
                 self.inner.rt.spawn(async move {
-                    let lt;
-                    let rb;
-                    {
-                        let mvs = mvs.read().unwrap();
-                        lt = mvs.top_left_key();
-                        rb = mvs.bottom_right_key();
-                    }
-                    if z == lt.depth() && lt.x() <= x && x <= rb.x() && lt.y() <= y && y <= rb.y() {
+                    let visible = mvs
+                        .read()
+                        .unwrap()
+                        .is_key_visible(key, wcs.as_ref(), &levels);
+                    if visible {
                         println!("fetch:{}_{}_{}", z, x, y);
                         let req = s
                             .inner
@@ -225,11 +279,17 @@ impl EguiMapTileRes for EguiMapBinResImpl {
                         let resp = ehttp::fetch_blocking(&req);
                         if let Ok(r) = resp {
                             if r.status == 200 {
+                                let content_type = r.content_type().map(str::to_owned);
                                 let bytes: Arc<[u8]> = s.inner.request_builder.decode_response(r);
                                 if !s.inner.loader.load_img(key, c.clone(), bytes.clone()) {
                                     cache.delete(key);
                                 } else {
-                                    cache.save(key, bytes);
+                                    s.save_to_cache(
+                                        cache.as_ref(),
+                                        key,
+                                        content_type.as_deref(),
+                                        bytes,
+                                    );
                                     c.request_repaint();
                                 }
                             } else {
@@ -253,14 +313,11 @@ impl EguiMapTileRes for EguiMapBinResImpl {
             } else {
                 loading_locks.insert(key.inner_key());
                 self.inner.rt.spawn(async move {
-                    let lt;
-                    let rb;
-                    {
-                        let mvs = mvs.read().unwrap();
-                        lt = mvs.top_left_key();
-                        rb = mvs.bottom_right_key();
-                    }
-                    if z == lt.depth() && lt.x() <= x && x <= rb.x() && lt.y() <= y && y <= rb.y() {
+                    let visible = mvs
+                        .read()
+                        .unwrap()
+                        .is_key_visible(key, wcs.as_ref(), &levels);
+                    if visible {
                         println!("fetch:{}_{}_{}", z, x, y);
                         let req = s
                             .inner
@@ -288,4 +345,86 @@ impl EguiMapTileRes for EguiMapBinResImpl {
             }
         }
     }
+
+    fn wcs(&self) -> Option<CommonWCS> {
+        self.inner.request_builder.wcs()
+    }
+
+    fn levels(&self) -> TileLevels {
+        self.inner.request_builder.levels()
+    }
+
+    fn tile_style(&self) -> TileStyle {
+        self.style
+    }
+
+    fn attribution(&self) -> Option<String> {
+        self.inner.request_builder.attribution()
+    }
+
+    fn pin_visible(&self, view: Id, keys: &[QTreeKey]) {
+        self.inner.loader.mem_cache().pin(view, keys);
+    }
+
+    fn unpin_view(&self, view: Id) {
+        self.inner.loader.mem_cache().unpin(view);
+    }
+
+    fn cache_stats(&self) -> Option<TileCacheStats> {
+        Some(self.inner.loader.mem_cache().stats())
+    }
+
+    /// 每次最多同时获取[EguiMapBinResImpl::BLOCKING_CONCURRENCY]个瓦片
+    fn load_blocking(&self, keys: &[QTreeKey]) -> Vec<Option<CommonEguiTileDrawable>> {
+        keys.chunks(Self::BLOCKING_CONCURRENCY)
+            .flat_map(|chunk| {
+                std::thread::scope(|s| {
+                    let handles: Vec<_> = chunk
+                        .iter()
+                        .map(|k| s.spawn(move || self.load_one_blocking(*k)))
+                        .collect();
+                    handles
+                        .into_iter()
+                        .map(|h| h.join().ok().flatten())
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect()
+    }
+}
+
+#[test]
+fn test_load_blocking_mem_cache() {
+    use egui::{load::SizedTexture, TextureId};
+
+    struct LocalRequest;
+    impl RequestBuilder for LocalRequest {
+        fn build_req(&self, _typ: &str, x: u32, y: u32, z: u8) -> Request {
+            Request::get(format!("http://127.0.0.1:1/{}/{}/{}", z, x, y))
+        }
+    }
+    #[derive(Default)]
+    struct MemLoader(MemoryDrawableCache);
+    impl TileLoader for MemLoader {
+        fn mem_cache(&self) -> &MemoryDrawableCache {
+            &self.0
+        }
+        fn load_img(&self, _key: QTreeKey, _ctx: Context, _vec: Arc<[u8]>) -> bool {
+            false
+        }
+    }
+
+    //没有磁盘缓存时，内存缓存中可以离屏绘制的瓦片不需要重新下载，纹理瓦片被跳过
+    let loader = MemLoader::default();
+    let (cpu, texture) = (
+        QTreeKey::new(1, 0, 0).unwrap(),
+        QTreeKey::new(1, 1, 0).unwrap(),
+    );
+    loader.0.put(cpu, Arc::new(cpu));
+    let sized = SizedTexture::new(TextureId::Managed(1), [256.0, 256.0]);
+    loader.0.put(texture, Arc::new(sized));
+    let res = EguiMapBinResImpl::new("mem", "bin", None, Box::new(LocalRequest), Box::new(loader));
+    let tiles = res.load_blocking(&[cpu, texture]);
+    assert!(tiles[0].is_some());
+    assert!(tiles[1].is_none());
 }
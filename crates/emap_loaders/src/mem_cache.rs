@@ -0,0 +1,469 @@
+use std::{collections::BTreeMap, sync::Mutex};
+
+use egui::Id;
+use emap::{tile_drawable::CommonEguiTileDrawable, TileCacheStats};
+use rustc_hash::{FxHashMap, FxHashSet};
+use rustitude_base::qtree::QTreeKey;
+
+/// 内存缓存的淘汰策略，只记录key的访问情况，瓦片由[MemoryDrawableCache]保存
+pub trait EvictionPolicy: Send {
+    /// 放入不在缓存中的key
+    fn insert(&mut self, key: QTreeKey);
+    /// 访问已在缓存中的key
+    fn access(&mut self, key: QTreeKey);
+    /// 手动移除key
+    fn remove(&mut self, key: QTreeKey);
+    /// 选出并移除下一个被淘汰的key，`pinned`返回true的key不能被淘汰
+    fn evict(&mut self, pinned: &dyn Fn(QTreeKey) -> bool) -> Option<QTreeKey>;
+}
+
+/// 按访问先后排列的key。
+///
+/// 哈希表都以[QTreeKey::inner_key]为键，[QTreeKey]的哈希不区分不同的key
+#[derive(Default)]
+struct RecencyList {
+    order: BTreeMap<u64, QTreeKey>,
+    ticks: FxHashMap<u64, u64>,
+    tick: u64,
+}
+
+impl RecencyList {
+    /// 放到最近访问的一端，已存在时移动过去
+    fn touch(&mut self, key: QTreeKey) {
+        self.remove(key);
+        self.tick += 1;
+        self.order.insert(self.tick, key);
+        self.ticks.insert(key.inner_key(), self.tick);
+    }
+
+    fn remove(&mut self, key: QTreeKey) -> bool {
+        match self.ticks.remove(&key.inner_key()) {
+            Some(tick) => {
+                self.order.remove(&tick);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn contains(&self, key: QTreeKey) -> bool {
+        self.ticks.contains_key(&key.inner_key())
+    }
+
+    fn len(&self) -> usize {
+        self.ticks.len()
+    }
+
+    /// 最久未访问且未被固定的key
+    fn oldest(&self, pinned: &dyn Fn(QTreeKey) -> bool) -> Option<QTreeKey> {
+        self.order.values().copied().find(|k| !pinned(*k))
+    }
+
+    fn pop_oldest(&mut self) -> Option<QTreeKey> {
+        let (_, key) = self.order.pop_first()?;
+        self.ticks.remove(&key.inner_key());
+        Some(key)
+    }
+}
+
+/// 淘汰最久未访问的瓦片
+#[derive(Default)]
+pub struct LruPolicy {
+    list: RecencyList,
+}
+
+impl EvictionPolicy for LruPolicy {
+    fn insert(&mut self, key: QTreeKey) {
+        self.list.touch(key);
+    }
+
+    fn access(&mut self, key: QTreeKey) {
+        self.list.touch(key);
+    }
+
+    fn remove(&mut self, key: QTreeKey) {
+        self.list.remove(key);
+    }
+
+    fn evict(&mut self, pinned: &dyn Fn(QTreeKey) -> bool) -> Option<QTreeKey> {
+        let key = self.list.oldest(pinned)?;
+        self.list.remove(key);
+        Some(key)
+    }
+}
+
+/// 淘汰访问次数最少的瓦片，次数相同时淘汰最久未访问的
+#[derive(Default)]
+pub struct LfuPolicy {
+    /// 按(访问次数, 最后访问顺序)排列
+    order: BTreeMap<(u64, u64), QTreeKey>,
+    entries: FxHashMap<u64, (u64, u64)>,
+    tick: u64,
+}
+
+impl LfuPolicy {
+    fn set_count(&mut self, key: QTreeKey, count: u64) {
+        self.remove(key);
+        self.tick += 1;
+        self.order.insert((count, self.tick), key);
+        self.entries.insert(key.inner_key(), (count, self.tick));
+    }
+}
+
+impl EvictionPolicy for LfuPolicy {
+    fn insert(&mut self, key: QTreeKey) {
+        self.set_count(key, 1);
+    }
+
+    fn access(&mut self, key: QTreeKey) {
+        let count = self.entries.get(&key.inner_key()).map_or(0, |e| e.0);
+        self.set_count(key, count + 1);
+    }
+
+    fn remove(&mut self, key: QTreeKey) {
+        if let Some(e) = self.entries.remove(&key.inner_key()) {
+            self.order.remove(&e);
+        }
+    }
+
+    fn evict(&mut self, pinned: &dyn Fn(QTreeKey) -> bool) -> Option<QTreeKey> {
+        let key = self.order.values().copied().find(|k| !pinned(*k))?;
+        self.remove(key);
+        Some(key)
+    }
+}
+
+/// 自适应替换（ARC），在只访问过一次与访问过多次的瓦片之间自动分配容量。
+///
+/// 记录最近淘汰的key，它们再次放入时按来源调整分配的比例
+#[derive(Default)]
+pub struct ArcPolicy {
+    /// 只访问过一次的key
+    t1: RecencyList,
+    /// 访问过多次的key
+    t2: RecencyList,
+    /// 从t1淘汰的key
+    b1: RecencyList,
+    /// 从t2淘汰的key
+    b2: RecencyList,
+    /// t1的目标数量
+    p: f64,
+}
+
+impl ArcPolicy {
+    /// 淘汰记录的数量不超过缓存中的数量
+    fn trim_ghosts(&mut self) {
+        let c = (self.t1.len() + self.t2.len()).max(1);
+        while self.b1.len() > c {
+            self.b1.pop_oldest();
+        }
+        while self.b2.len() > c {
+            self.b2.pop_oldest();
+        }
+    }
+}
+
+impl EvictionPolicy for ArcPolicy {
+    fn insert(&mut self, key: QTreeKey) {
+        let c = (self.t1.len() + self.t2.len() + 1) as f64;
+        let (b1, b2) = (self.b1.len().max(1) as f64, self.b2.len().max(1) as f64);
+        if self.b1.remove(key) {
+            //最近从t1淘汰的key又被用到，t1应该更大
+            self.p = (self.p + (b2 / b1).max(1.0)).min(c);
+            self.t2.touch(key);
+        } else if self.b2.remove(key) {
+            self.p = (self.p - (b1 / b2).max(1.0)).max(0.0);
+            self.t2.touch(key);
+        } else {
+            self.t1.touch(key);
+        }
+        self.trim_ghosts();
+    }
+
+    fn access(&mut self, key: QTreeKey) {
+        if self.t1.remove(key) || self.t2.contains(key) {
+            self.t2.touch(key);
+        }
+    }
+
+    fn remove(&mut self, key: QTreeKey) {
+        self.t1.remove(key);
+        self.t2.remove(key);
+        self.b1.remove(key);
+        self.b2.remove(key);
+    }
+
+    fn evict(&mut self, pinned: &dyn Fn(QTreeKey) -> bool) -> Option<QTreeKey> {
+        let key = if self.t1.len() as f64 > self.p {
+            self.t1.oldest(pinned).or_else(|| self.t2.oldest(pinned))
+        } else {
+            self.t2.oldest(pinned).or_else(|| self.t1.oldest(pinned))
+        }?;
+        if self.t1.remove(key) {
+            self.b1.touch(key);
+        } else {
+            self.t2.remove(key);
+            self.b2.touch(key);
+        }
+        self.trim_ghosts();
+        Some(key)
+    }
+}
+
+/// 每个瓦片在估算大小之外额外计入的字节数，避免估算大小为0的瓦片不受限制
+const ENTRY_OVERHEAD: usize = 256;
+
+struct CacheEntry {
+    key: QTreeKey,
+    tile: CommonEguiTileDrawable,
+    bytes: usize,
+}
+
+struct CacheState {
+    tiles: FxHashMap<u64, CacheEntry>,
+    policy: Box<dyn EvictionPolicy>,
+    /// 每个视图可见的瓦片及其祖先，不会被淘汰
+    pinned: FxHashMap<Id, FxHashSet<u64>>,
+    bytes: usize,
+    budget: usize,
+    hits: u64,
+    misses: u64,
+    evictions: u64,
+}
+
+impl CacheState {
+    /// 淘汰瓦片直到不超过大小上限，`keep`为刚放入的key。
+    /// 返回被淘汰的瓦片，由调用方在释放锁后再释放
+    fn evict(&mut self, keep: QTreeKey) -> Vec<CommonEguiTileDrawable> {
+        let mut evicted = vec![];
+        while self.bytes > self.budget {
+            let pinned = &self.pinned;
+            let is_pinned =
+                |k: QTreeKey| k == keep || pinned.values().any(|p| p.contains(&k.inner_key()));
+            let Some(key) = self.policy.evict(&is_pinned) else {
+                break;
+            };
+            if let Some(entry) = self.tiles.remove(&key.inner_key()) {
+                self.bytes -= entry.bytes;
+                self.evictions += 1;
+                evicted.push(entry.tile);
+            }
+        }
+        evicted
+    }
+}
+
+/// 按估算字节数限制大小的瓦片内存缓存，超出上限时按[EvictionPolicy]淘汰。
+///
+/// 瓦片被淘汰或移除后，其持有的纹理在最后一个引用释放时随之释放
+pub struct MemoryDrawableCache {
+    state: Mutex<CacheState>,
+}
+
+impl Default for MemoryDrawableCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MemoryDrawableCache {
+    /// 默认的大小上限，约可容纳一千个256×256的栅格瓦片
+    pub const DEFAULT_BUDGET: usize = 256 << 20;
+
+    pub fn new() -> Self {
+        Self {
+            state: Mutex::new(CacheState {
+                tiles: FxHashMap::default(),
+                policy: Box::new(LruPolicy::default()),
+                pinned: FxHashMap::default(),
+                bytes: 0,
+                budget: Self::DEFAULT_BUDGET,
+                hits: 0,
+                misses: 0,
+                evictions: 0,
+            }),
+        }
+    }
+
+    /// 大小上限，单位为字节
+    pub fn with_budget(self, budget: usize) -> Self {
+        self.state.lock().unwrap().budget = budget;
+        self
+    }
+
+    pub fn with_policy(self, policy: impl EvictionPolicy + 'static) -> Self {
+        {
+            let mut state = self.state.lock().unwrap();
+            state.policy = Box::new(policy);
+            let keys: Vec<QTreeKey> = state.tiles.values().map(|e| e.key).collect();
+            keys.into_iter().for_each(|k| state.policy.insert(k));
+        }
+        self
+    }
+
+    /// 获取瓦片并计入命中统计
+    pub fn get(&self, key: QTreeKey) -> Option<CommonEguiTileDrawable> {
+        let mut state = self.state.lock().unwrap();
+        match state.tiles.get(&key.inner_key()).map(|e| e.tile.clone()) {
+            Some(tile) => {
+                state.hits += 1;
+                state.policy.access(key);
+                Some(tile)
+            }
+            None => {
+                state.misses += 1;
+                None
+            }
+        }
+    }
+
+    /// 获取瓦片，不计入统计也不影响淘汰顺序，用于查找替代瓦片等场景
+    pub fn peek(&self, key: QTreeKey) -> Option<CommonEguiTileDrawable> {
+        self.state
+            .lock()
+            .unwrap()
+            .tiles
+            .get(&key.inner_key())
+            .map(|e| e.tile.clone())
+    }
+
+    /// 放入瓦片，超出大小上限时淘汰其他未固定的瓦片
+    pub fn put(&self, key: QTreeKey, value: CommonEguiTileDrawable) {
+        let bytes = value.byte_size() + ENTRY_OVERHEAD;
+        let mut state = self.state.lock().unwrap();
+        let entry = CacheEntry {
+            key,
+            tile: value,
+            bytes,
+        };
+        match state.tiles.insert(key.inner_key(), entry) {
+            Some(old) => {
+                state.bytes -= old.bytes;
+                state.policy.access(key);
+            }
+            None => state.policy.insert(key),
+        }
+        state.bytes += bytes;
+        let evicted = state.evict(key);
+        drop(state);
+        drop(evicted);
+    }
+
+    pub fn remove(&self, key: QTreeKey) {
+        let mut state = self.state.lock().unwrap();
+        let removed = state.tiles.remove(&key.inner_key());
+        if let Some(entry) = &removed {
+            state.bytes -= entry.bytes;
+            state.policy.remove(key);
+        }
+        drop(state);
+        drop(removed);
+    }
+
+    /// 固定视图`view`中的`keys`及其祖先，替换该视图上一次固定的key，放大时父瓦片仍可作为替代瓦片。
+    /// 淘汰时保留所有视图固定的key，`keys`为空时取消该视图的固定
+    pub fn pin(&self, view: Id, keys: &[QTreeKey]) {
+        let mut pinned = FxHashSet::default();
+        keys.iter().for_each(|k| {
+            let mut key = Some(*k);
+            while let Some(k) = key {
+                //祖先已经加入过
+                if !pinned.insert(k.inner_key()) {
+                    break;
+                }
+                key = k.parent();
+            }
+        });
+        let mut state = self.state.lock().unwrap();
+        if pinned.is_empty() {
+            state.pinned.remove(&view);
+        } else {
+            state.pinned.insert(view, pinned);
+        }
+    }
+
+    /// 取消视图`view`的固定，视图关闭后其固定的瓦片可以被淘汰
+    pub fn unpin(&self, view: Id) {
+        self.state.lock().unwrap().pinned.remove(&view);
+    }
+
+    pub fn stats(&self) -> TileCacheStats {
+        let state = self.state.lock().unwrap();
+        TileCacheStats {
+            hits: state.hits,
+            misses: state.misses,
+            evictions: state.evictions,
+            entries: state.tiles.len(),
+            bytes: state.bytes,
+            budget: state.budget,
+        }
+    }
+}
+
+#[test]
+fn test_memory_cache() {
+    use std::sync::Arc;
+    let tile = |k: QTreeKey| -> CommonEguiTileDrawable { Arc::new(k) };
+    let root = QTreeKey::root();
+    let keys: Vec<QTreeKey> = [
+        root.child_lt(),
+        root.child_rt(),
+        root.child_lb(),
+        root.child_rb(),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+
+    //QTreeKey的估算大小为0，每个瓦片只计入ENTRY_OVERHEAD
+    let cache = MemoryDrawableCache::new().with_budget(ENTRY_OVERHEAD * 3);
+    keys[..3].iter().for_each(|k| cache.put(*k, tile(*k)));
+    assert!(cache.get(keys[0]).is_some());
+    cache.put(keys[3], tile(keys[3]));
+    assert!(cache.peek(keys[1]).is_none());
+    let stats = cache.stats();
+    assert_eq!((stats.entries, stats.evictions, stats.hits), (3, 1, 1));
+
+    //固定的key及其祖先不会被淘汰
+    let (main, minimap) = (Id::new("main"), Id::new("minimap"));
+    cache.pin(main, &[keys[2].child_lt().unwrap()]);
+    cache.put(root, tile(root));
+    assert!(cache.peek(keys[2]).is_some());
+    assert!(cache.peek(keys[0]).is_none());
+    cache.put(keys[1], tile(keys[1]));
+    assert!(cache.peek(root).is_some());
+    assert!(cache.peek(keys[3]).is_none());
+
+    //共享缓存的另一个视图固定时不会覆盖前一个视图的固定
+    cache.pin(minimap, &[keys[1]]);
+    cache.put(keys[0], tile(keys[0]));
+    assert!(cache.peek(keys[2]).is_some());
+    assert!(cache.peek(keys[1]).is_some());
+    assert!(cache.peek(keys[0]).is_some());
+    cache.unpin(main);
+    cache.put(keys[3], tile(keys[3]));
+    assert!(cache.peek(keys[2]).is_none());
+    assert!(cache.peek(keys[1]).is_some());
+
+    //LRU淘汰最久未访问的，LFU淘汰访问次数最少的
+    let lfu = MemoryDrawableCache::new()
+        .with_budget(ENTRY_OVERHEAD * 2)
+        .with_policy(LfuPolicy::default());
+    lfu.put(keys[0], tile(keys[0]));
+    lfu.get(keys[0]);
+    lfu.put(keys[1], tile(keys[1]));
+    lfu.put(keys[2], tile(keys[2]));
+    assert!(lfu.peek(keys[0]).is_some());
+    assert!(lfu.peek(keys[1]).is_none());
+
+    //ARC中刚淘汰的key再次放入时进入t2
+    let mut arc = ArcPolicy::default();
+    arc.insert(keys[0]);
+    arc.insert(keys[1]);
+    arc.access(keys[0]);
+    assert_eq!(arc.evict(&|_| false), Some(keys[1]));
+    arc.insert(keys[1]);
+    assert!(arc.t2.contains(keys[1]) && arc.p > 0.0);
+    assert_eq!(arc.evict(&|k| k == keys[1]), Some(keys[0]));
+    assert_eq!(arc.evict(&|k| k == keys[1]), None);
+}
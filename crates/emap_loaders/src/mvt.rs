@@ -2,9 +2,12 @@ use std::sync::Arc;
 
 use crate::{MemoryDrawableCache, TileLoader};
 use egui::{Align2, Color32, Context, FontId, Pos2};
-use emap::tile_drawable::EguiTileDrawable;
+use emap::{
+    pick::FeatureInfo,
+    tile_drawable::{CommonEguiTileDrawable, EguiTileDrawable, TileDrawContext, TileQuad},
+};
 use rustitude_base::qtree::QTreeKey;
-use rustitude_mvt::mvt::tile::{Feature, Layer, Tile};
+use rustitude_mvt::mvt::tile::{Feature, Geometry, Layer, Tile, Value};
 
 pub struct MvtLoader {
     pub typ: String,
@@ -14,7 +17,7 @@ pub struct MvtLoader {
 impl TileLoader for MvtLoader {
     fn load_img(self: &Self, key: QTreeKey, ctx: Context, vec: Arc<[u8]>) -> bool {
         if let Ok(mvt) = Tile::decode(&vec) {
-            let _ = self.mem_cache.put(key, Arc::new(MvtLayer(mvt.layers)));
+            self.mem_cache.put(key, Arc::new(MvtLayer(mvt.layers)));
             true
         } else {
             false
@@ -24,41 +27,40 @@ impl TileLoader for MvtLoader {
     fn mem_cache(self: &Self) -> &MemoryDrawableCache {
         &self.mem_cache
     }
+
+    fn decode(&self, _key: QTreeKey, vec: Arc<[u8]>) -> Option<CommonEguiTileDrawable> {
+        let mvt = Tile::decode(&vec).ok()?;
+        Some(Arc::new(MvtLayer(mvt.layers)))
+    }
 }
 
 pub struct MvtLayer(Vec<Layer>);
 impl EguiTileDrawable for MvtLayer {
-    fn draw(&self, painter: &egui::Painter, rect: egui::Rect) {
-        let width = rect.width();
-        let height = rect.height();
+    fn draw(&self, ctx: &TileDrawContext) {
+        let (canvas, quad) = (ctx.canvas, ctx.quad);
         self.0.iter().for_each(|l| {
             l.features.iter().for_each(|f| {
                 match &f.geometry {
                     rustitude_mvt::mvt::tile::Geometry::UnKnown => {}
                     rustitude_mvt::mvt::tile::Geometry::Point { points } => {
-                        points
-                            .iter()
-                            .map(|p| {
-                                Pos2::new((rect.min.x) + width * p.0, (rect.min.y) + height * p.1)
-                            })
-                            .for_each(|p| {
-                                // painter.circle_filled(p, 2.0, Color32::RED);
-                                let name = f
-                                    .props
-                                    .get("name")
-                                    .map(|v| v.string_value())
-                                    .unwrap_or_default();
-                                painter.text(
-                                    p,
-                                    Align2::CENTER_CENTER,
-                                    format!("{}\n{}", name, l.name),
-                                    FontId {
-                                        size: 8.0,
-                                        family: egui::FontFamily::Monospace,
-                                    },
-                                    Color32::WHITE,
-                                );
-                            });
+                        points.iter().map(|p| quad.project(p.0, p.1)).for_each(|p| {
+                            // painter.circle_filled(p, 2.0, Color32::RED);
+                            let name = f
+                                .props
+                                .get("name")
+                                .map(|v| v.string_value())
+                                .unwrap_or_default();
+                            canvas.text(
+                                p,
+                                Align2::CENTER_CENTER,
+                                &format!("{}\n{}", name, l.name),
+                                FontId {
+                                    size: 8.0,
+                                    family: egui::FontFamily::Monospace,
+                                },
+                                ctx.style.color(Color32::WHITE),
+                            );
+                        });
                     }
                 };
             })
@@ -69,4 +71,72 @@ impl EguiTileDrawable for MvtLayer {
     fn clip(&self, rect: egui::Rect) -> Option<emap::tile_drawable::CommonEguiTileDrawable> {
         return None;
     }
+
+    fn pick(&self, quad: TileQuad, pos: Pos2, tolerance: f32) -> Vec<FeatureInfo> {
+        let mut picked = vec![];
+        self.0.iter().for_each(|l| {
+            l.features.iter().for_each(|f| {
+                let distance = match &f.geometry {
+                    Geometry::Point { points } => points
+                        .iter()
+                        .map(|p| quad.project(p.0, p.1).distance(pos))
+                        .fold(f32::INFINITY, f32::min),
+                    Geometry::UnKnown => f32::INFINITY,
+                };
+                if distance > tolerance {
+                    return;
+                }
+                let mut props: Vec<(String, String)> = f
+                    .props
+                    .iter()
+                    .map(|(k, v)| (k.clone(), value_to_string(v)))
+                    .collect();
+                props.sort();
+                props.insert(0, (String::from("layer"), l.name.clone()));
+                picked.push(FeatureInfo {
+                    //mvt中id为0表示没有id
+                    id: (f.id != 0).then_some(f.id),
+                    name: f.props.get("name").map(value_to_string).unwrap_or_default(),
+                    props,
+                    distance,
+                });
+            })
+        });
+        picked
+    }
+
+    /// 按要素、坐标与属性的数量估算
+    fn byte_size(&self) -> usize {
+        self.0
+            .iter()
+            .flat_map(|l| l.features.iter())
+            .map(|f| {
+                let points = match &f.geometry {
+                    Geometry::Point { points } => std::mem::size_of_val(points.as_slice()),
+                    Geometry::UnKnown => 0,
+                };
+                std::mem::size_of::<Feature>()
+                    + points
+                    + f.props.len() * std::mem::size_of::<(String, Value)>()
+            })
+            .sum()
+    }
+}
+
+fn value_to_string(v: &Value) -> String {
+    if let Some(s) = &v.string_value {
+        s.clone()
+    } else if let Some(f) = v.float_value {
+        f.to_string()
+    } else if let Some(d) = v.double_value {
+        d.to_string()
+    } else if let Some(i) = v.int_value.or(v.sint_value) {
+        i.to_string()
+    } else if let Some(u) = v.uint_value {
+        u.to_string()
+    } else if let Some(b) = v.bool_value {
+        b.to_string()
+    } else {
+        String::new()
+    }
 }
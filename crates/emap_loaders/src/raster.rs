@@ -0,0 +1,254 @@
+use std::{error::Error, fmt::Display, sync::Arc};
+
+use egui::{ColorImage, Context, TextureOptions};
+use emap::tile_drawable::{CommonEguiTileDrawable, ImageTile, TextureTile};
+use image::ImageFormat;
+use rustitude_base::qtree::QTreeKey;
+
+use crate::{report_error, MemoryDrawableCache, TileLoader};
+
+/// 栅格瓦片的图片格式
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RasterFormat {
+    Png,
+    Jpeg,
+    WebP,
+    Avif,
+}
+
+impl RasterFormat {
+    pub const ALL: [RasterFormat; 4] = [
+        RasterFormat::Png,
+        RasterFormat::Jpeg,
+        RasterFormat::WebP,
+        RasterFormat::Avif,
+    ];
+
+    /// 按文件头识别格式，不依赖URL或Content-Type
+    pub fn sniff(bytes: &[u8]) -> Option<Self> {
+        match bytes {
+            [0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a, ..] => Some(RasterFormat::Png),
+            [0xff, 0xd8, 0xff, ..] => Some(RasterFormat::Jpeg),
+            [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => {
+                Some(RasterFormat::WebP)
+            }
+            [_, _, _, _, b'f', b't', b'y', b'p', b'a', b'v', b'i', b'f' | b's', ..] => {
+                Some(RasterFormat::Avif)
+            }
+            _ => None,
+        }
+    }
+
+    /// 由HTTP响应的Content-Type识别格式，忽略参数与大小写
+    pub fn from_content_type(content_type: &str) -> Option<Self> {
+        let mime = content_type.split(';').next()?.trim().to_ascii_lowercase();
+        match mime.as_str() {
+            "image/png" => Some(RasterFormat::Png),
+            "image/jpeg" | "image/jpg" | "image/pjpeg" => Some(RasterFormat::Jpeg),
+            "image/webp" => Some(RasterFormat::WebP),
+            "image/avif" => Some(RasterFormat::Avif),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            RasterFormat::Png => "PNG",
+            RasterFormat::Jpeg => "JPEG",
+            RasterFormat::WebP => "WebP",
+            RasterFormat::Avif => "AVIF",
+        }
+    }
+
+    /// 磁盘缓存使用的扩展名
+    pub fn extension(&self) -> &'static str {
+        match self {
+            RasterFormat::Png => "png",
+            RasterFormat::Jpeg => "jpg",
+            RasterFormat::WebP => "webp",
+            RasterFormat::Avif => "avif",
+        }
+    }
+
+    pub fn image_format(&self) -> ImageFormat {
+        match self {
+            RasterFormat::Png => ImageFormat::Png,
+            RasterFormat::Jpeg => ImageFormat::Jpeg,
+            RasterFormat::WebP => ImageFormat::WebP,
+            RasterFormat::Avif => ImageFormat::Avif,
+        }
+    }
+
+    /// 当前编译的image能否解码该格式，AVIF需要启用image的`avif-native`特性
+    pub fn is_supported(&self) -> bool {
+        self.image_format().reading_enabled()
+    }
+}
+
+/// 栅格瓦片加载器，支持PNG、JPEG、WebP，以及image支持时的AVIF。
+///
+/// 格式按文件头识别，瓦片服务返回的格式与URL的扩展名不一致时也能正确解码。
+/// 瓦片在加载线程中解码后直接上传为纹理，从内存缓存中移除后纹理自动释放
+pub struct RasterLoader {
+    pub typ: String,
+    pub mem_cache: MemoryDrawableCache,
+    pub texture_options: TextureOptions,
+}
+
+impl RasterLoader {
+    pub fn new(typ: impl Into<String>) -> Self {
+        Self {
+            typ: typ.into(),
+            mem_cache: MemoryDrawableCache::new(),
+            texture_options: TextureOptions::LINEAR,
+        }
+    }
+
+    pub fn with_texture_options(mut self, texture_options: TextureOptions) -> Self {
+        self.texture_options = texture_options;
+        self
+    }
+
+    /// 解码为[ColorImage]
+    pub fn decode_image(vec: &[u8]) -> Result<ColorImage, RasterDecodeError> {
+        let format = RasterFormat::sniff(vec).ok_or(RasterDecodeError::UnknownFormat)?;
+        if !format.is_supported() {
+            return Err(RasterDecodeError::Unsupported(format));
+        }
+        let img = image::load_from_memory_with_format(vec, format.image_format())
+            .map_err(|e| RasterDecodeError::Image(e.to_string()))?
+            .to_rgba8();
+        let size = [img.width() as usize, img.height() as usize];
+        Ok(ColorImage::from_rgba_unmultiplied(size, img.as_raw()))
+    }
+}
+
+/// [RasterLoader::decode_image]的错误
+#[derive(Clone, Debug, PartialEq)]
+pub enum RasterDecodeError {
+    /// 无法从文件头识别格式
+    UnknownFormat,
+    /// 格式可以识别，但没有启用对应的解码器
+    Unsupported(RasterFormat),
+    Image(String),
+}
+
+impl Display for RasterDecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RasterDecodeError::UnknownFormat => write!(f, "unknown image format"),
+            RasterDecodeError::Unsupported(format) => {
+                write!(f, "{} is not supported", format.name())
+            }
+            RasterDecodeError::Image(e) => write!(f, "decode error:{}", e),
+        }
+    }
+}
+
+impl Error for RasterDecodeError {}
+
+/// 解码并上传为纹理瓦片放入`mem_cache`，解码失败时移除旧的瓦片
+pub(crate) fn load_texture_tile(
+    typ: &str,
+    mem_cache: &MemoryDrawableCache,
+    texture_options: TextureOptions,
+    key: QTreeKey,
+    ctx: &Context,
+    vec: &[u8],
+) -> bool {
+    let image = match RasterLoader::decode_image(vec) {
+        Ok(image) => image,
+        Err(e) => {
+            report_error(key, e);
+            mem_cache.remove(key);
+            return false;
+        }
+    };
+    let name = format!("tiles/{}/{}", typ, key);
+    let texture = ctx.load_texture(name, image, texture_options);
+    mem_cache.put(key, Arc::new(TextureTile::new(texture)));
+    true
+}
+
+/// 解码为可以离屏绘制的[ImageTile]
+pub(crate) fn decode_image_tile(key: QTreeKey, vec: &[u8]) -> Option<CommonEguiTileDrawable> {
+    match RasterLoader::decode_image(vec) {
+        Ok(image) => Some(Arc::new(ImageTile::new(image))),
+        Err(e) => {
+            report_error(key, e);
+            None
+        }
+    }
+}
+
+impl TileLoader for RasterLoader {
+    /// 在调用线程中解码并上传纹理，[EguiMapBinResImpl](crate::EguiMapBinResImpl)在tokio线程池中调用
+    fn load_img(&self, key: QTreeKey, ctx: Context, vec: Arc<[u8]>) -> bool {
+        load_texture_tile(
+            &self.typ,
+            &self.mem_cache,
+            self.texture_options,
+            key,
+            &ctx,
+            &vec,
+        )
+    }
+
+    fn mem_cache(&self) -> &MemoryDrawableCache {
+        &self.mem_cache
+    }
+
+    fn decode(&self, key: QTreeKey, vec: Arc<[u8]>) -> Option<CommonEguiTileDrawable> {
+        decode_image_tile(key, &vec)
+    }
+
+    /// 优先按文件头识别，无法识别时使用Content-Type
+    fn file_ext(&self, content_type: Option<&str>, vec: &[u8]) -> Option<&'static str> {
+        RasterFormat::sniff(vec)
+            .or_else(|| content_type.and_then(RasterFormat::from_content_type))
+            .map(|f| f.extension())
+    }
+
+    fn file_exts(&self) -> Vec<&'static str> {
+        RasterFormat::ALL.iter().map(|f| f.extension()).collect()
+    }
+}
+
+#[test]
+fn test_sniff() {
+    let png = [
+        0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a, 0, 0, 0, 0x0d,
+    ];
+    assert_eq!(RasterFormat::sniff(&png), Some(RasterFormat::Png));
+    assert_eq!(
+        RasterFormat::sniff(&[0xff, 0xd8, 0xff, 0xe0]),
+        Some(RasterFormat::Jpeg)
+    );
+    assert_eq!(
+        RasterFormat::sniff(b"RIFF\x24\0\0\0WEBPVP8 "),
+        Some(RasterFormat::WebP)
+    );
+    assert_eq!(
+        RasterFormat::sniff(b"\0\0\0\x1cftypavif\0\0\0\0"),
+        Some(RasterFormat::Avif)
+    );
+    assert_eq!(RasterFormat::sniff(b"\x1a\x45"), None);
+    assert_eq!(
+        RasterLoader::decode_image(b"\x1a\x45"),
+        Err(RasterDecodeError::UnknownFormat)
+    );
+    assert_eq!(
+        RasterFormat::from_content_type("Image/JPEG; charset=binary"),
+        Some(RasterFormat::Jpeg)
+    );
+    assert_eq!(
+        RasterFormat::from_content_type("application/x-protobuf"),
+        None
+    );
+
+    //文件头优先于Content-Type
+    let loader = RasterLoader::new("img");
+    assert_eq!(loader.file_ext(Some("image/jpeg"), &png), Some("png"));
+    assert_eq!(loader.file_ext(Some("image/webp"), &[]), Some("webp"));
+    assert!(RasterFormat::Png.is_supported() && RasterFormat::Jpeg.is_supported());
+}
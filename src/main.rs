@@ -1,15 +1,34 @@
-use egui::Margin;
+use egui::{Color32, Margin};
 use ehttp::Request;
-use emap::{egui_map::EguiMap, EguiMapTileRes};
+use emap::{
+    cluster::ClusterLayer,
+    controls::MapControls,
+    editor::{DrawTool, MapEditor},
+    export::MapExport,
+    map_widget::{MapLayer, MapWidget},
+    measure::MeasureTool,
+    minimap::Minimap,
+    overlay::{ItemStyle, MapOverlay},
+    swipe::SwipeCompare,
+    sync::{CameraLink, SyncMode},
+    tile_drawable::TileStyle,
+    DebugPrintKeyTileRes,
+};
 use emap_loaders::{
-    mvt::MvtLoader, png::PngLoader, EguiMapBinResImpl, MemoryDrawableCache, RequestBuilder,
+    mvt::MvtLoader, raster::RasterLoader, EguiMapBinResImpl, MemoryDrawableCache, RequestBuilder,
 };
 use rustitude_base::{
-    latlng::{WebMercator, WCS},
-    map_state::Location,
-    map_view_state::MapViewState,
+    coord_format::CoordFormat,
+    crs::Gcj02Mercator,
+    geodesy::UnitSystem,
+    latlng::{CommonWCS, LatLng, WebMercator, WCS},
+    map_state::{Location, MapItem, MapItemData},
+    map_view_state::{MapViewState, TileLevels, MAX_PITCH},
+};
+use std::{
+    sync::{Arc, Mutex},
+    thread::JoinHandle,
 };
-use std::sync::{Arc, RwLock};
 
 fn main() {
     let _ = eframe::run_native(
@@ -41,51 +60,123 @@ fn main() {
                 .entry(egui::FontFamily::Monospace)
                 .or_default()
                 .push("my_font".to_owned());
-            cc.egui_ctx.set_fonts(fonts);
-            egui_extras::install_image_loaders(&cc.egui_ctx);
+            cc.egui_ctx.set_fonts(fonts.clone());
             Ok(Box::new(MapViewStateTestApp {
-                map_view_state: Arc::new(RwLock::new(MapViewState {
-                    central: Location { x: 0.5, y: 0.5 },
-                    view_size: [1280.0, 800.0],
-                    zoom_lvl: 2.0,
-                })),
-                main_res: Arc::new(EguiMapBinResImpl::new(
+                map: MapWidget::new(MapViewState::new(
+                    Location { x: 0.5, y: 0.5 },
+                    [1280.0, 800.0],
+                    2.0,
+                ))
+                .with_layer(MapLayer::base(
                     "img",
-                    "png",
-                    Some("tiles"),
-                    Box::new(ShipxyReqBuilder),
-                    Box::new(PngLoader {
-                        typ: String::from("img"),
-                        mem_cache: MemoryDrawableCache::new(),
-                    }),
-                )),
-                other_res: vec![
                     Arc::new(EguiMapBinResImpl::new(
-                        "mvt",
-                        "mvt",
+                        "img",
+                        "png",
                         Some("tiles"),
-                        Box::new(BingReqBuilder),
-                        Box::new(MvtLoader {
-                            typ: String::from("mvt"),
-                            mem_cache: MemoryDrawableCache::new(),
-                        }),
-                    )), // Arc::new(EguiMapPngResImpl::new(
-                        //     "cia",
-                        //     Some("tiles"),
-                        //     Arc::new(TiandituRequestBuilder::Test),
-                        // )),
-                        // Arc::new(EguiMapPngResImpl::new(
-                        //     "cva",
-                        //     Some("tiles"),
-                        //     Arc::new(TiandituRequestBuilder::Test),
-                        // )),
-                ],
-                debug: false,
+                        Box::new(ShipxyReqBuilder),
+                        Box::new(RasterLoader::new("img")),
+                    )),
+                ))
+                .with_layer(MapLayer::new(
+                    "mvt",
+                    Arc::new(
+                        EguiMapBinResImpl::new(
+                            "mvt",
+                            "mvt",
+                            Some("tiles"),
+                            Box::new(BingReqBuilder),
+                            Box::new(MvtLoader {
+                                typ: String::from("mvt"),
+                                mem_cache: MemoryDrawableCache::new(),
+                            }),
+                        )
+                        .with_style(TileStyle::default().with_hide_below_zoom(5.0)),
+                    ),
+                ))
+                .with_layer(
+                    MapLayer::new("debug", Arc::new(DebugPrintKeyTileRes))
+                        .with_z_index(1)
+                        .with_visible(false),
+                )
+                .with_overlay(demo_overlay())
+                .with_cluster_layer(demo_clusters(100_000))
+                .with_editor(MapEditor::new()),
+                compare: None,
+                fonts,
+                export_job: None,
+                export_result: Arc::new(Mutex::new(None)),
+                coord_format: CoordFormat::Decimal(6),
+                goto_text: String::new(),
+                goto_error: None,
             }))
         }),
     );
 }
 
+/// 演示用的矢量图层
+fn demo_overlay() -> MapOverlay {
+    let ll = |lat, lng| LatLng { lat, lng };
+    let (beijing, shanghai, guangzhou) = (
+        ll(39.9042, 116.4074),
+        ll(31.2304, 121.4737),
+        ll(23.1291, 113.2644),
+    );
+    MapOverlay::new("overlay")
+        .with_item(
+            MapItem::polygon_lat_lng(
+                &WebMercator,
+                &[
+                    ll(41.0, 118.0),
+                    ll(40.5, 122.0),
+                    ll(38.0, 121.5),
+                    ll(38.8, 119.5),
+                    ll(37.5, 118.5),
+                ],
+                MapItemData::new("Bohai"),
+            ),
+            ItemStyle::default().with_label(true),
+        )
+        .with_item(
+            MapItem::line_lat_lng(
+                &WebMercator,
+                &[beijing, shanghai, guangzhou],
+                MapItemData::new("route"),
+            ),
+            ItemStyle::default().with_stroke((3.0, Color32::from_rgb(0xff, 0x88, 0x00))),
+        )
+        .with_item(
+            MapItem::point_lat_lng(&WebMercator, beijing, MapItemData::new("Beijing")),
+            ItemStyle::default()
+                .with_fill(Color32::WHITE)
+                .with_label(true),
+        )
+}
+
+/// 演示用的聚合点图层，在中国近海随机生成`count`个点
+fn demo_clusters(count: u64) -> ClusterLayer {
+    let mut layer = ClusterLayer::new("ships");
+    let mut seed = 42u64;
+    let mut rand = || {
+        seed = seed
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        (seed >> 33) as f64 / (1u64 << 31) as f64
+    };
+    for id in 0..count {
+        let lat_lng = LatLng {
+            lat: 18.0 + rand() * 22.0,
+            lng: 108.0 + rand() * 17.0,
+        };
+        layer.insert(
+            id,
+            WebMercator.to_location(lat_lng),
+            MapItemData::new(format!("ship {}", id))
+                .with_prop("mmsi", (412_000_000 + id).to_string()),
+        );
+    }
+    layer
+}
+
 pub struct ShipxyReqBuilder;
 impl RequestBuilder for ShipxyReqBuilder {
     fn build_req(&self, _typ: &str, x: u32, y: u32, z: u8) -> ehttp::Request {
@@ -94,6 +185,10 @@ impl RequestBuilder for ShipxyReqBuilder {
             z, x, y
         ))
     }
+
+    fn attribution(&self) -> Option<String> {
+        Some(String::from("© 船讯网"))
+    }
 }
 
 pub struct BingReqBuilder;
@@ -104,43 +199,220 @@ impl RequestBuilder for BingReqBuilder {
             z, x, y
         ))
     }
-}
 
-struct MapViewStateTestApp {
-    map_view_state: Arc<RwLock<MapViewState>>,
-    main_res: Arc<dyn EguiMapTileRes>,
-    other_res: Vec<Arc<dyn EguiMapTileRes>>,
-    debug: bool,
-}
+    fn wcs(&self) -> Option<CommonWCS> {
+        Some(Arc::new(Gcj02Mercator))
+    }
+
+    fn levels(&self) -> TileLevels {
+        TileLevels::native(1, 19)
+    }
 
-impl EguiMap for MapViewStateTestApp {
-    fn map_view_state(&self) -> Arc<RwLock<MapViewState>> {
-        self.map_view_state.clone()
+    fn attribution(&self) -> Option<String> {
+        Some(String::from("© Microsoft Bing"))
     }
 }
 
+struct MapViewStateTestApp {
+    map: MapWidget,
+    /// 与主地图同步相机的对比地图
+    compare: Option<MapWidget>,
+    /// 界面使用的字体，导出图片时也使用
+    fonts: egui::FontDefinitions,
+    /// 后台导出任务
+    export_job: Option<JoinHandle<()>>,
+    /// 上次导出的结果
+    export_result: Arc<Mutex<Option<String>>>,
+    coord_format: CoordFormat,
+    goto_text: String,
+    goto_error: Option<String>,
+}
+
 impl eframe::App for MapViewStateTestApp {
     fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
+        if let Some(compare) = self.compare.as_mut() {
+            egui::SidePanel::right("compare")
+                .default_width(480.0)
+                .frame(egui::Frame::canvas(&ctx.style()).inner_margin(Margin::ZERO))
+                .show(ctx, |ui| {
+                    compare.show(ui);
+                });
+        }
         egui::CentralPanel::default()
             .frame(egui::Frame::canvas(&ctx.style()).inner_margin(Margin::ZERO))
             .show(ctx, |ui| {
                 ui.horizontal(|ui| {
-                    ui.label(format!(
-                        "{}",
-                        WebMercator.to_lat_lng(self.map_view_state.read().unwrap().central)
-                    ));
+                    let central = self.map.state().central_lat_lng();
+                    let central_text = central.format(self.coord_format);
+                    ui.label(&central_text);
+                    if ui.small_button("Copy").clicked() {
+                        ui.ctx().copy_text(central_text);
+                    }
+                    egui::ComboBox::from_id_salt("coord_format")
+                        .selected_text(self.coord_format.name())
+                        .show_ui(ui, |ui| {
+                            CoordFormat::PRESETS.iter().for_each(|f| {
+                                ui.selectable_value(&mut self.coord_format, *f, f.name());
+                            });
+                        });
+                    let goto = ui.text_edit_singleline(&mut self.goto_text);
+                    if (goto.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)))
+                        || ui.button("Go").clicked()
+                    {
+                        match self.goto_text.parse::<LatLng>() {
+                            Ok(lat_lng) => {
+                                let mut mvs = self.map.state_mut();
+                                let target = mvs.wcs.to_location(mvs.wcs.clamp_lat_lng(lat_lng));
+                                let (zoom_lvl, bearing) = (mvs.zoom_lvl.max(12.0), mvs.bearing);
+                                mvs.fly_to(target, zoom_lvl, bearing, None);
+                                self.goto_error = None;
+                            }
+                            Err(e) => self.goto_error = Some(e.to_string()),
+                        }
+                    }
+                    if let Some(e) = &self.goto_error {
+                        ui.colored_label(ui.visuals().error_fg_color, e);
+                    }
+                    let mut bearing = self.map.state().bearing;
+                    if ui
+                        .add(
+                            egui::DragValue::new(&mut bearing)
+                                .range(0.0..=360.0)
+                                .suffix("°"),
+                        )
+                        .changed()
+                    {
+                        self.map.state_mut().set_bearing(bearing);
+                    }
+                    let mut pitch = self.map.state().pitch;
+                    if ui
+                        .add(
+                            egui::DragValue::new(&mut pitch)
+                                .range(0.0..=MAX_PITCH)
+                                .prefix("pitch:")
+                                .suffix("°"),
+                        )
+                        .changed()
+                    {
+                        self.map.state_mut().set_pitch(pitch);
+                    }
+                    self.map.layers.iter_mut().for_each(|l| {
+                        ui.checkbox(&mut l.visible, l.name.as_str());
+                    });
+                    self.map.overlays.iter_mut().for_each(|o| {
+                        ui.checkbox(&mut o.visible, o.name.as_str());
+                    });
+                    self.map.clusters.iter_mut().for_each(|c| {
+                        ui.checkbox(&mut c.visible, c.name.as_str());
+                    });
+                    ui.checkbox(&mut self.map.debug, "debug");
+                    if let Some(editor) = self.map.editor.as_mut() {
+                        let mut tool = editor.tool;
+                        egui::ComboBox::from_id_salt("draw_tool")
+                            .selected_text(tool.name())
+                            .show_ui(ui, |ui| {
+                                DrawTool::ALL.iter().for_each(|t| {
+                                    ui.selectable_value(&mut tool, *t, t.name());
+                                });
+                            });
+                        editor.set_tool(tool);
+                        if ui
+                            .add_enabled(editor.shapes.can_undo(), egui::Button::new("Undo"))
+                            .clicked()
+                        {
+                            editor.undo();
+                        }
+                        if ui
+                            .add_enabled(editor.shapes.can_redo(), egui::Button::new("Redo"))
+                            .clicked()
+                        {
+                            editor.redo();
+                        }
+                    }
+                    let mut minimap = self.map.minimap.is_some();
+                    if ui.checkbox(&mut minimap, "minimap").changed() {
+                        //与主地图共用底图的瓦片源与缓存
+                        self.map.minimap = minimap.then(|| {
+                            Minimap::new(self.map.layer("img").into_iter().cloned().collect())
+                        });
+                    }
+                    let mut swipe = self.map.swipe.is_some();
+                    if ui.checkbox(&mut swipe, "swipe").changed() {
+                        self.map.swipe = swipe.then(|| SwipeCompare::new("mvt"));
+                    }
+                    let mut compare = self.compare.is_some();
+                    if ui.checkbox(&mut compare, "compare").changed() {
+                        //对比地图只显示矢量图层，两个地图的相机互相同步
+                        self.compare = compare.then(|| {
+                            let link = CameraLink::new();
+                            self.map.sync = Some(link.sync(SyncMode::Share));
+                            let mut state =
+                                MapViewState::new(Location::new(0.5, 0.5), [480.0, 800.0], 2.0);
+                            state.set_camera(self.map.state().camera());
+                            let mut map =
+                                MapWidget::new(state).with_sync(link.sync(SyncMode::Share));
+                            map.layers.extend(self.map.layer("mvt").map(|l| MapLayer {
+                                clip: None,
+                                ..l.clone()
+                            }));
+                            map
+                        });
+                        if !compare {
+                            self.map.sync = None;
+                        }
+                    }
+                    let mut measuring = self.map.measure.is_some();
+                    if ui.checkbox(&mut measuring, "measure").changed() {
+                        //船讯网底图的用户大多使用海里
+                        self.map.measure =
+                            measuring.then(|| MeasureTool::new().with_units(UnitSystem::Nautical));
+                    }
+                    if let Some(measure) = self.map.measure.as_mut() {
+                        ui.checkbox(&mut measure.area, "area");
+                        egui::ComboBox::from_id_salt("measure_units")
+                            .selected_text(measure.units.name())
+                            .show_ui(ui, |ui| {
+                                UnitSystem::ALL.iter().for_each(|u| {
+                                    ui.selectable_value(&mut measure.units, *u, u.name());
+                                });
+                            });
+                    }
+                    let exporting = self.export_job.as_ref().is_some_and(|j| !j.is_finished());
+                    if ui
+                        .add_enabled(!exporting, egui::Button::new("Export"))
+                        .clicked()
+                    {
+                        //以两倍屏幕DPI导出，瓦片可能需要从网络获取，在后台线程中进行
+                        let export =
+                            MapExport::of_view(&self.map.state(), MapExport::SCREEN_DPI * 2.0)
+                                .with_controls(MapControls {
+                                    coord_format: self.coord_format,
+                                    ..self.map.controls
+                                })
+                                .with_fonts(self.fonts.clone());
+                        let result = self.export_result.clone();
+                        let ctx = ctx.clone();
+                        self.export_job =
+                            Some(self.map.export_in_background(&export, move |map| {
+                                *result.lock().unwrap() = Some(match map.save("export.png") {
+                                    Ok(()) => String::from("exported to export.png"),
+                                    Err(e) => e.to_string(),
+                                });
+                                ctx.request_repaint();
+                            }));
+                    }
+                    if exporting {
+                        ui.label("exporting...");
+                    } else if let Some(result) = self.export_result.lock().unwrap().as_ref() {
+                        ui.label(result);
+                    }
                     if let Some(cpu_usage) = frame.info().cpu_usage {
                         ui.label(format!("cpuTime:{}ms", cpu_usage * 1000.0));
                     }
                 });
 
-                // ui.checkbox(&mut self.debug, "debug");
-                // if self.debug && self.other_res.len() == 0 {
-                //     self.other_res.push(Arc::new(DebugPrintKeyTileRes));
-                // } else if !self.debug && self.other_res.len() == 1 {
-                //     self.other_res.pop();
-                // }
-                self.egui_map(ui, self.main_res.clone(), &self.other_res, self.debug);
+                self.map.controls.coord_format = self.coord_format;
+                self.map.show(ui);
             });
     }
 }